@@ -6,8 +6,8 @@ use runa::{math::*, dna::*, dna::DnaMetricSpace as Dms};
 use runa::io::read_test_insts_by_size;
 use std::env;
 
-type DistFunc = fn(&[Dna], &[Dna]) -> <Dms as MetricSpace>::Cost;
-type SolFunc = fn(&[Dna], &[Dna]) -> Align<Dms>;
+type DistFunc = fn(&Dms, &[Dna], &[Dna]) -> <Dms as MetricSpace>::Cost;
+type SolFunc = fn(&Dms, &[Dna], &[Dna]) -> Align<Dms>;
 const DIST_FUNCTIONS: [(&str, DistFunc); 3] = [
     ("dist_1", dist_1::<Dms>),
     ("dist_2", dist_2::<Dms>),
@@ -49,9 +49,9 @@ where F: Fn(DnaBlock)
 
 fn all_limits(){
     let limits = vec![
-        lapse_limit("dist_2", |DnaBlock(l, r)| {dist_2::<DnaMetricSpace>(&l, &r);}), // 100000 in 68.3044632s
-        lapse_limit("dist_1", |DnaBlock(l, r)| {dist_1::<DnaMetricSpace>(&l, &r);}), // 10000 before memory limit.
-        lapse_limit("dist_naif", |DnaBlock(l, r)| {dist_naif::<DnaMetricSpace>(&l, &r);}), // this gives 14, 15 executes in much more
+        lapse_limit("dist_2", |DnaBlock(l, r)| {dist_2(&Dms, &l, &r);}), // 100000 in 68.3044632s
+        lapse_limit("dist_1", |DnaBlock(l, r)| {dist_1(&Dms, &l, &r);}), // 10000 before memory limit.
+        lapse_limit("dist_naif", |DnaBlock(l, r)| {dist_naif(&Dms, &l, &r);}), // this gives 14, 15 executes in much more
     ];
     
     for (name, limit) in limits {
@@ -62,10 +62,10 @@ fn all_limits(){
 fn gnuplot(){
     let dists = DIST_FUNCTIONS
         .iter().copied()
-        .map(|(s, f)| (s, Box::new(move |DnaBlock(l, r)| { f(&l, &r); }) as Box<dyn Fn(DnaBlock)>));
+        .map(|(s, f)| (s, Box::new(move |DnaBlock(l, r)| { f(&Dms, &l, &r); }) as Box<dyn Fn(DnaBlock)>));
     let sols = SOL_FUNCTIONS
         .iter().copied()
-        .map(|(s, f)| (s, Box::new(move |DnaBlock(l, r)| { f(&l, &r); }) as Box<dyn Fn(DnaBlock)>));
+        .map(|(s, f)| (s, Box::new(move |DnaBlock(l, r)| { f(&Dms, &l, &r); }) as Box<dyn Fn(DnaBlock)>));
     let mut funcs = dists.chain(sols);
 
 
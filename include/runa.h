@@ -0,0 +1,79 @@
+// runa.h, the C API of RuNA. Generated from src/ffi.rs, do not edit: `RUNA_BLESS=1 cargo test ffi` rewrites it.
+// Link with -lruna, see src/ffi.rs for the rules of the API.
+
+#ifndef RUNA_H
+#define RUNA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// Full table algorithms, dist_1 and sol_1
+#define RUNA_FULL 0
+
+// Linear memory algorithms, dist_2 and sol_2
+#define RUNA_LINEAR 1
+
+// Best local alignment, dist_local and sol_local, with a similarity scheme
+#define RUNA_LOCAL 2
+
+// Best overlap of a suffix of x with a prefix of y, dist_overlap and sol_overlap, with a similarity scheme
+#define RUNA_OVERLAP 3
+
+// A scoring scheme, a distance or a similarity
+typedef struct RunaScheme RunaScheme;
+
+// An alignment with its cost, the alignment of the parts x[x_start..x_end] and y[y_start..y_end]
+typedef struct RunaAlignment RunaAlignment;
+
+// The version of the library, a static string
+const char *runa_version(void);
+
+// The message of the last error of the calling thread, NULL if there was none. It lives until the next error
+const char *runa_last_error(void);
+
+// A scheme by name: course, unit, kimura (distances) or similarity (match 1, mismatch -1, gap -2)
+RunaScheme *runa_scheme_new(const char *name);
+
+// A similarity scheme with the given scores, the best alignments have the highest score
+RunaScheme *runa_scheme_similarity(int64_t matched, int64_t mismatch, int64_t gap);
+
+// A distance scheme read from the text of a substitution matrix (see runa::matrix)
+RunaScheme *runa_scheme_matrix(const char *matrix);
+
+// Free a scheme, NULL is ignored
+void runa_scheme_free(RunaScheme *scheme);
+
+// The distance (or score) of x and y with the algorithm, written to cost. 0 on success, -1 on an error
+int runa_dist(const RunaScheme *scheme, const uint8_t *x, size_t x_len, const uint8_t *y, size_t y_len, int algo, int64_t *cost);
+
+// The optimal alignment of x and y with the algorithm, NULL on an error
+RunaAlignment *runa_align(const RunaScheme *scheme, const uint8_t *x, size_t x_len, const uint8_t *y, size_t y_len, int algo);
+
+// The cost (or score) of the alignment
+int64_t runa_alignment_cost(const RunaAlignment *al);
+
+// The CIGAR string of the alignment, with =, X, I and D, x being the reference
+const char *runa_alignment_cigar(const RunaAlignment *al);
+
+// The row of x in the alignment, the gaps being '-'
+const char *runa_alignment_row_x(const RunaAlignment *al);
+
+// The row of y in the alignment, the gaps being '-'
+const char *runa_alignment_row_y(const RunaAlignment *al);
+
+// The aligned parts x[x_start..x_end] and y[y_start..y_end], the whole sequences for a global alignment.
+// The NULL pointers are skipped
+void runa_alignment_range(const RunaAlignment *al, size_t *x_start, size_t *x_end, size_t *y_start, size_t *y_end);
+
+// Free an alignment, NULL is ignored
+void runa_alignment_free(RunaAlignment *al);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,200 @@
+//! A small overlap-layout-consensus assembler, to rebuild a sequence from overlapping reads.
+//!
+//! - overlap: every ordered pair of reads is aligned with sol_overlap, the overlaps scoring enough
+//!   are the edges of the overlap graph;
+//! - layout: the edges are taken greedily from the best one, as long as every read keeps at most one successor
+//!   and one predecessor and no cycle appears, the paths of the graph are then the contigs;
+//! - consensus: the reads of a contig are glued along their overlaps into a draft, every read is aligned
+//!   back onto the draft (see [`crate::search`]) and every letter of the draft is decided by a vote of the reads.
+//!
+//! Reads contained in other reads are not handled, they should be removed beforehand.
+
+use crate::dna::Dna;
+use crate::math::*;
+use crate::search::{recover, sellers};
+
+/// An edge of the overlap graph, the end of the read `from` overlaps the start of the read `to`
+pub struct OverlapEdge<M: MetricSpace> {
+    pub from: usize,
+    pub to: usize,
+    pub overlap: Overlap<M>,
+}
+
+/// The overlaps between every ordered pair of reads scoring at least min_score, sorted from the best one.
+/// O(r^2 n^2) time for r reads of length n
+pub fn overlap_graph<M>(ms: &M, reads: &[Vec<Dna>], min_score: M::Cost) -> Vec<OverlapEdge<M>>
+where M: MetricSpace<Item = Dna, Goal = Maximise>
+{
+    let mut edges = vec![];
+    for (from, a) in reads.iter().enumerate() {
+        for (to, b) in reads.iter().enumerate() {
+            if from == to { continue; }
+            let overlap = sol_overlap(ms, a, b);
+            if overlap.score >= min_score {
+                edges.push(OverlapEdge { from, to, overlap });
+            }
+        }
+    }
+    edges.sort_by(|e, f| f.overlap.score.cmp(&e.overlap.score).then((e.from, e.to).cmp(&(f.from, f.to))));
+    edges
+}
+
+/// The layout of the reads: the contigs as paths of the overlap graph, with the edges between
+/// their consecutive reads. Every read belongs to exactly one contig
+pub fn layout<M: MetricSpace>(n: usize, edges: &[OverlapEdge<M>]) -> Vec<(Vec<usize>, Vec<usize>)> {
+    fn find(parent: &mut [usize], mut a: usize) -> usize {
+        while parent[a] != a {
+            parent[a] = parent[parent[a]];
+            a = parent[a];
+        }
+        a
+    }
+    let mut next: Vec<Option<usize>> = vec![None; n];
+    let mut has_prev = vec![false; n];
+    let mut parent = (0..n).collect::<Vec<_>>();
+    for (k, e) in edges.iter().enumerate() {
+        if next[e.from].is_some() || has_prev[e.to] { continue; }
+        let (a, b) = (find(&mut parent, e.from), find(&mut parent, e.to));
+        if a == b { continue; }
+        parent[a] = b;
+        next[e.from] = Some(k);
+        has_prev[e.to] = true;
+    }
+    (0..n)
+        .filter(|&r| !has_prev[r])
+        .map(|mut r| {
+            let (mut reads, mut path) = (vec![r], vec![]);
+            while let Some(k) = next[r] {
+                path.push(k);
+                r = edges[k].to;
+                reads.push(r);
+            }
+            (reads, path)
+        })
+        .collect()
+}
+
+/// Glue the reads of a contig along their overlaps
+fn draft<M: MetricSpace>(reads: &[Vec<Dna>], first: usize, path: &[usize], edges: &[OverlapEdge<M>]) -> Vec<Dna> {
+    let mut res = reads[first].clone();
+    for &k in path {
+        let e = &edges[k];
+        res.extend_from_slice(&reads[e.to][e.overlap.y.end..]);
+    }
+    res
+}
+
+/// The consensus of the reads aligned onto the draft: every letter of the draft becomes the most common
+/// letter aligned with it (the draft letter wins ties), and is dropped if more reads skip it than not.
+/// The letters the reads have in addition to the draft are ignored
+fn consensus<M>(ms: &M, draft: &[Dna], reads: &[&Vec<Dna>]) -> Vec<Dna>
+where M: MetricSpace<Item = Dna>
+{
+    // the votes for A, C, G, T and for a gap at every position of the draft
+    let mut votes = vec![[0usize; 5]; draft.len()];
+    let slot = |d: Dna| match d { Dna::A => 0, Dna::C => 1, Dna::G => 2, Dna::T => 3 };
+    for read in reads {
+        let Some(end) = sellers(ms, read, draft, M::INFCOST)
+            .reduce(|a, b| if M::Goal::better(&b.1, &a.1) { b } else { a })
+        else { continue };
+        let m = recover(ms, read, draft, end);
+        let mut p = m.start;
+        for (a, b) in m.align.0.iter().zip(&m.align.1) {
+            match (a, b) {
+                (Gapped::Item(r), Gapped::Item(_)) => votes[p][slot(*r)] += 1,
+                (Gapped::Gap, Gapped::Item(_)) => votes[p][4] += 1,
+                _ => continue,
+            }
+            p += 1;
+        }
+    }
+    draft
+        .iter()
+        .zip(votes)
+        .filter(|(_, v)| v[4] <= v[..4].iter().sum())
+        .map(|(&d, v)| {
+            let letters = [Dna::A, Dna::C, Dna::G, Dna::T];
+            letters.into_iter().fold(d, |b, l| if v[slot(l)] > v[slot(b)] { l } else { b })
+        })
+        .collect()
+}
+
+/// Assemble the reads into contigs by overlap, layout and consensus,
+/// only the overlaps scoring at least min_score are trusted. The contigs are sorted from the longest
+pub fn assemble<M>(ms: &M, reads: &[Vec<Dna>], min_score: M::Cost) -> Vec<Vec<Dna>>
+where M: MetricSpace<Item = Dna, Goal = Maximise>
+{
+    let edges = overlap_graph(ms, reads, min_score);
+    let mut contigs = layout(reads.len(), &edges)
+        .into_iter()
+        .map(|(members, path)| {
+            let d = draft(reads, members[0], &path, &edges);
+            consensus(ms, &d, &members.iter().map(|&r| &reads[r]).collect::<Vec<_>>())
+        })
+        .collect::<Vec<_>>();
+    contigs.sort_by_key(|c| std::cmp::Reverse(c.len()));
+    contigs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{Dna, DnaMetricSpace as Dms, DnaSimilarity};
+    use crate::math::dist_2;
+    use crate::rng::Rng;
+    use super::{assemble, layout, overlap_graph};
+
+    fn random(n: usize, rng: &mut Rng) -> Vec<Dna> {
+        let letters = [Dna::A, Dna::C, Dna::G, Dna::T];
+        (0..n).map(|_| letters[rng.below(4) as usize]).collect()
+    }
+
+    /// Reads of length len covering the genome, shuffled
+    fn reads(genome: &[Dna], len: usize, rng: &mut Rng) -> Vec<Vec<Dna>> {
+        let mut starts = vec![0];
+        while starts.last().unwrap() + len < genome.len() {
+            let s = (starts.last().unwrap() + len / 4 + rng.below(len as u64 / 3) as usize).min(genome.len() - len);
+            starts.push(s);
+        }
+        let mut res = starts.into_iter().map(|s| genome[s..s + len].to_vec()).collect::<Vec<_>>();
+        rng.shuffle(&mut res);
+        res
+    }
+
+    #[test]
+    fn graph_and_layout(){
+        let mut rng = Rng::new(39);
+        let genome = random(300, &mut rng);
+        let rs = vec![genome[200..300].to_vec(), genome[0..100].to_vec(), genome[80..220].to_vec()];
+        let sim = DnaSimilarity::default();
+        let edges = overlap_graph(&sim, &rs, 10);
+        assert_eq!((edges[0].from, edges[0].to, edges[0].overlap.score), (1, 2, 20));
+        assert_eq!((edges[1].from, edges[1].to, edges[1].overlap.score), (2, 0, 20));
+        let l = layout(rs.len(), &edges);
+        assert_eq!(l.len(), 1);
+        assert_eq!(l[0].0, vec![1, 2, 0]);
+        assert_eq!(assemble(&sim, &rs, 10), vec![genome]);
+    }
+
+    #[test]
+    fn exact_reads(){
+        let mut rng = Rng::new(1);
+        let genome = random(1000, &mut rng);
+        let rs = reads(&genome, 120, &mut rng);
+        assert_eq!(assemble(&DnaSimilarity::default(), &rs, 20), vec![genome]);
+    }
+
+    #[test]
+    fn noisy_reads(){
+        let mut rng = Rng::new(2);
+        let genome = random(800, &mut rng);
+        let mut rs = reads(&genome, 120, &mut rng);
+        rs.extend(reads(&genome, 120, &mut rng));
+        for r in rs.iter_mut() {
+            let p = rng.below(r.len() as u64) as usize;
+            r[p] = if r[p] == Dna::A { Dna::T } else { Dna::A };
+        }
+        let contigs = assemble(&DnaSimilarity::default(), &rs, 30);
+        let errors = dist_2(&Dms, &contigs[0], &genome);
+        assert!(errors <= 2 * 4, "{} contigs, {} away from the genome", contigs.len(), errors);
+    }
+}
@@ -0,0 +1,237 @@
+//! Batch alignment of many pairs of sequences on all the cores.
+//!
+//! The jobs are handed out to a pool of worker threads, while the calling thread collects the results
+//! and passes them on in the order of the jobs, as soon as all the jobs before them are done.
+//! At most a few jobs per worker are in flight at any time, so that the jobs can be read lazily
+//! (from a manifest for instance) and the results written as they come, whatever the size of the batch.
+//! A job which fails, or panics, only gives an error for its own pair.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, mpsc};
+use std::thread;
+
+use crate::dna::Dna;
+use crate::io::{FastaRecord, read_sequences};
+use crate::math::*;
+
+/// The error of a single job of a batch
+pub type PairError = Box<dyn Error + Send + Sync>;
+
+/// How many jobs may be in flight per worker
+const JOBS_PER_WORKER: usize = 4;
+
+/// A pair of named sequences to align
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pair {
+    pub x: FastaRecord,
+    pub y: FastaRecord,
+}
+
+/// The number of available cores, the default size of the worker pool
+pub fn default_workers() -> usize {
+    thread::available_parallelism().map_or(1, |w| w.get())
+}
+
+/// Run f on every job with the given number of workers, and give every result to sink with the index
+/// of its job, in the order of the jobs. The sink runs on the calling thread
+pub fn run_batch<J, T, F, S>(jobs: impl IntoIterator<Item = J>, workers: usize, f: F, mut sink: S)
+where J: Send, T: Send, F: Fn(J) -> Result<T, PairError> + Sync, S: FnMut(usize, Result<T, PairError>)
+{
+    let workers = workers.max(1);
+    let window = JOBS_PER_WORKER * workers;
+    // the job channel never fills up, as there are at most window jobs in flight
+    let (job_tx, job_rx) = mpsc::sync_channel::<(usize, J)>(window);
+    let job_rx = Mutex::new(job_rx);
+    let (res_tx, res_rx) = mpsc::channel::<(usize, Result<T, PairError>)>();
+
+    thread::scope(|s| {
+        for _ in 0..workers {
+            let (job_rx, f, res_tx) = (&job_rx, &f, res_tx.clone());
+            s.spawn(move || loop {
+                let Ok((k, job)) = job_rx.lock().expect("a worker panicked!").recv() else { break };
+                let res = panic::catch_unwind(AssertUnwindSafe(|| f(job))).unwrap_or_else(|p| {
+                    let msg = p.downcast_ref::<&str>().map(|s| s.to_string())
+                        .or_else(|| p.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "unknown panic".to_string());
+                    Err(format!("the job panicked: {}", msg).into())
+                });
+                if res_tx.send((k, res)).is_err() { break; }
+            });
+        }
+        drop(res_tx);
+
+        let mut jobs = jobs.into_iter().enumerate();
+        let mut job_tx = Some(job_tx);
+        let mut done = BTreeMap::new();
+        let (mut sent, mut next) = (0, 0);
+        loop {
+            while sent < next + window {
+                let Some(tx) = &job_tx else { break };
+                match jobs.next() {
+                    Some(job) => {
+                        tx.send(job).expect("the workers stopped!");
+                        sent += 1;
+                    }
+                    None => job_tx = None,
+                }
+            }
+            if next == sent { break; }
+            let (k, res) = res_rx.recv().expect("the workers stopped!");
+            done.insert(k, res);
+            while let Some(res) = done.remove(&next) {
+                sink(next, res);
+                next += 1;
+            }
+        }
+        // let the workers stop if the jobs were not all taken
+        drop(job_tx);
+    });
+}
+
+/// The distances between the pairs, with dist_2, see [`run_batch`]
+pub fn dist_pairs<M, S>(ms: &M, pairs: impl IntoIterator<Item = Pair>, workers: usize, sink: S)
+where M: MetricSpace<Item = Dna> + Sync, M::Cost: Send, S: FnMut(usize, Result<M::Cost, PairError>)
+{
+    run_batch(pairs, workers, |p: Pair| Ok(dist_2(ms, &p.x.seq, &p.y.seq)), sink)
+}
+
+/// The optimal alignments of the pairs with their cost, with sol_2, see [`run_batch`]
+pub fn align_pairs<M, S>(ms: &M, pairs: impl IntoIterator<Item = Pair>, workers: usize, sink: S)
+where M: MetricSpace<Item = Dna> + Sync, M::Cost: Send, S: FnMut(usize, Result<(M::Cost, Align<M>), PairError>)
+{
+    run_batch(pairs, workers, |p: Pair| {
+        let al = sol_2(ms, &p.x.seq, &p.y.seq);
+        Ok((al.cost(ms), al))
+    }, sink)
+}
+
+/// All the pairs of different records, (0, 1), (0, 2)... (1, 2)...
+pub fn all_vs_all(records: &[FastaRecord]) -> impl Iterator<Item = Pair> + '_ {
+    (0..records.len()).flat_map(move |i| {
+        (i + 1..records.len()).map(move |j| Pair { x: records[i].clone(), y: records[j].clone() })
+    })
+}
+
+/// A line of a manifest: the files of a pair, relative paths being taken from the directory of the manifest.
+/// Either one file holding the two sequences (an .adn file, or a FASTA file whose first two records are used),
+/// or two files whose first sequences are aligned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// the line of the manifest, from 1
+    pub line: usize,
+    pub files: Vec<PathBuf>,
+}
+
+/// Parse a manifest, one pair per line. Empty lines and lines starting with '#' are skipped
+pub fn parse_manifest(s: &str, dir: &Path) -> Vec<ManifestEntry> {
+    s.lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+        .map(|(k, l)| ManifestEntry { line: k + 1, files: l.split_whitespace().map(|f| dir.join(f)).collect() })
+        .collect()
+}
+
+/// The records of a sequence file, in any format
+fn read_records(path: &Path) -> Result<Vec<FastaRecord>, PairError> {
+    let f = std::fs::File::open(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    read_sequences(f, None).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+impl ManifestEntry {
+    /// Read the sequences of the pair
+    pub fn load(&self) -> Result<Pair, PairError> {
+        let mut records = match &self.files[..] {
+            [f] => read_records(f)?.into_iter(),
+            [f, g] => {
+                let mut r = read_records(f)?;
+                r.truncate(1);
+                r.extend(read_records(g)?.into_iter().take(1));
+                r.into_iter()
+            }
+            _ => return Err(format!("manifest error: line {} must hold one or two files", self.line).into()),
+        };
+        match (records.next(), records.next()) {
+            (Some(x), Some(y)) => Ok(Pair { x, y }),
+            _ => Err(format!("manifest error: line {} does not give two sequences", self.line).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use crate::dna::{Dna, DnaMetricSpace as Dms};
+    use crate::io::{FastaRecord, read_test_insts_all};
+    use crate::math::{dist_2, sol_2};
+    use super::{Pair, align_pairs, all_vs_all, dist_pairs, parse_manifest, run_batch};
+
+    #[test]
+    fn order_and_errors(){
+        // the first jobs are the slowest, their results must still come first
+        let mut got = vec![];
+        run_batch(0..100u64, 4, |k| {
+            std::thread::sleep(std::time::Duration::from_millis(100u64.saturating_sub(k * 5)));
+            match k % 10 {
+                3 => Err(format!("job {} failed", k).into()),
+                7 => panic!("job {} exploded", k),
+                _ => Ok(k * k),
+            }
+        }, |k, r| got.push((k, r.map_err(|e| e.to_string()))));
+        assert_eq!(got.len(), 100);
+        for (k, (i, r)) in got.into_iter().enumerate() {
+            assert_eq!(i, k);
+            match k % 10 {
+                3 => assert_eq!(r, Err(format!("job {} failed", k))),
+                7 => assert_eq!(r, Err(format!("the job panicked: job {} exploded", k))),
+                _ => assert_eq!(r, Ok(k as u64 * k as u64)),
+            }
+        }
+        // no job at all, and a lazy stream of jobs
+        run_batch(std::iter::empty::<u8>(), 3, |_| Ok(()), |_, _| panic!("there is no job!"));
+        let count = Cell::new(0);
+        run_batch((0..1000).inspect(|_| count.set(count.get() + 1)), 1, Ok, |k, r| assert_eq!(r.unwrap(), k));
+        assert_eq!(count.get(), 1000);
+    }
+
+    #[test]
+    fn pairs(){
+        let pairs = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 1000)
+            .map(|(size, b)| Pair {
+                x: FastaRecord { name: format!("{}x", size), seq: b.0 },
+                y: FastaRecord { name: format!("{}y", size), seq: b.1 },
+            })
+            .collect::<Vec<_>>();
+        let mut dists = vec![];
+        dist_pairs(&Dms, pairs.clone(), 3, |_, r| dists.push(r.unwrap()));
+        assert_eq!(dists, pairs.iter().map(|p| dist_2(&Dms, &p.x.seq, &p.y.seq)).collect::<Vec<_>>());
+        align_pairs(&Dms, pairs.clone(), 3, |k, r| {
+            let (cost, al) = r.unwrap();
+            assert_eq!(cost, dists[k]);
+            assert_eq!(al, sol_2(&Dms, &pairs[k].x.seq, &pairs[k].y.seq));
+        });
+
+        let records = ["A", "C", "G", "T"].map(|s| FastaRecord { name: s.to_string(), seq: vec![s.parse::<Dna>().unwrap()] });
+        let names = all_vs_all(&records).map(|p| p.x.name + &p.y.name).collect::<Vec<_>>();
+        assert_eq!(names, ["AC", "AG", "AT", "CG", "CT", "GT"]);
+    }
+
+    #[test]
+    fn manifest(){
+        let dir = std::env::temp_dir().join("runa_batch_manifest");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.fasta"), ">a\nACGT\n>b\nAGT\n").unwrap();
+        std::fs::write(dir.join("c.adn"), "2\n1\nA C\nC\n").unwrap();
+        let entries = parse_manifest("# pairs\na.fasta\n\nc.adn a.fasta\nmissing.adn\na.fasta c.adn c.adn\n", &dir);
+        assert_eq!(entries.iter().map(|e| e.line).collect::<Vec<_>>(), [2, 4, 5, 6]);
+        let p = entries[0].load().unwrap();
+        assert_eq!((p.x.name.as_str(), p.y.name.as_str()), ("a", "b"));
+        let p = entries[1].load().unwrap();
+        assert_eq!((p.x.name.as_str(), p.y.name.as_str()), ("x", "a"));
+        assert!(entries[2].load().unwrap_err().to_string().contains("missing.adn"));
+        assert!(entries[3].load().unwrap_err().to_string().contains("line 6"));
+    }
+}
@@ -0,0 +1,718 @@
+//! The command line interface of the `runa` binary.
+//!
+//! The binary only forwards its arguments and standard streams to [`run`], so that every command
+//! can be tested without spawning processes. The exit code is 0 on success, 1 when the command failed
+//! (unreadable or invalid input...) and 2 when the command line itself is wrong.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+
+use crate::batch::{ManifestEntry, Pair, all_vs_all, default_workers, parse_manifest, run_batch};
+use crate::dna::{Dna, DnaBlock, DnaSimilarity};
+use crate::io::{AdnReader, FastaRecord, Output, SeqFormat, decompress, read_decoded, read_subst_matrix, sniff, write_adn, write_fasta};
+use crate::math::*;
+use crate::matrix::Scheme;
+use crate::output::{AlignRecord, tsv_field, writer};
+use crate::msa::msa;
+use crate::search::{recover, sellers};
+
+pub const USAGE: &str = "\
+runa, align DNA sequences
+
+usage: runa <command> [options]
+
+commands:
+  dist      the distance (or score) between two sequences
+  align     the optimal alignment of two sequences
+  search    the approximate occurences of a pattern in a sequence
+  msa       the multiple alignment of several sequences
+  convert   convert sequences between the adn and FASTA formats
+  batch     the distances (or alignments) of many pairs, on all the cores
+
+The sequences are read from the input, an .adn file (two sequences) or a FASTA file,
+the first two sequences being used by dist and align.
+Run `runa <command> --help` for the options of a command.
+";
+
+const IO_HELP: &str = "\
+  -i, --input FILE        read the sequences from FILE instead of the standard input
+  -o, --output FILE       write to FILE instead of the standard output
+      --in-format FORMAT  adn, fasta or auto (default: auto, FASTA files start with '>')
+      --gzip              compress the output with gzip (gzip inputs are always read, with the gzip feature)
+";
+
+const SCHEME_HELP: &str = "\
+      --scheme SCHEME     course, unit, kimura or similarity (default: course)
+      --matrix FILE       read the substitution matrix from FILE (see runa::matrix)
+      --match N, --mismatch N, --gap N
+                          the scores of the similarity scheme (default: 1, -1, -2)
+";
+
+const DIST_HELP: &str = "\
+usage: runa dist [options]
+
+the distance between the first two sequences of the input, or their score for a similarity
+
+options:
+      --algo ALGO         naive, full (dist_1) or linear (dist_2, default)
+      --mode MODE         global (default), local or overlap, local and overlap need a similarity
+";
+
+const ALIGN_HELP: &str = "\
+usage: runa align [options]
+
+the optimal alignment of the first two sequences of the input
+
+options:
+      --algo ALGO         full (sol_1) or linear (sol_2, default)
+      --mode MODE         global (default), local or overlap, local and overlap need a similarity
+      --tie POLICY        diagonal (default), left or right, how ties between optimal alignments are broken
+      --format FORMAT     pretty (default), fasta, pairwise, tsv or jsonl (see runa::output)
+";
+
+const SEARCH_HELP: &str = "\
+usage: runa search --pattern SEQ -k N [options]
+
+the end positions of the matches of the pattern within k in the first sequence of the input,
+one per line with its cost
+
+options:
+  -p, --pattern SEQ       the pattern, as letters
+  -k, --max-cost N        the worst cost (or score for a similarity) of a match
+      --recover           also give the start of every match, the lines are then start, end and cost
+";
+
+const MSA_HELP: &str = "\
+usage: runa msa [options]
+
+the multiple alignment of all the sequences of the input, written as FASTA
+";
+
+const CONVERT_HELP: &str = "\
+usage: runa convert --to FORMAT [options]
+
+write the sequences of the input in another format
+
+options:
+      --to FORMAT         adn (exactly two sequences) or fasta
+";
+
+const BATCH_HELP: &str = "\
+usage: runa batch [--manifest FILE] [options]
+
+the distances of many pairs of sequences, computed in parallel with dist_2 (or sol_2 with --align):
+the pairs listed by the manifest, or else all the pairs of sequences of the input.
+A line of the manifest gives one file holding a pair (.adn or FASTA) or two files whose first sequences
+are aligned, the paths being relative to the manifest.
+The results are written in the order of the pairs, one line per pair: its index (from 0), the names
+of the sequences and the cost, followed with --align by the two rows of the alignment.
+The pairwise, tsv and jsonl formats write the alignments instead (see runa::output).
+A pair which fails gives the line: index, `error` and the message (a comment line in tsv, an object
+with an error field in jsonl), the exit code is then 1.
+
+options:
+  -m, --manifest FILE     the pairs to align
+  -j, --jobs N            the number of workers (default: the number of cores)
+      --align             also give the alignments
+      --format FORMAT     lines (default), pairwise, tsv or jsonl
+";
+
+/// Why a command failed
+#[derive(Debug)]
+pub enum CliError {
+    /// the command line is invalid
+    Usage(String),
+    /// the command could not be carried out
+    Failed(Box<dyn Error>),
+}
+
+impl CliError {
+    /// The exit code of the process
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => 2,
+            CliError::Failed(_) => 1,
+        }
+    }
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Usage(s) => write!(f, "{}\nrun `runa --help` for help", s),
+            CliError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<E: Into<Box<dyn Error>>> From<E> for CliError {
+    fn from(e: E) -> Self {
+        CliError::Failed(e.into())
+    }
+}
+
+fn usage<T>(s: String) -> Result<T, CliError> {
+    Err(CliError::Usage(s))
+}
+
+/// The options of a command: long name, short name, whether it takes a value
+type Spec = &'static [(&'static str, Option<char>, bool)];
+
+const IO_OPTS: Spec = &[("input", Some('i'), true), ("output", Some('o'), true), ("in-format", None, true), ("gzip", None, false), ("help", Some('h'), false)];
+const SCHEME_OPTS: Spec = &[("scheme", None, true), ("matrix", None, true), ("match", None, true), ("mismatch", None, true), ("gap", None, true)];
+
+/// The parsed options of a command, by long name
+struct Opts(HashMap<&'static str, String>);
+
+impl Opts {
+    fn parse(args: &[String], specs: &[Spec]) -> Result<Self, CliError> {
+        let mut res = HashMap::new();
+        let mut it = args.iter();
+        while let Some(a) = it.next() {
+            let (name, inline) = match a.strip_prefix("--") {
+                Some(long) => match long.split_once('=') {
+                    Some((n, v)) => (n.to_string(), Some(v.to_string())),
+                    None => (long.to_string(), None),
+                },
+                None => match a.strip_prefix('-').map(|s| s.chars().collect::<Vec<_>>()).as_deref() {
+                    Some(&[c]) => match specs.iter().flat_map(|s| s.iter()).find(|o| o.1 == Some(c)) {
+                        Some(o) => (o.0.to_string(), None),
+                        None => return usage(format!("unknown option -{}", c)),
+                    },
+                    _ => return usage(format!("unexpected argument {}", a)),
+                },
+            };
+            let Some(&(long, _, takes_value)) = specs.iter().flat_map(|s| s.iter()).find(|o| o.0 == name) else {
+                return usage(format!("unknown option --{}", name));
+            };
+            let value = match (takes_value, inline) {
+                (true, Some(v)) => v,
+                (true, None) => match it.next() {
+                    Some(v) => v.clone(),
+                    None => return usage(format!("--{} needs a value", long)),
+                },
+                (false, None) => String::new(),
+                (false, Some(_)) => return usage(format!("--{} takes no value", long)),
+            };
+            res.insert(long, value);
+        }
+        Ok(Opts(res))
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(|s| s.as_str())
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// The value of the option among the allowed ones, the first one by default
+    fn choice(&self, name: &str, allowed: &[&'static str]) -> Result<&'static str, CliError> {
+        match self.get(name) {
+            None => Ok(allowed[0]),
+            Some(v) => match allowed.iter().find(|&&a| a == v) {
+                Some(a) => Ok(a),
+                None => usage(format!("invalid value {} for --{}, expected one of: {}", v, name, allowed.join(", "))),
+            },
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>, CliError> {
+        match self.get(name) {
+            None => Ok(None),
+            Some(v) => match v.parse() {
+                Ok(x) => Ok(Some(x)),
+                Err(_) => usage(format!("invalid number {} for --{}", v, name)),
+            },
+        }
+    }
+}
+
+impl Scheme {
+    /// The scoring scheme chosen on the command line
+    fn from_opts(o: &Opts) -> Result<Self, CliError> {
+        if let Some(f) = o.get("matrix") {
+            return Ok(Scheme::Distance(read_subst_matrix(f)?));
+        }
+        Ok(match Scheme::preset(o.choice("scheme", &["course", "unit", "kimura", "similarity"])?)? {
+            Scheme::Similarity(d) => Scheme::Similarity(DnaSimilarity {
+                matched: o.number("match")?.unwrap_or(d.matched),
+                mismatch: o.number("mismatch")?.unwrap_or(d.mismatch),
+                gap: o.number("gap")?.unwrap_or(d.gap),
+            }),
+            scheme => scheme,
+        })
+    }
+}
+
+/// Read the sequences of the input, as named records
+fn read_input(o: &Opts, stdin: &mut dyn Read) -> Result<Vec<FastaRecord>, CliError> {
+    let (r, format) = open_input(o, stdin)?;
+    Ok(read_decoded(r, format)?)
+}
+
+/// The input, buffered, with its format
+fn open_input<'a>(o: &Opts, stdin: &'a mut dyn Read) -> Result<(Box<dyn BufRead + 'a>, SeqFormat), CliError> {
+    let mut r: Box<dyn BufRead + 'a> = match o.get("input") {
+        None | Some("-") => Box::new(BufReader::new(decompress(stdin)?)),
+        Some(f) => Box::new(BufReader::new(decompress(File::open(f).map_err(|e| format!("cannot read {}: {}", f, e))?)?)),
+    };
+    let format = match o.choice("in-format", &["auto", "adn", "fasta"])? {
+        "auto" => sniff(&mut r)?,
+        "adn" => SeqFormat::Adn,
+        _ => SeqFormat::Fasta,
+    };
+    Ok((r, format))
+}
+
+/// The first two sequences of the input
+fn read_pair(o: &Opts, stdin: &mut dyn Read) -> Result<(FastaRecord, FastaRecord), CliError> {
+    first_two(read_input(o, stdin)?)
+}
+
+fn first_two(records: Vec<FastaRecord>) -> Result<(FastaRecord, FastaRecord), CliError> {
+    let mut r = records.into_iter();
+    match (r.next(), r.next()) {
+        (Some(x), Some(y)) => Ok((x, y)),
+        _ => Err("the input must hold at least two sequences".into()),
+    }
+}
+
+/// Write the output of a command to the output file, or to stdout
+fn write_output(o: &Opts, stdout: &mut dyn Write, out: &[u8]) -> Result<(), CliError> {
+    let mut w = open_output(o, stdout)?;
+    w.write_all(out)?;
+    w.finish()?;
+    Ok(())
+}
+
+/// The output file, or stdout, compressed with --gzip
+fn open_output<'a>(o: &Opts, stdout: &'a mut dyn Write) -> Result<Output<Box<dyn Write + 'a>>, CliError> {
+    let w: Box<dyn Write + 'a> = match o.get("output") {
+        None | Some("-") => Box::new(stdout),
+        Some(f) => Box::new(BufWriter::new(File::create(f).map_err(|e| format!("cannot write {}: {}", f, e))?)),
+    };
+    Ok(Output::new(w, o.has("gzip"))?)
+}
+
+fn needs_similarity(mode: &str) -> CliError {
+    CliError::Usage(format!("the {} mode needs a similarity, use --scheme similarity", mode))
+}
+
+fn dist_with<M>(ms: &M, algo: &str, x: &[Dna], y: &[Dna]) -> M::Cost
+where M: MetricSpace<Item = Dna>
+{
+    match algo {
+        "naive" => dist_naif(ms, x, y),
+        "full" => dist_1(ms, x, y),
+        _ => dist_2(ms, x, y),
+    }
+}
+
+fn dist(args: &[String], stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<(), CliError> {
+    let o = Opts::parse(args, &[IO_OPTS, SCHEME_OPTS, &[("algo", None, true), ("mode", None, true)]])?;
+    if o.has("help") { return Ok(stdout.write_all(format!("{}{}{}", DIST_HELP, IO_HELP, SCHEME_HELP).as_bytes())?); }
+    let algo = o.choice("algo", &["linear", "full", "naive"])?;
+    let mode = o.choice("mode", &["global", "local", "overlap"])?;
+    let scheme = Scheme::from_opts(&o)?;
+    if mode != "global" && matches!(scheme, Scheme::Distance(_)) { return Err(needs_similarity(mode)); }
+    let (input, format) = open_input(&o, stdin)?;
+    if (format, algo, mode) == (SeqFormat::Adn, "linear", "global") {
+        // y is read as it is aligned, only x is in memory
+        let mut r = AdnReader::new(input)?;
+        let x = r.read_x()?;
+        let res = match &scheme {
+            Scheme::Distance(m) => dist_2_stream(m, &x, r)?.to_string(),
+            Scheme::Similarity(s) => dist_2_stream(s, &x, r)?.to_string(),
+        };
+        return write_output(&o, stdout, format!("{}\n", res).as_bytes());
+    }
+    let (x, y) = first_two(read_decoded(input, format)?)?;
+    let res = match (&scheme, mode) {
+        (Scheme::Distance(m), "global") => dist_with(m, algo, &x.seq, &y.seq).to_string(),
+        (Scheme::Similarity(s), "global") => dist_with(s, algo, &x.seq, &y.seq).to_string(),
+        (Scheme::Similarity(s), "local") => dist_local(s, &x.seq, &y.seq).to_string(),
+        (Scheme::Similarity(s), _) => dist_overlap(s, &x.seq, &y.seq).to_string(),
+        (Scheme::Distance(_), _) => unreachable!(),
+    };
+    write_output(&o, stdout, format!("{}\n", res).as_bytes())
+}
+
+/// Write an alignment of the parts x[rx] and y[ry] in the given format
+fn write_align<M>(format: &str, label: &str, cost: M::Cost, x: &FastaRecord, y: &FastaRecord, ranges: Option<(std::ops::Range<usize>, std::ops::Range<usize>)>, al: &Align<M>) -> Result<Vec<u8>, CliError>
+where M: MetricSpace<Item = Dna>
+{
+    let mut out = vec![];
+    match format {
+        "fasta" => {
+            write_fasta(&mut out, &x.name, &al.0)?;
+            write_fasta(&mut out, &y.name, &al.1)?;
+        }
+        "pairwise" | "tsv" | "jsonl" => {
+            let rec = match ranges {
+                Some((x_range, y_range)) => AlignRecord { x_name: &x.name, y_name: &y.name, x_range, y_range, cost, align: al },
+                None => AlignRecord::global(&x.name, &y.name, cost, al),
+            };
+            let mut w = writer(format.parse()?, &mut out);
+            w.write(&rec)?;
+            w.finish()?;
+        }
+        _ => {
+            writeln!(out, "{}: {:?}", label, cost)?;
+            if let Some((rx, ry)) = ranges {
+                writeln!(out, "{}: {}..{}", x.name, rx.start, rx.end)?;
+                writeln!(out, "{}: {}..{}", y.name, ry.start, ry.end)?;
+            }
+            writeln!(out, "{}", al.to_string().trim_start())?;
+        }
+    }
+    Ok(out)
+}
+
+fn align_global<M>(ms: &M, algo: &str, tie: TieBreak, format: &str, x: &FastaRecord, y: &FastaRecord) -> Result<Vec<u8>, CliError>
+where M: MetricSpace<Item = Dna>
+{
+    let al = match algo {
+        "full" => sol_1_with(ms, &x.seq, &y.seq, tie),
+        _ => sol_2_with(ms, &x.seq, &y.seq, tie),
+    };
+    write_align(format, "cost", al.cost(ms), x, y, None, &al)
+}
+
+fn align(args: &[String], stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<(), CliError> {
+    let o = Opts::parse(args, &[IO_OPTS, SCHEME_OPTS, &[("algo", None, true), ("mode", None, true), ("tie", None, true), ("format", None, true)]])?;
+    if o.has("help") { return Ok(stdout.write_all(format!("{}{}{}", ALIGN_HELP, IO_HELP, SCHEME_HELP).as_bytes())?); }
+    let algo = o.choice("algo", &["linear", "full"])?;
+    let mode = o.choice("mode", &["global", "local", "overlap"])?;
+    let tie = match o.choice("tie", &["diagonal", "left", "right"])? {
+        "diagonal" => TieBreak::Diagonal,
+        "left" => TieBreak::LeftGaps,
+        _ => TieBreak::RightGaps,
+    };
+    let format = o.choice("format", &["pretty", "fasta", "pairwise", "tsv", "jsonl"])?;
+    let scheme = Scheme::from_opts(&o)?;
+    if mode != "global" && matches!(scheme, Scheme::Distance(_)) { return Err(needs_similarity(mode)); }
+    let (x, y) = read_pair(&o, stdin)?;
+    let out = match (&scheme, mode) {
+        (Scheme::Distance(m), "global") => align_global(m, algo, tie, format, &x, &y)?,
+        (Scheme::Similarity(s), "global") => align_global(s, algo, tie, format, &x, &y)?,
+        (Scheme::Similarity(s), "local") => {
+            let l = sol_local(s, &x.seq, &y.seq);
+            write_align(format, "score", l.score, &x, &y, Some((l.x, l.y)), &l.align)?
+        }
+        (Scheme::Similarity(s), _) => {
+            let l = sol_overlap(s, &x.seq, &y.seq);
+            write_align(format, "score", l.score, &x, &y, Some((l.x, l.y)), &l.align)?
+        }
+        (Scheme::Distance(_), _) => unreachable!(),
+    };
+    write_output(&o, stdout, &out)
+}
+
+fn search_with<M>(ms: &M, pattern: &[Dna], text: &[Dna], k: M::Cost, with_start: bool) -> Result<Vec<u8>, CliError>
+where M: MetricSpace<Item = Dna>, M::Cost: Display
+{
+    let mut out = vec![];
+    for (end, cost) in sellers(ms, pattern, text, k) {
+        if with_start {
+            writeln!(out, "{}\t{}\t{}", recover(ms, pattern, text, (end, cost)).start, end, cost)?;
+        } else {
+            writeln!(out, "{}\t{}", end, cost)?;
+        }
+    }
+    Ok(out)
+}
+
+fn search(args: &[String], stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<(), CliError> {
+    let o = Opts::parse(args, &[IO_OPTS, SCHEME_OPTS, &[("pattern", Some('p'), true), ("max-cost", Some('k'), true), ("recover", None, false)]])?;
+    if o.has("help") { return Ok(stdout.write_all(format!("{}{}{}", SEARCH_HELP, IO_HELP, SCHEME_HELP).as_bytes())?); }
+    let Some(pattern) = o.get("pattern") else { return usage("search needs a --pattern".to_string()) };
+    let pattern = pattern
+        .chars()
+        .map(|c| c.to_string().parse::<Dna>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| CliError::Usage(e.to_string()))?;
+    let scheme = Scheme::from_opts(&o)?;
+    let text = read_input(&o, stdin)?.into_iter().next().ok_or("the input holds no sequence")?;
+    let with_start = o.has("recover");
+    let out = match &scheme {
+        Scheme::Distance(m) => {
+            let Some(k) = o.number::<u64>("max-cost")? else { return usage("search needs -k".to_string()) };
+            search_with(m, &pattern, &text.seq, k, with_start)?
+        }
+        Scheme::Similarity(s) => {
+            let Some(k) = o.number::<i64>("max-cost")? else { return usage("search needs -k".to_string()) };
+            search_with(s, &pattern, &text.seq, k, with_start)?
+        }
+    };
+    write_output(&o, stdout, &out)
+}
+
+fn msa_with<M>(ms: &M, records: &[FastaRecord]) -> Result<Vec<u8>, CliError>
+where M: MetricSpace<Item = Dna> + Sync, M::Cost: ToF64
+{
+    let al = msa(ms, &records.iter().map(|r| r.seq.clone()).collect::<Vec<_>>());
+    let mut out = vec![];
+    for (r, row) in records.iter().zip(&al.0) {
+        write_fasta(&mut out, &r.name, row)?;
+    }
+    Ok(out)
+}
+
+fn multi(args: &[String], stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<(), CliError> {
+    let o = Opts::parse(args, &[IO_OPTS, SCHEME_OPTS])?;
+    if o.has("help") { return Ok(stdout.write_all(format!("{}\noptions:\n{}{}", MSA_HELP, IO_HELP, SCHEME_HELP).as_bytes())?); }
+    let scheme = Scheme::from_opts(&o)?;
+    let records = read_input(&o, stdin)?;
+    let out = match &scheme {
+        Scheme::Distance(m) => msa_with(m, &records)?,
+        Scheme::Similarity(s) => msa_with(s, &records)?,
+    };
+    write_output(&o, stdout, &out)
+}
+
+fn convert(args: &[String], stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<(), CliError> {
+    let o = Opts::parse(args, &[IO_OPTS, &[("to", None, true)]])?;
+    if o.has("help") { return Ok(stdout.write_all(format!("{}{}", CONVERT_HELP, IO_HELP).as_bytes())?); }
+    if !o.has("to") { return usage("convert needs --to".to_string()); }
+    let to = o.choice("to", &["fasta", "adn"])?;
+    let records = read_input(&o, stdin)?;
+    let mut out = vec![];
+    if to == "fasta" {
+        for r in &records {
+            write_fasta(&mut out, &r.name, &r.seq)?;
+        }
+    } else {
+        let [x, y] = &records[..] else {
+            return Err(format!("the adn format holds exactly two sequences, not {}", records.len()).into());
+        };
+        write_adn(&mut out, &DnaBlock(x.seq.clone(), y.seq.clone()))?;
+    }
+    write_output(&o, stdout, &out)
+}
+
+/// A pair of the batch command, the pairs of a manifest are read by the workers
+enum Job {
+    Pair(Pair),
+    Entry(ManifestEntry),
+}
+
+fn batch_with<M>(ms: &M, jobs: Box<dyn Iterator<Item = Job> + '_>, workers: usize, align: bool, format: &str, out: &mut dyn Write) -> Result<(), CliError>
+where M: MetricSpace<Item = Dna> + Sync, M::Cost: Send + Display
+{
+    let word = |row: &[Gapped<Dna>]| row.iter().map(|g| g.to_string()).collect::<String>();
+    // the lines format, or a writer of alignments
+    let mut w = match format {
+        "lines" => Err(out),
+        f => Ok(writer::<M, _>(f.parse()?, out)),
+    };
+    let align = align || w.is_ok();
+    let (mut failed, mut total) = (0, 0);
+    let mut write_err = None;
+    run_batch(jobs, workers, |job| {
+        let p = match job {
+            Job::Pair(p) => p,
+            Job::Entry(e) => e.load()?,
+        };
+        if align {
+            let al = sol_2(ms, &p.x.seq, &p.y.seq);
+            Ok((al.cost(ms), Some(al), p))
+        } else {
+            Ok((dist_2(ms, &p.x.seq, &p.y.seq), None, p))
+        }
+    }, |k, res| {
+        total += 1;
+        if res.is_err() { failed += 1; }
+        let res = match (res, &mut w) {
+            (Ok((cost, Some(al), p)), Err(out)) => writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}", k, tsv_field(&p.x.name), tsv_field(&p.y.name), cost, word(&al.0), word(&al.1)),
+            (Ok((cost, None, p)), Err(out)) => writeln!(out, "{}\t{}\t{}\t{}", k, tsv_field(&p.x.name), tsv_field(&p.y.name), cost),
+            (Err(e), Err(out)) => writeln!(out, "{}\terror\t{}", k, tsv_field(&e.to_string())),
+            (Ok((cost, al, p)), Ok(w)) => w.write(&AlignRecord::global(&p.x.name, &p.y.name, cost, &al.expect("the alignment is computed!"))),
+            (Err(e), Ok(w)) => w.write_error(k, &e.to_string().replace('\n', " ")),
+        };
+        if let Err(e) = res { write_err.get_or_insert(e); }
+    });
+    if let Some(e) = write_err { return Err(e.into()); }
+    match w {
+        Ok(mut w) => w.finish()?,
+        Err(out) => out.flush()?,
+    }
+    if failed > 0 {
+        return Err(format!("{} of the {} pairs failed", failed, total).into());
+    }
+    Ok(())
+}
+
+fn batch(args: &[String], stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<(), CliError> {
+    let o = Opts::parse(args, &[IO_OPTS, SCHEME_OPTS, &[("manifest", Some('m'), true), ("jobs", Some('j'), true), ("align", None, false), ("format", None, true)]])?;
+    if o.has("help") { return Ok(stdout.write_all(format!("{}{}{}", BATCH_HELP, IO_HELP, SCHEME_HELP).as_bytes())?); }
+    let workers = match o.number::<usize>("jobs")? {
+        Some(0) => return usage("--jobs must be at least 1".to_string()),
+        Some(j) => j,
+        None => default_workers(),
+    };
+    let format = o.choice("format", &["lines", "pairwise", "tsv", "jsonl"])?;
+    let scheme = Scheme::from_opts(&o)?;
+    let records;
+    let jobs: Box<dyn Iterator<Item = Job>> = match o.get("manifest") {
+        Some(m) => {
+            let s = std::fs::read_to_string(m).map_err(|e| format!("cannot read {}: {}", m, e))?;
+            let dir = std::path::Path::new(m).parent().unwrap_or(std::path::Path::new(""));
+            Box::new(parse_manifest(&s, dir).into_iter().map(Job::Entry))
+        }
+        None => {
+            records = read_input(&o, stdin)?;
+            Box::new(all_vs_all(&records).map(Job::Pair))
+        }
+    };
+    let mut out = open_output(&o, stdout)?;
+    let res = match &scheme {
+        Scheme::Distance(m) => batch_with(m, jobs, workers, o.has("align"), format, &mut out),
+        Scheme::Similarity(s) => batch_with(s, jobs, workers, o.has("align"), format, &mut out),
+    };
+    out.finish()?;
+    res
+}
+
+/// Run the command line `args` (without the name of the program)
+pub fn run(args: &[String], stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<(), CliError> {
+    let Some((cmd, rest)) = args.split_first() else { return usage("a command is needed".to_string()) };
+    match cmd.as_str() {
+        "dist" => dist(rest, stdin, stdout),
+        "align" => align(rest, stdin, stdout),
+        "search" => search(rest, stdin, stdout),
+        "msa" => multi(rest, stdin, stdout),
+        "convert" => convert(rest, stdin, stdout),
+        "batch" => batch(rest, stdin, stdout),
+        "help" | "--help" | "-h" => Ok(stdout.write_all(USAGE.as_bytes())?),
+        c => usage(format!("unknown command {}", c)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::read_test_inst;
+    use super::{CliError, run};
+
+    /// Run a command line with the given input, its output and exit code
+    fn exec(line: &str, input: &str) -> (String, i32) {
+        let args = line.split_whitespace().map(|s| s.to_string()).collect::<Vec<_>>();
+        let mut out = vec![];
+        let code = match run(&args, &mut input.as_bytes(), &mut out) {
+            Ok(()) => 0,
+            Err(e) => e.exit_code(),
+        };
+        (String::from_utf8(out).unwrap(), code)
+    }
+
+    const ADN: &str = "10\n5\nT A T A T G A G T C\nT A T T T\n";
+
+    #[test]
+    fn dist_and_align(){
+        assert_eq!(exec("dist", ADN), ("10\n".to_string(), 0));
+        assert_eq!(exec("dist --algo full", ADN).0, "10\n");
+        assert_eq!(exec("dist --algo=naive --scheme unit", ADN).0, "5\n");
+        assert_eq!(exec("dist --scheme similarity --mode local", ADN).0, "3\n");
+        assert_eq!(exec("dist --scheme similarity --match 2 --gap -1", ">a\nACGT\n>b\nAGT\n").0, "5\n");
+        assert_eq!(exec("align", ADN), ("cost: 10\n| T A T A T G A G T C \n| T A T - T - - - T - \n".to_string(), 0));
+        assert_eq!(exec("align --format fasta --algo full", ">a\nACGT\n>b\nAGT\n").0, ">a\nACGT\n>b\nA-GT\n");
+        assert!(exec("align --format pairwise", ">a\nACGT\n>b\nAGT\n").0.contains("Query  1  ACGT  4\n          | ||\nSbjct  1  A-GT  3\n"));
+        let local = exec("align --mode local --scheme similarity", ">a\nTTTTACGTACGTTTTT\n>b\nGGACGTACGGG\n").0;
+        assert!(local.starts_with("score: 7\na: 4..11\nb: 2..9\n"), "{}", local);
+    }
+
+    #[test]
+    fn search_msa_convert(){
+        let text = ">text\nTTTTACGTTTTTACCTTTT\n";
+        assert_eq!(exec("search -p ACGT -k 0", text).0, "8\t0\n");
+        assert_eq!(exec("search --pattern ACGT -k 1 --recover --scheme unit", text).0, "4\t7\t1\n4\t8\t0\n4\t9\t1\n12\t16\t1\n");
+
+        let (out, code) = exec("msa", ">a\nACGTACGT\n>b\nACGTCGT\n>c\nACGAACGT\n");
+        assert_eq!(code, 0);
+        let rows = crate::io::parse_fasta(&out.replace('-', "")).unwrap();
+        assert_eq!(rows.iter().map(|r| r.name.as_str()).collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert!(out.contains(">b\nACGT-CGT\n"), "{}", out);
+
+        let fasta = exec("convert --to fasta", ADN).0;
+        assert_eq!(fasta, ">x\nTATATGAGTC\n>y\nTATTT\n");
+        assert_eq!(exec("convert --to adn", &fasta).0, "10\n5\nT A T A T G A G T C \nT A T T T \n");
+        assert_eq!(exec("convert --to adn", ">a\nA\n").1, 1);
+    }
+
+    #[test]
+    fn errors_and_help(){
+        assert_eq!(exec("", "").1, 2);
+        assert_eq!(exec("frobnicate", "").1, 2);
+        assert_eq!(exec("dist --algo quantum", ADN).1, 2);
+        assert_eq!(exec("dist --mode local", ADN).1, 2, "local needs a similarity");
+        assert_eq!(exec("dist --bogus", ADN).1, 2);
+        assert_eq!(exec("dist -z", ADN).1, 2);
+        assert_eq!(exec("dist --algo", ADN).1, 2);
+        assert_eq!(exec("search -k 2", ADN).1, 2);
+        assert_eq!(exec("dist", "3\n1\nA C\nA\n").1, 1, "invalid input");
+        assert_eq!(exec("dist -i this/file/does/not.exist", "").1, 1);
+        assert!(matches!(super::run(&["dist".to_string()], &mut "".as_bytes(), &mut vec![]), Err(CliError::Failed(_))));
+        for cmd in ["--help", "help", "dist --help", "align -h", "search --help", "msa --help", "convert --help"] {
+            let (out, code) = exec(cmd, "");
+            assert_eq!(code, 0);
+            assert!(out.starts_with("usage") || out.starts_with("runa"), "{}", out);
+        }
+    }
+
+    #[test]
+    fn batch(){
+        let fasta = ">a\nACGT\n>b\nAGT\n>c\nACGA\n";
+        assert_eq!(exec("batch -j 2 --scheme unit", fasta), ("0\ta\tb\t1\n1\ta\tc\t1\n2\tb\tc\t2\n".to_string(), 0));
+        assert_eq!(exec("batch --align --scheme unit", ">a\nACGT\n>b\nAGT\n").0, "0\ta\tb\t1\tACGT\tA-GT\n");
+        assert_eq!(exec("batch -j 0", fasta).1, 2);
+        let jsonl = exec("batch --format jsonl --scheme unit", fasta).0;
+        assert_eq!(jsonl.lines().count(), 3);
+        assert!(jsonl.starts_with("{\"x\":\"a\",\"y\":\"b\",") && jsonl.contains("\"cigar\":\"1=1D2=\""), "{}", jsonl);
+        let tsv = exec("batch --format tsv", fasta).0;
+        assert_eq!(tsv.lines().next(), Some(crate::output::TSV_HEADER));
+
+        let dir = std::env::temp_dir().join("runa_cli_batch");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("p.fasta"), fasta).unwrap();
+        std::fs::write(dir.join("list"), "p.fasta\nnothing.adn\n").unwrap();
+        let (out, code) = exec(&format!("batch --scheme unit -m {}", dir.join("list").display()), "");
+        assert_eq!(code, 1, "a pair failed");
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "0\ta\tb\t1");
+        assert!(lines[1].starts_with("1\terror\tcannot read"), "{}", lines[1]);
+    }
+
+    #[test]
+    fn files(){
+        let dir = std::env::temp_dir();
+        let (i, o) = (dir.join("runa_cli_in.adn"), dir.join("runa_cli_out.txt"));
+        let b = read_test_inst("Inst_0000010_7.adn").unwrap();
+        let word = |s: &[crate::dna::Dna]| s.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(" ");
+        std::fs::write(&i, format!("{}\n{}\n{}\n{}\n", b.0.len(), b.1.len(), word(&b.0), word(&b.1))).unwrap();
+        let line = format!("dist -i {} -o {}", i.display(), o.display());
+        assert_eq!(exec(&line, ""), (String::new(), 0));
+        assert_eq!(std::fs::read_to_string(&o).unwrap(), "8\n");
+
+        // compressed output, and input recognised whatever the name of the file
+        let line = format!("convert --to fasta --gzip -i {} -o {}", i.display(), o.display());
+        if cfg!(feature = "gzip") {
+            assert_eq!(exec(&line, "").1, 0);
+            assert_eq!(&std::fs::read(&o).unwrap()[..2], &[0x1f, 0x8b]);
+            assert_eq!(exec(&format!("dist -i {}", o.display()), ""), ("8\n".to_string(), 0));
+            // the input is decompressed once, a file compressed twice is not sequences
+            let twice = dir.join("runa_cli_twice.gz");
+            let mut w = crate::io::Output::new(vec![], true).unwrap();
+            std::io::Write::write_all(&mut w, &std::fs::read(&o).unwrap()).unwrap();
+            std::fs::write(&twice, w.finish().unwrap()).unwrap();
+            assert_eq!(exec(&format!("dist --in-format fasta -i {}", twice.display()), "").1, 1, "compressed twice");
+            assert_eq!(exec(&format!("batch --gzip --in-format fasta -i {} -o {}", o.display(), i.display()), "").1, 0);
+            assert_eq!(exec(&format!("convert --to fasta -i {}", i.display()), "").1, 1, "not sequences");
+        } else {
+            assert_eq!(exec(&line, "").1, 1);
+        }
+    }
+}
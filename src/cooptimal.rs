@@ -0,0 +1,229 @@
+//! Co-optimal alignments, when several moves tie in the dp table there are several optimal alignments
+//! and sol_1 only returns one of them. They can be counted, enumerated or sampled from the table
+//! computed by dist_dp_full.
+
+use crate::math::*;
+use crate::rng::Rng;
+
+/// The moves of the dp table, in the order in which they are preferred by sol_1_tab
+pub(crate) const MOVES: [(usize, usize); 3] = [(1, 1), (0, 1), (1, 0)];
+
+/// true if coming to (i, j) by the move (di, dj) is optimal in the table t
+fn optimal_move<M>(ms: &M, x: &[M::Item], y: &[M::Item], t: &[Vec<M::Cost>], i: usize, j: usize, (di, dj): (usize, usize)) -> bool
+where M: MetricSpace
+{
+    if i < di || j < dj { return false; }
+    let c = match (di, dj) {
+        (1, 1) => ms.sub(x[i-1], y[j-1]),
+        (0, 1) => ms.ins(),
+        _ => ms.del(),
+    };
+    t[i][j] == t[i-di][j-dj] + c
+}
+
+/// The column of the alignment corresponding to the move (di, dj) arriving at (i, j)
+pub(crate) fn column<T: Copy>(x: &[T], y: &[T], i: usize, j: usize, (di, dj): (usize, usize)) -> (Gapped<T>, Gapped<T>) {
+    let a = if di == 1 { Gapped::Item(x[i-1]) } else { Gapped::Gap };
+    let b = if dj == 1 { Gapped::Item(y[j-1]) } else { Gapped::Gap };
+    (a, b)
+}
+
+/// The number of optimal paths from (0, 0) to every cell of the table t,
+/// the counts saturate at u128::MAX. O(n^2) time and memory
+pub fn count_tab<M>(ms: &M, x: &[M::Item], y: &[M::Item], t: &[Vec<M::Cost>]) -> Vec<Vec<u128>>
+where M: MetricSpace
+{
+    let (n, m) = (x.len(), y.len());
+    let mut c = vec![vec![0u128; m + 1]; n + 1];
+    c[0][0] = 1;
+    for i in 0..=n {
+        for j in 0..=m {
+            for mv in MOVES {
+                if optimal_move(ms, x, y, t, i, j, mv) {
+                    c[i][j] = c[i][j].saturating_add(c[i - mv.0][j - mv.1]);
+                }
+            }
+        }
+    }
+    c
+}
+
+/// The number of optimal alignments of x and y, t being the table of dist_dp_full.
+/// Saturates at u128::MAX
+pub fn count_optimal<M>(ms: &M, x: &[M::Item], y: &[M::Item], t: &[Vec<M::Cost>]) -> u128
+where M: MetricSpace
+{
+    count_tab(ms, x, y, t)[x.len()][y.len()]
+}
+
+/// Lazy enumeration of the optimal alignments, see [`optimal_alignments`]
+pub struct CoOptimal<'a, M: MetricSpace> {
+    ms: &'a M,
+    x: &'a [M::Item],
+    y: &'a [M::Item],
+    t: &'a [Vec<M::Cost>],
+    /// the cells of the current path from (n, m), with the next move to try from each of them
+    stack: Vec<(usize, usize, usize)>,
+}
+
+impl<M: MetricSpace> Iterator for CoOptimal<'_, M> {
+    type Item = Align<M>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let &(i, j, k) = self.stack.last()?;
+            if i == 0 && j == 0 {
+                let (mut xb, mut yb) = (vec![], vec![]);
+                for w in self.stack.windows(2) {
+                    let (a, b) = column(self.x, self.y, w[0].0, w[0].1, (w[0].0 - w[1].0, w[0].1 - w[1].1));
+                    xb.push(a);
+                    yb.push(b);
+                }
+                self.stack.pop();
+                xb.reverse();
+                yb.reverse();
+                return Some(Align(xb, yb));
+            }
+            if k == MOVES.len() {
+                self.stack.pop();
+                continue;
+            }
+            self.stack.last_mut().unwrap().2 += 1;
+            let mv = MOVES[k];
+            if optimal_move(self.ms, self.x, self.y, self.t, i, j, mv) {
+                self.stack.push((i - mv.0, j - mv.1, 0));
+            }
+        }
+    }
+}
+
+/// Enumerate lazily all the optimal alignments of x and y, t being the table of dist_dp_full.
+/// The first one is the alignment of sol_1, every next one costs O(n + m) time.
+pub fn optimal_alignments<'a, M>(ms: &'a M, x: &'a [M::Item], y: &'a [M::Item], t: &'a [Vec<M::Cost>]) -> CoOptimal<'a, M>
+where M: MetricSpace
+{
+    assert_eq!(x.len() + 1, t.len());
+    assert_eq!(y.len() + 1, t[0].len());
+    CoOptimal { ms, x, y, t, stack: vec![(x.len(), y.len(), 0)] }
+}
+
+/// Uniform sampling of the optimal alignments of two sequences
+pub struct OptimalSampler<'a, M: MetricSpace> {
+    ms: &'a M,
+    x: &'a [M::Item],
+    y: &'a [M::Item],
+    t: &'a [Vec<M::Cost>],
+    counts: Vec<Vec<u128>>,
+}
+
+impl<'a, M: MetricSpace> OptimalSampler<'a, M> {
+    /// Prepare the sampling of the optimal alignments of x and y, t being the table of dist_dp_full.
+    /// O(n^2) time and memory
+    pub fn new(ms: &'a M, x: &'a [M::Item], y: &'a [M::Item], t: &'a [Vec<M::Cost>]) -> Self {
+        let counts = count_tab(ms, x, y, t);
+        OptimalSampler { ms, x, y, t, counts }
+    }
+
+    /// The number of optimal alignments, saturating at u128::MAX
+    pub fn count(&self) -> u128 {
+        self.counts[self.x.len()][self.y.len()]
+    }
+
+    /// Draw one of the optimal alignments, all of them being equally likely
+    /// (as long as the counts did not saturate). O(n + m) time
+    pub fn sample(&self, rng: &mut Rng) -> Align<M> {
+        let (mut xb, mut yb) = (vec![], vec![]);
+        let (mut i, mut j) = (self.x.len(), self.y.len());
+        while i > 0 || j > 0 {
+            let options = MOVES
+                .into_iter()
+                .filter(|&mv| optimal_move(self.ms, self.x, self.y, self.t, i, j, mv))
+                .map(|mv| (mv, self.counts[i - mv.0][j - mv.1]))
+                .collect::<Vec<_>>();
+            let total = options.iter().fold(0u128, |s, &(_, c)| s.saturating_add(c));
+            let mut r = rng.below_u128(total);
+            let mut mv = options[0].0;
+            for &(o, c) in options.iter() {
+                mv = o;
+                if r < c { break; }
+                r -= c;
+            }
+            let (a, b) = column(self.x, self.y, i, j, mv);
+            xb.push(a);
+            yb.push(b);
+            i -= mv.0;
+            j -= mv.1;
+        }
+        xb.reverse();
+        yb.reverse();
+        Align(xb, yb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{Dna, DnaBlock, DnaMetricSpace as Dms};
+    use crate::io::read_test_insts_all;
+    use crate::math::{cout_align, dist_dp_full, rm_gaps, sol_1};
+    use crate::matrix::SubstMatrix;
+    use crate::rng::Rng;
+    use super::{OptimalSampler, count_optimal, optimal_alignments};
+
+    fn seq(s: &str) -> Vec<Dna> {
+        s.chars().map(|c| c.to_string().parse().expect("invalid sequence!")).collect()
+    }
+
+    #[test]
+    fn small(){
+        let (x, y) = (seq("AA"), seq("A"));
+        let t = dist_dp_full(&Dms, &x, &y);
+        assert_eq!(count_optimal(&Dms, &x, &y, &t), 2);
+        let all = optimal_alignments(&Dms, &x, &y, &t).map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(all, vec!["\n| A A \n| - A ", "\n| A A \n| A - "]);
+
+        let sampler = OptimalSampler::new(&Dms, &x, &y, &t);
+        let mut rng = Rng::new(1);
+        let firsts = (0..1000).filter(|_| sampler.sample(&mut rng).1[0].is_gap()).count();
+        assert!((400..600).contains(&firsts), "not uniform: {} / 1000", firsts);
+    }
+
+    #[test]
+    fn enumerate_instances(){
+        let testcases = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 20)
+            .map(|(_, b)| b);
+        for DnaBlock(l, r) in testcases {
+            let t = dist_dp_full(&Dms, &l, &r);
+            let d = t[l.len()][r.len()];
+            let count = count_optimal(&Dms, &l, &r, &t);
+            let all = optimal_alignments(&Dms, &l, &r, &t).take(1000).collect::<Vec<_>>();
+            assert_eq!(all.len() as u128, count.min(1000));
+            assert_eq!(all[0], sol_1(&Dms, &l, &r));
+            for (k, al) in all.iter().enumerate() {
+                assert_eq!(cout_align(&Dms, &al.0, &al.1), d);
+                assert_eq!(rm_gaps::<Dms>(al.0.clone()), l);
+                assert_eq!(rm_gaps::<Dms>(al.1.clone()), r);
+                assert!(all[..k].iter().all(|b| b != al), "alignment enumerated twice");
+            }
+
+            let sampler = OptimalSampler::new(&Dms, &l, &r, &t);
+            let mut rng = Rng::new(7);
+            for _ in 0..10 {
+                let al = sampler.sample(&mut rng);
+                assert_eq!(cout_align(&Dms, &al.0, &al.1), d);
+            }
+        }
+    }
+
+    #[test]
+    fn saturation(){
+        // with unit costs, the 100 deletions can be anywhere: C(200, 100) > u128::MAX
+        let (x, y) = (vec![Dna::A; 200], vec![Dna::A; 100]);
+        let unit = SubstMatrix::unit();
+        let t = dist_dp_full(&unit, &x, &y);
+        assert_eq!(count_optimal(&unit, &x, &y, &t), u128::MAX);
+        let (x, y) = (vec![Dna::A; 20], vec![Dna::A; 10]);
+        let t = dist_dp_full(&unit, &x, &y);
+        assert_eq!(count_optimal(&unit, &x, &y, &t), 184756);
+    }
+}
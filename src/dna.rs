@@ -7,18 +7,16 @@ use std::fmt::Display;
 
 use crate::math::*;
 
-/// Dna element, an item in a dna sequence
+/// Dna element, an item in a dna sequence. 
+/// Gaps are not Dna elements, in alignments they are represented with [`Gapped`]
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum Dna {
-    A, C, T, G, Gap
+    A, C, T, G
 }
 
 impl Display for Dna {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Dna::Gap => write!(f, "-"),
-            _ => write!(f, "{:?}", self)
-        }
+        write!(f, "{:?}", self)
     }
 }
 
@@ -30,29 +28,30 @@ impl std::str::FromStr for Dna {
             "C" | "c" => Ok(Self::C),
             "T" | "t" => Ok(Self::T),
             "G" | "g" => Ok(Self::G),
-            "-" | "_" => Ok(Self::Gap),
             x => bail!(&format!("DNA element error: invalid string passed \"{}\"", x)[..]),
         }
     }
 }
 
-/// Metric space defined for dna sequences, it is defined as mentioned in the assignement
-#[derive(Debug, PartialEq, Eq)]
+/// Metric space defined for dna sequences, it is defined as mentioned in the assignement.
+/// Notice that the pairs costing 3 (A/T and G/C) are the complementary pairs, not the transitions,
+/// see [`crate::matrix::SubstMatrix`] for other schemes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
 pub struct DnaMetricSpace;
 impl MetricSpace for DnaMetricSpace {
     type Cost = u64;
     type Item = Dna; 
+    type Goal = Minimise;
 
-    const DEL: Self::Cost = 2;
-    const INS: Self::Cost = 2;
-    const GAP: Self::Item = Dna::Gap;
     const ZEROCOST: Self::Cost = 0;
     const INFCOST: Self::Cost = Self::Cost::MAX;
 
-    fn sub(a: Self::Item, b: Self::Item) -> Self::Cost { 
+    fn del(&self) -> Self::Cost { 2 }
+    fn ins(&self) -> Self::Cost { 2 }
+
+    fn sub(&self, a: Self::Item, b: Self::Item) -> Self::Cost { 
         use Dna as D;
         match (a, b) {
-            (D::Gap, _) | (_, D::Gap) => panic!("invalid argument passed!"),
             (x, y) if x == y => 0,
             (D::A, D::T) | (D::G, D::C) | (D::T, D::A) | (D::C, D::G) => 3,
             (_, _) => 4
@@ -60,10 +59,53 @@ impl MetricSpace for DnaMetricSpace {
     }
 }
 
+/// Similarity scoring scheme for dna sequences, matches are rewarded, mismatches and gaps are penalised
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DnaSimilarity {
+    pub matched: i64,
+    pub mismatch: i64,
+    pub gap: i64,
+}
+
+impl Default for DnaSimilarity {
+    fn default() -> Self {
+        DnaSimilarity { matched: 1, mismatch: -1, gap: -2 }
+    }
+}
+
+impl Similarity for DnaSimilarity {
+    type Item = Dna;
+    type Score = i64;
+
+    const ZEROSCORE: Self::Score = 0;
+    const MINSCORE: Self::Score = Self::Score::MIN;
+
+    fn gap(&self) -> Self::Score { self.gap }
+
+    fn score(&self, a: Self::Item, b: Self::Item) -> Self::Score {
+        if a == b { self.matched } else { self.mismatch }
+    }
+}
+
 /// Data structure representing a pair of Dna's
 #[derive(Debug, PartialEq, Eq)]
 pub struct DnaBlock(pub Vec<Dna>, pub Vec<Dna>);
 
+/// The .adn format, the lengths and then the letters of x and y each followed by a space
+impl Display for DnaBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}\n{}", self.0.len(), self.1.len())?;
+        for s in [&self.0, &self.1] {
+            for d in s {
+                write!(f, "{} ", d)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
 impl FromStr for DnaBlock {
     type Err = Box<dyn Error>;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -107,5 +149,17 @@ mod tests{
         let x = read_test_inst("Inst_0000010_44.adn").expect("the reader cannot read the file!");
         assert_eq!(x, DnaBlock(vec![T, A, T, A, T, G, A ,G ,T, C], vec![T, A, T, T, T]), "the reader is not correct!")
     }
+
+    #[test]
+    fn write_double_dna_block(){
+        assert_eq!(DnaBlock(vec![T, A], vec![]).to_string(), "2\n0\nT A \n\n");
+        // the instances are written back byte for byte
+        for e in std::fs::read_dir(std::env::var("GENOME_DATA").unwrap()).unwrap() {
+            let path = e.unwrap().path();
+            if path.extension().is_none_or(|e| e != "adn") { continue; }
+            let s = std::fs::read_to_string(&path).unwrap();
+            assert_eq!(s.parse::<DnaBlock>().unwrap().to_string(), s, "{} is not written back", path.display());
+        }
+    }
 }
 
@@ -0,0 +1,390 @@
+//! The C API of the shared library (the cdylib target), declared in include/runa.h.
+//!
+//! A scheme is created with one of the `runa_scheme_*` functions, two sequences are then compared with
+//! [`runa_dist`] or aligned with [`runa_align`], and the alignment is read back with the `runa_alignment_*`
+//! functions. Everything created by the library is freed by it, with [`runa_scheme_free`] and
+//! [`runa_alignment_free`]. The sequences are byte strings of A, C, G and T (in any case) with their length,
+//! they need no terminating zero. On an error the functions return NULL (or -1) and [`runa_last_error`]
+//! tells what went wrong; no panic ever crosses the API.
+//!
+//! Safety: the pointers given to the library must be NULL or valid, the sequences for their length and the
+//! C strings up to their terminating zero, and the schemes and alignments must come from the library
+//! and not have been freed. The strings returned by an alignment live as long as it.
+//!
+//! The header is generated from this file, `RUNA_BLESS=1 cargo test ffi` rewrites it after a change.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::dna::{Dna, DnaSimilarity};
+use crate::io::{letter, shown};
+use crate::math::*;
+use crate::matrix::{Scheme, SubstMatrix};
+use crate::output::cigar;
+
+/// Full table algorithms, dist_1 and sol_1
+pub const RUNA_FULL: c_int = 0;
+/// Linear memory algorithms, dist_2 and sol_2
+pub const RUNA_LINEAR: c_int = 1;
+/// Best local alignment, dist_local and sol_local, with a similarity scheme
+pub const RUNA_LOCAL: c_int = 2;
+/// Best overlap of a suffix of x with a prefix of y, dist_overlap and sol_overlap, with a similarity scheme
+pub const RUNA_OVERLAP: c_int = 3;
+
+/// A scoring scheme, a distance or a similarity
+pub struct RunaScheme(Scheme);
+
+/// An alignment with its cost, the alignment of the parts x[x_start..x_end] and y[y_start..y_end]
+pub struct RunaAlignment {
+    cost: i64,
+    x: Range<usize>,
+    y: Range<usize>,
+    cigar: CString,
+    row_x: CString,
+    row_y: CString,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(msg: String) {
+    let msg = CString::new(msg.replace('\0', "\\0")).expect("no zero left!");
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+/// Run f, an error or a panic is kept for runa_last_error
+fn guard<T>(f: impl FnOnce() -> Result<T, Box<dyn Error>>) -> Option<T> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(x)) => Some(x),
+        Ok(Err(e)) => {
+            set_error(e.to_string());
+            None
+        }
+        Err(_) => {
+            set_error("RuNA panicked".to_string());
+            None
+        }
+    }
+}
+
+unsafe fn text<'a>(s: *const c_char, what: &str) -> Result<&'a str, Box<dyn Error>> {
+    if s.is_null() { return Err(format!("{} is NULL", what).into()); }
+    Ok(CStr::from_ptr(s).to_str().map_err(|_| format!("{} is not UTF-8", what))?)
+}
+
+unsafe fn sequence(s: *const u8, len: usize, what: &str) -> Result<Vec<Dna>, Box<dyn Error>> {
+    if len == 0 { return Ok(vec![]); }
+    if s.is_null() { return Err(format!("{} is NULL", what).into()); }
+    std::slice::from_raw_parts(s, len).iter().enumerate()
+        .map(|(i, &b)| letter(b).ok_or_else(|| format!("invalid letter '{}' at {} of {}", shown(b), i, what).into()))
+        .collect()
+}
+
+unsafe fn scheme_of<'a>(s: *const RunaScheme) -> Result<&'a Scheme, Box<dyn Error>> {
+    s.as_ref().map(|s| &s.0).ok_or_else(|| "the scheme is NULL".into())
+}
+
+fn cost<C: TryInto<i64>>(c: C) -> i64 {
+    c.try_into().unwrap_or(i64::MAX)
+}
+
+fn needs_similarity() -> Box<dyn Error> {
+    "the local and overlap algorithms need a similarity scheme".into()
+}
+
+fn unknown(algo: c_int) -> Box<dyn Error> {
+    format!("unknown algorithm {}", algo).into()
+}
+
+fn distance(s: &Scheme, algo: c_int, x: &[Dna], y: &[Dna]) -> Result<i64, Box<dyn Error>> {
+    Ok(match (s, algo) {
+        (Scheme::Distance(m), RUNA_FULL) => cost(dist_1(m, x, y)),
+        (Scheme::Distance(m), RUNA_LINEAR) => cost(dist_2(m, x, y)),
+        (Scheme::Distance(_), RUNA_LOCAL | RUNA_OVERLAP) => return Err(needs_similarity()),
+        (Scheme::Similarity(m), RUNA_FULL) => cost(dist_1(m, x, y)),
+        (Scheme::Similarity(m), RUNA_LINEAR) => cost(dist_2(m, x, y)),
+        (Scheme::Similarity(m), RUNA_LOCAL) => cost(dist_local(m, x, y)),
+        (Scheme::Similarity(m), RUNA_OVERLAP) => cost(dist_overlap(m, x, y)),
+        _ => return Err(unknown(algo)),
+    })
+}
+
+impl RunaAlignment {
+    fn new<M>(cost: i64, x: Range<usize>, y: Range<usize>, al: &Align<M>) -> Self
+    where M: MetricSpace<Item = Dna>
+    {
+        let row = |r: &[Gapped<Dna>]| CString::new(r.iter().map(|g| g.to_string()).collect::<String>()).expect("a row has no zero!");
+        let cigar = CString::new(cigar(al)).expect("a cigar has no zero!");
+        RunaAlignment { cost, x, y, cigar, row_x: row(&al.0), row_y: row(&al.1) }
+    }
+
+    fn global<M>(ms: &M, al: Align<M>, x: &[Dna], y: &[Dna]) -> Self
+    where M: MetricSpace<Item = Dna>, M::Cost: TryInto<i64>
+    {
+        RunaAlignment::new(cost(al.cost(ms)), 0..x.len(), 0..y.len(), &al)
+    }
+}
+
+fn alignment(s: &Scheme, algo: c_int, x: &[Dna], y: &[Dna]) -> Result<RunaAlignment, Box<dyn Error>> {
+    Ok(match (s, algo) {
+        (Scheme::Distance(m), RUNA_FULL) => RunaAlignment::global(m, sol_1(m, x, y), x, y),
+        (Scheme::Distance(m), RUNA_LINEAR) => RunaAlignment::global(m, sol_2(m, x, y), x, y),
+        (Scheme::Distance(_), RUNA_LOCAL | RUNA_OVERLAP) => return Err(needs_similarity()),
+        (Scheme::Similarity(m), RUNA_FULL) => RunaAlignment::global(m, sol_1(m, x, y), x, y),
+        (Scheme::Similarity(m), RUNA_LINEAR) => RunaAlignment::global(m, sol_2(m, x, y), x, y),
+        (Scheme::Similarity(m), RUNA_LOCAL) => {
+            let l = sol_local(m, x, y);
+            RunaAlignment::new(cost(l.score), l.x, l.y, &l.align)
+        }
+        (Scheme::Similarity(m), RUNA_OVERLAP) => {
+            let l = sol_overlap(m, x, y);
+            RunaAlignment::new(cost(l.score), l.x, l.y, &l.align)
+        }
+        _ => return Err(unknown(algo)),
+    })
+}
+
+/// The version of the library, a static string
+#[no_mangle]
+pub extern "C" fn runa_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// The message of the last error of the calling thread, NULL if there was none. It lives until the next error
+#[no_mangle]
+pub extern "C" fn runa_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+/// A scheme by name: course, unit, kimura (distances) or similarity (match 1, mismatch -1, gap -2)
+#[no_mangle]
+pub unsafe extern "C" fn runa_scheme_new(name: *const c_char) -> *mut RunaScheme {
+    guard(|| {
+        let s = Scheme::preset(text(name, "the name of the scheme")?)?;
+        Ok(Box::into_raw(Box::new(RunaScheme(s))))
+    }).unwrap_or(ptr::null_mut())
+}
+
+/// A similarity scheme with the given scores, the best alignments have the highest score
+#[no_mangle]
+pub extern "C" fn runa_scheme_similarity(matched: i64, mismatch: i64, gap: i64) -> *mut RunaScheme {
+    Box::into_raw(Box::new(RunaScheme(Scheme::Similarity(DnaSimilarity { matched, mismatch, gap }))))
+}
+
+/// A distance scheme read from the text of a substitution matrix (see runa::matrix)
+#[no_mangle]
+pub unsafe extern "C" fn runa_scheme_matrix(matrix: *const c_char) -> *mut RunaScheme {
+    guard(|| {
+        let m = text(matrix, "the matrix")?.parse::<SubstMatrix>()?;
+        Ok(Box::into_raw(Box::new(RunaScheme(Scheme::Distance(m)))))
+    }).unwrap_or(ptr::null_mut())
+}
+
+/// Free a scheme, NULL is ignored
+#[no_mangle]
+pub unsafe extern "C" fn runa_scheme_free(scheme: *mut RunaScheme) {
+    if !scheme.is_null() { drop(Box::from_raw(scheme)); }
+}
+
+/// The distance (or score) of x and y with the algorithm, written to cost. 0 on success, -1 on an error
+#[no_mangle]
+pub unsafe extern "C" fn runa_dist(scheme: *const RunaScheme, x: *const u8, x_len: usize, y: *const u8, y_len: usize, algo: c_int, cost: *mut i64) -> c_int {
+    guard(|| {
+        let d = distance(scheme_of(scheme)?, algo, &sequence(x, x_len, "x")?, &sequence(y, y_len, "y")?)?;
+        *cost.as_mut().ok_or("the cost is NULL")? = d;
+        Ok(0)
+    }).unwrap_or(-1)
+}
+
+/// The optimal alignment of x and y with the algorithm, NULL on an error
+#[no_mangle]
+pub unsafe extern "C" fn runa_align(scheme: *const RunaScheme, x: *const u8, x_len: usize, y: *const u8, y_len: usize, algo: c_int) -> *mut RunaAlignment {
+    guard(|| {
+        let al = alignment(scheme_of(scheme)?, algo, &sequence(x, x_len, "x")?, &sequence(y, y_len, "y")?)?;
+        Ok(Box::into_raw(Box::new(al)))
+    }).unwrap_or(ptr::null_mut())
+}
+
+/// The cost (or score) of the alignment
+#[no_mangle]
+pub unsafe extern "C" fn runa_alignment_cost(al: *const RunaAlignment) -> i64 {
+    al.as_ref().map_or(0, |a| a.cost)
+}
+
+/// The CIGAR string of the alignment, with =, X, I and D, x being the reference
+#[no_mangle]
+pub unsafe extern "C" fn runa_alignment_cigar(al: *const RunaAlignment) -> *const c_char {
+    al.as_ref().map_or(ptr::null(), |a| a.cigar.as_ptr())
+}
+
+/// The row of x in the alignment, the gaps being '-'
+#[no_mangle]
+pub unsafe extern "C" fn runa_alignment_row_x(al: *const RunaAlignment) -> *const c_char {
+    al.as_ref().map_or(ptr::null(), |a| a.row_x.as_ptr())
+}
+
+/// The row of y in the alignment, the gaps being '-'
+#[no_mangle]
+pub unsafe extern "C" fn runa_alignment_row_y(al: *const RunaAlignment) -> *const c_char {
+    al.as_ref().map_or(ptr::null(), |a| a.row_y.as_ptr())
+}
+
+/// The aligned parts x[x_start..x_end] and y[y_start..y_end], the whole sequences for a global alignment.
+/// The NULL pointers are skipped
+#[no_mangle]
+pub unsafe extern "C" fn runa_alignment_range(al: *const RunaAlignment, x_start: *mut usize, x_end: *mut usize, y_start: *mut usize, y_end: *mut usize) {
+    let Some(a) = al.as_ref() else { return };
+    for (p, v) in [(x_start, a.x.start), (x_end, a.x.end), (y_start, a.y.start), (y_end, a.y.end)] {
+        if let Some(p) = p.as_mut() { *p = v; }
+    }
+}
+
+/// Free an alignment, NULL is ignored
+#[no_mangle]
+pub unsafe extern "C" fn runa_alignment_free(al: *mut RunaAlignment) {
+    if !al.is_null() { drop(Box::from_raw(al)); }
+}
+
+/// The C type of a Rust type of the API
+#[cfg(test)]
+fn c_type(t: &str) -> String {
+    if let Some(t) = t.strip_prefix("*const ") { return format!("const {} *", c_type(t)); }
+    if let Some(t) = t.strip_prefix("*mut ") { return format!("{} *", c_type(t)); }
+    match t {
+        "u8" => "uint8_t",
+        "usize" => "size_t",
+        "i64" => "int64_t",
+        "c_int" => "int",
+        "c_char" => "char",
+        t => t,
+    }.to_string()
+}
+
+/// The C header of the API, from the documented items of this file
+#[cfg(test)]
+fn header() -> String {
+    let typed = |t: String, name: &str| if t.ends_with('*') { format!("{}{}", t, name) } else { format!("{} {}", t, name) };
+    let mut items = vec![];
+    let mut doc = String::new();
+    for l in include_str!("ffi.rs").lines() {
+        if let Some(d) = l.strip_prefix("///") {
+            doc += &format!("//{}\n", d);
+            continue;
+        }
+        if l.starts_with("#[") { continue; }
+        if let Some(s) = l.strip_prefix("pub struct ") {
+            let name = &s[..s.find(|c: char| !c.is_alphanumeric()).unwrap_or(s.len())];
+            items.push(format!("{}typedef struct {} {};", doc, name, name));
+        } else if let Some(c) = l.strip_prefix("pub const ") {
+            let (name, value) = c.split_once(':').zip(c.split_once("= ")).map(|((n, _), (_, v))| (n, v.trim_end_matches(';'))).expect("a constant");
+            items.push(format!("{}#define {} {}", doc, name, value));
+        } else if let Some(f) = l.strip_prefix("pub extern \"C\" fn ").or_else(|| l.strip_prefix("pub unsafe extern \"C\" fn ")) {
+            let (name, rest) = f.split_once('(').expect("a function");
+            let (args, ret) = rest.split_once(')').expect("a function");
+            let args = args.split(", ").filter(|a| !a.is_empty()).map(|a| {
+                let (n, t) = a.split_once(": ").expect("an argument");
+                typed(c_type(t), n)
+            }).collect::<Vec<_>>();
+            let ret = ret.trim_start().strip_prefix("-> ").map_or("void".to_string(), |r| c_type(r.trim_end_matches(" {")));
+            let args = if args.is_empty() { "void".to_string() } else { args.join(", ") };
+            items.push(format!("{}{}({});", doc, typed(ret, name), args));
+        }
+        doc.clear();
+    }
+    format!("\
+// runa.h, the C API of RuNA. Generated from src/ffi.rs, do not edit: `RUNA_BLESS=1 cargo test ffi` rewrites it.
+// Link with -lruna, see src/ffi.rs for the rules of the API.
+
+#ifndef RUNA_H
+#define RUNA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern \"C\" {{
+#endif
+
+{}
+
+#ifdef __cplusplus
+}}
+#endif
+
+#endif
+", items.join("\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, c_char};
+    use std::ptr;
+    use super::*;
+
+    fn string(s: *const c_char) -> String {
+        unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn header_is_current(){
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/include/runa.h");
+        if std::env::var_os("RUNA_BLESS").is_some() {
+            std::fs::write(path, header()).unwrap();
+        }
+        assert_eq!(std::fs::read_to_string(path).unwrap_or_default(), header(), "include/runa.h is out of date, run RUNA_BLESS=1 cargo test ffi");
+    }
+
+    #[test]
+    fn api(){
+        unsafe {
+            let s = runa_scheme_new(c"course".as_ptr());
+            let (x, y) = (b"ATTGTA", b"ATCTTA");
+            let mut c = -1;
+            assert_eq!(runa_dist(s, x.as_ptr(), x.len(), y.as_ptr(), y.len(), RUNA_LINEAR, &mut c), 0);
+            assert_eq!(c, 4);
+            let al = runa_align(s, x.as_ptr(), x.len(), y.as_ptr(), y.len(), RUNA_FULL);
+            assert_eq!(runa_alignment_cost(al), c);
+            let rows = (string(runa_alignment_row_x(al)), string(runa_alignment_row_y(al)));
+            assert_eq!(rows.0.replace('-', ""), "ATTGTA");
+            assert_eq!(rows.1.replace('-', ""), "ATCTTA");
+            assert!(!string(runa_alignment_cigar(al)).is_empty());
+            runa_alignment_free(al);
+
+            // errors
+            assert!(runa_align(s, x.as_ptr(), x.len(), y.as_ptr(), y.len(), RUNA_LOCAL).is_null());
+            assert_eq!(string(runa_last_error()), "the local and overlap algorithms need a similarity scheme");
+            assert_eq!(runa_dist(s, b"AN".as_ptr(), 2, y.as_ptr(), y.len(), RUNA_LINEAR, &mut c), -1);
+            assert_eq!(string(runa_last_error()), "invalid letter 'N' at 1 of x");
+            assert_eq!(runa_dist(s, x.as_ptr(), x.len(), y.as_ptr(), y.len(), 7, &mut c), -1);
+            assert_eq!(string(runa_last_error()), "unknown algorithm 7");
+            assert!(runa_scheme_new(c"blosum".as_ptr()).is_null());
+            assert!(runa_scheme_matrix(c"nonsense".as_ptr()).is_null());
+            assert!(runa_align(ptr::null(), ptr::null(), 0, ptr::null(), 0, RUNA_LINEAR).is_null());
+            assert_eq!(string(runa_last_error()), "the scheme is NULL");
+            runa_scheme_free(s);
+
+            // a local alignment
+            let s = runa_scheme_similarity(2, -1, -2);
+            let (x, y) = (b"TTTTACGTACGTTTTT", b"GGGACGTACGGG");
+            let al = runa_align(s, x.as_ptr(), x.len(), y.as_ptr(), y.len(), RUNA_LOCAL);
+            let mut r = [0usize; 4];
+            runa_alignment_range(al, &mut r[0], &mut r[1], &mut r[2], &mut r[3]);
+            assert_eq!((runa_alignment_cost(al), r), (14, [4, 11, 3, 10]));
+            assert_eq!(string(runa_alignment_cigar(al)), "7=");
+            runa_alignment_free(al);
+            runa_scheme_free(s);
+            let s = runa_scheme_matrix(CString::new(SubstMatrix::unit().to_string()).unwrap().as_ptr());
+            assert!(!s.is_null());
+            assert_eq!(runa_dist(s, x.as_ptr(), x.len(), y.as_ptr(), y.len(), RUNA_LINEAR, &mut c), 0);
+            runa_scheme_free(s);
+        }
+        assert_eq!(string(runa_version()), env!("CARGO_PKG_VERSION"));
+    }
+}
@@ -0,0 +1,276 @@
+//! An FM-index over a dna text, to find the occurences of patterns in long texts.
+//!
+//! The index is built from the suffix array of the text (by prefix doubling): it holds the
+//! Burrows-Wheeler transform of the text with sampled occurence counts, and the suffix array itself
+//! to locate the occurences. Patterns are searched backwards, from their last letter to their first one,
+//! which keeps the interval of the suffixes starting with the part of the pattern read so far.
+//! Approximate occurences are found by backtracking over this search, every branch being a substitution,
+//! deletion or insertion priced by a [`MetricSpace`] and cut as soon as it costs more than allowed.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::dna::Dna;
+use crate::math::*;
+
+/// Every how many letters of the transform the occurence counts are stored
+const CHECKPOINT: usize = 64;
+
+/// The letters in lexicographic order
+const LETTERS: [Dna; 4] = [Dna::A, Dna::C, Dna::G, Dna::T];
+
+/// The rank of a letter, 0 being the end of the text
+fn rank(d: Dna) -> usize {
+    match d {
+        Dna::A => 1,
+        Dna::C => 2,
+        Dna::G => 3,
+        Dna::T => 4,
+    }
+}
+
+/// The suffix array of t, whose last symbol must be the only 0. O(n log^2 n) time
+fn suffix_array(t: &[usize]) -> Vec<usize> {
+    let n = t.len();
+    let mut sa = (0..n).collect::<Vec<_>>();
+    let mut ranks = t.to_vec();
+    let mut tmp = vec![0; n];
+    let mut k = 1;
+    loop {
+        // the suffixes are sorted by their first 2k letters
+        let key = |r: &[usize], i: usize| (r[i], if i + k < n { r[i + k] + 1 } else { 0 });
+        sa.sort_unstable_by_key(|&i| key(&ranks, i));
+        tmp[sa[0]] = 0;
+        for w in 1..n {
+            tmp[sa[w]] = tmp[sa[w - 1]] + (key(&ranks, sa[w - 1]) < key(&ranks, sa[w])) as usize;
+        }
+        std::mem::swap(&mut ranks, &mut tmp);
+        if ranks[sa[n - 1]] == n - 1 { return sa; }
+        k *= 2;
+    }
+}
+
+/// An approximate occurence of a pattern, the text[start..end] aligned with it for the given cost
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hit<C> {
+    pub start: usize,
+    pub end: usize,
+    pub cost: C,
+}
+
+/// The FM-index of a dna text
+pub struct FmIndex {
+    /// the Burrows-Wheeler transform of the text, as ranks
+    bwt: Vec<u8>,
+    /// the number of symbols of the text smaller than every rank
+    smaller: [usize; 5],
+    /// the occurences of every rank in bwt[..CHECKPOINT * k]
+    checkpoints: Vec<[usize; 5]>,
+    sa: Vec<usize>,
+}
+
+impl FmIndex {
+    /// Index the text. O(n log^2 n) time and O(n) memory
+    pub fn new(text: &[Dna]) -> Self {
+        let mut t = text.iter().map(|&d| rank(d)).collect::<Vec<_>>();
+        t.push(0);
+        let sa = suffix_array(&t);
+        let bwt = sa
+            .iter()
+            .map(|&i| t[if i == 0 { t.len() - 1 } else { i - 1 }] as u8)
+            .collect::<Vec<_>>();
+
+        let mut counts = [0; 5];
+        let mut checkpoints = vec![];
+        for (i, &c) in bwt.iter().enumerate() {
+            if i % CHECKPOINT == 0 { checkpoints.push(counts); }
+            counts[c as usize] += 1;
+        }
+        checkpoints.push(counts);
+        let mut smaller = [0; 5];
+        for c in 1..5 {
+            smaller[c] = smaller[c - 1] + counts[c - 1];
+        }
+        FmIndex { bwt, smaller, checkpoints, sa }
+    }
+
+    /// The length of the text
+    pub fn len(&self) -> usize {
+        self.sa.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of occurences of the rank c in bwt[..i]
+    fn occ(&self, c: usize, i: usize) -> usize {
+        let k = i / CHECKPOINT;
+        let from = k * CHECKPOINT;
+        self.checkpoints[k][c] + self.bwt[from..i].iter().filter(|&&b| b as usize == c).count()
+    }
+
+    /// The interval of the suffixes starting with d followed by the suffixes of the interval r
+    fn extend(&self, d: Dna, r: &Range<usize>) -> Range<usize> {
+        let c = rank(d);
+        self.smaller[c] + self.occ(c, r.start)..self.smaller[c] + self.occ(c, r.end)
+    }
+
+    /// The interval of the suffix array holding the suffixes starting with the pattern (backward search).
+    /// O(m) time
+    pub fn range(&self, pattern: &[Dna]) -> Range<usize> {
+        let mut r = 0..self.sa.len();
+        for &d in pattern.iter().rev() {
+            if r.is_empty() { break; }
+            r = self.extend(d, &r);
+        }
+        r
+    }
+
+    /// The number of occurences of the pattern in the text. O(m) time
+    pub fn count(&self, pattern: &[Dna]) -> usize {
+        self.range(pattern).len()
+    }
+
+    /// The positions of the occurences of the pattern in the text, sorted. O(m + occ log occ) time
+    pub fn locate(&self, pattern: &[Dna]) -> Vec<usize> {
+        let mut res = self.sa[self.range(pattern)].to_vec();
+        res.sort_unstable();
+        res
+    }
+
+    /// The approximate occurences of the pattern, the parts of the text which can be aligned with it
+    /// for a cost of at most k. The pattern is the first sequence of the alignments,
+    /// so skipping a letter of the pattern costs a deletion and skipping a letter of the text an insertion.
+    /// Only the cheapest hit starting at every position of the text is kept, the hits are sorted by start.
+    /// The alignments never start or end with an insertion (those would be other hits).
+    /// The time grows exponentially with k, as every branch of the search is explored.
+    ///
+    /// # Panics
+    /// If insertions are free, the search would then extend the hits forever
+    pub fn search_approx<M>(&self, ms: &M, pattern: &[Dna], k: M::Cost) -> Vec<Hit<M::Cost>>
+    where M: MetricSpace<Item = Dna, Goal = Minimise>
+    {
+        assert!(ms.ins() > M::ZEROCOST, "insertions must cost something!");
+        let mut best: HashMap<usize, Hit<M::Cost>> = HashMap::new();
+        let mut stack = vec![(0..self.sa.len(), pattern.len(), 0, M::ZEROCOST)];
+        while let Some((r, i, len, cost)) = stack.pop() {
+            if r.is_empty() || cost > k { continue; }
+            if i == 0 {
+                for &start in &self.sa[r] {
+                    let hit = Hit { start, end: start + len, cost };
+                    let b = best.entry(start).or_insert_with(|| hit.clone());
+                    if (hit.cost, hit.end) < (b.cost, b.end) { *b = hit; }
+                }
+                continue;
+            }
+            // the pattern letter is deleted
+            stack.push((r.clone(), i - 1, len, cost + ms.del()));
+            for d in LETTERS {
+                let e = self.extend(d, &r);
+                if e.is_empty() { continue; }
+                // the text letter is inserted, except after the end of the pattern
+                if i < pattern.len() {
+                    stack.push((e.clone(), i, len + 1, cost + ms.ins()));
+                }
+                stack.push((e, i - 1, len + 1, cost + ms.sub(pattern[i - 1], d)));
+            }
+        }
+        let mut res = best.into_values().collect::<Vec<_>>();
+        res.sort_by_key(|h| h.start);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{Dna, DnaMetricSpace as Dms};
+    use crate::math::dist_1;
+    use crate::rng::Rng;
+    use super::{FmIndex, rank, suffix_array};
+
+    fn random(n: usize, rng: &mut Rng) -> Vec<Dna> {
+        let letters = [Dna::A, Dna::C, Dna::G, Dna::T];
+        (0..n).map(|_| letters[rng.below(4) as usize]).collect()
+    }
+
+    #[test]
+    fn suffixes(){
+        let mut rng = Rng::new(37);
+        for n in [0, 1, 2, 10, 100, 1000] {
+            let mut t = random(n, &mut rng).into_iter().map(rank).collect::<Vec<_>>();
+            t.push(0);
+            let mut naive = (0..t.len()).collect::<Vec<_>>();
+            naive.sort_by_key(|&i| &t[i..]);
+            assert_eq!(suffix_array(&t), naive);
+        }
+        // a very repetitive text needs all the doubling steps
+        let mut t = vec![1; 300];
+        t.push(0);
+        assert_eq!(suffix_array(&t), (0..301).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn exact(){
+        let mut rng = Rng::new(1);
+        let text = random(5000, &mut rng);
+        let index = FmIndex::new(&text);
+        assert_eq!(index.len(), 5000);
+        for m in [0, 1, 3, 6, 10, 50] {
+            for _ in 0..20 {
+                let pattern = if m > 0 && rng.below(2) == 0 {
+                    let s = rng.below((text.len() - m) as u64) as usize;
+                    text[s..s + m].to_vec()
+                } else {
+                    random(m, &mut rng)
+                };
+                let naive = (0..=text.len() - m).filter(|&s| text[s..s + m] == pattern[..]).collect::<Vec<_>>();
+                assert_eq!(index.count(&pattern), naive.len());
+                assert_eq!(index.locate(&pattern), naive);
+            }
+        }
+        assert!(FmIndex::new(&[]).is_empty());
+    }
+
+    #[test]
+    fn approximate(){
+        let mut rng = Rng::new(2);
+        let text = random(400, &mut rng);
+        let index = FmIndex::new(&text);
+        for _ in 0..10 {
+            let s = rng.below(380) as usize;
+            let mut pattern = text[s..s + 12].to_vec();
+            pattern[5] = if pattern[5] == Dna::A { Dna::G } else { Dna::A };
+            pattern.remove(8);
+            let k = 6;
+
+            let hits = index.search_approx(&Dms, &pattern, k);
+            assert!(hits.iter().any(|h| h.start == s && h.cost <= 6), "the planted occurence was missed");
+            for h in &hits {
+                assert!(h.cost <= k);
+                assert!(dist_1(&Dms, &pattern, &text[h.start..h.end]) <= h.cost, "the hit is cheaper than possible");
+            }
+            // every part of the text close enough to the pattern is found, maybe without its first letters
+            for a in 0..text.len() {
+                for b in a..(a + pattern.len() + 4).min(text.len()) {
+                    let d = dist_1(&Dms, &pattern, &text[a..b]);
+                    if d <= k {
+                        assert!(hits.iter().any(|h| (a..=b).contains(&h.start) && h.cost <= d), "{}..{} was missed", a, b);
+                    }
+                }
+            }
+        }
+        let pattern = text[100..120].to_vec();
+        let exact = index.search_approx(&Dms, &pattern, 0).into_iter().map(|h| h.start).collect::<Vec<_>>();
+        assert_eq!(exact, index.locate(&pattern));
+    }
+
+    #[test]
+    #[should_panic(expected = "insertions must cost something!")]
+    fn approximate_free_insertions(){
+        let free = "A C G T\nA 0 1 1 1\nC 1 0 1 1\nG 1 1 0 1\nT 1 1 1 0\ndel 1\nins 0"
+            .parse::<crate::matrix::SubstMatrix>().expect("cannot parse matrix!");
+        let text = random(50, &mut Rng::new(3));
+        FmIndex::new(&text).search_approx(&free, &text[10..20], 2);
+    }
+}
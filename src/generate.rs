@@ -0,0 +1,129 @@
+//! Random instances, to grow the test corpus beyond the files of tests/genome_instances_data.
+//!
+//! An instance is a base sequence x and a copy y of it mutated letter by letter: every letter is substituted
+//! by another one with the substitution rate, and an insertion or a deletion (one of the two equally likely)
+//! happens before it with the indel rate. Everything is drawn from a seeded [`Rng`], so that the same seed always
+//! gives the same instance. The instances are written as the test files are, `Inst_NNNNNNN_K.adn` with the
+//! length of x on seven digits and the seed K.
+
+use std::io::{self, BufWriter};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::dna::{Dna, DnaBlock};
+use crate::io::write_adn;
+use crate::rng::Rng;
+
+const LETTERS: [Dna; 4] = [Dna::A, Dna::C, Dna::G, Dna::T];
+
+/// The probabilities of the mutations of every letter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MutationRates {
+    pub substitution: f64,
+    pub indel: f64,
+}
+
+impl Default for MutationRates {
+    fn default() -> Self {
+        MutationRates { substitution: 0.1, indel: 0.05 }
+    }
+}
+
+/// A uniformly random sequence of length n
+pub fn random_sequence(n: usize, rng: &mut Rng) -> Vec<Dna> {
+    (0..n).map(|_| LETTERS[rng.below(4) as usize]).collect()
+}
+
+/// A mutated copy of the base sequence
+pub fn mutate(base: &[Dna], rates: MutationRates, rng: &mut Rng) -> Vec<Dna> {
+    let mut res = Vec::with_capacity(base.len());
+    let mut deleted = false;
+    for &d in base {
+        if rng.f64() < rates.indel {
+            if rng.below(2) == 0 {
+                res.push(LETTERS[rng.below(4) as usize]);
+            } else {
+                deleted = true;
+            }
+        }
+        if std::mem::take(&mut deleted) { continue; }
+        if rng.f64() < rates.substitution {
+            // one of the three other letters
+            let k = LETTERS.iter().position(|&l| l == d).expect("a letter");
+            res.push(LETTERS[(k + 1 + rng.below(3) as usize) % 4]);
+        } else {
+            res.push(d);
+        }
+    }
+    res
+}
+
+/// The instance of the given seed: the base sequence, or a random one of length n if there is none, and its mutated copy
+pub fn generate_instance(base: Option<&[Dna]>, n: usize, rates: MutationRates, seed: u64) -> DnaBlock {
+    let mut rng = Rng::new(seed);
+    let x = match base {
+        Some(b) => b.to_vec(),
+        None => random_sequence(n, &mut rng),
+    };
+    let y = mutate(&x, rates, &mut rng);
+    DnaBlock(x, y)
+}
+
+/// The name of the file of an instance, `Inst_NNNNNNN_K.adn`
+pub fn instance_file_name(n: usize, k: u64) -> String {
+    format!("Inst_{:07}_{}.adn", n, k)
+}
+
+/// Write the instance in the directory, named by the length of x and the seed k, its path
+pub fn write_instance(dir: &Path, block: &DnaBlock, k: u64) -> io::Result<PathBuf> {
+    let path = dir.join(instance_file_name(block.0.len(), k));
+    let mut w = BufWriter::new(File::create(&path)?);
+    write_adn(&mut w, block)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{DnaBlock, DnaMetricSpace as Dms};
+    use crate::io::read_test_inst;
+    use crate::math::{Gapped, sol_2};
+    use crate::rng::Rng;
+    use super::{MutationRates, generate_instance, instance_file_name, mutate, random_sequence, write_instance};
+
+    #[test]
+    fn rates(){
+        let mut rng = Rng::new(46);
+        let base = random_sequence(20000, &mut rng);
+        assert_eq!(mutate(&base, MutationRates { substitution: 0., indel: 0. }, &mut rng), base);
+
+        let y = mutate(&base, MutationRates { substitution: 0.1, indel: 0. }, &mut rng);
+        let changed = base.iter().zip(&y).filter(|(a, b)| a != b).count() as f64 / base.len() as f64;
+        assert!((changed - 0.1).abs() < 0.01, "{} of the letters changed", changed);
+
+        // about as many insertions as deletions, and gaps in the alignment for about 5% of the letters
+        let y = mutate(&base, MutationRates { substitution: 0., indel: 0.05 }, &mut rng);
+        assert!((y.len() as f64 - base.len() as f64).abs() < 300., "{} letters", y.len());
+        let x = &base[..500];
+        let y = mutate(x, MutationRates { substitution: 0., indel: 0.05 }, &mut rng);
+        let al = sol_2(&Dms, x, &y);
+        let gaps = al.0.iter().chain(&al.1).filter(|g| matches!(g, Gapped::Gap)).count() as f64 / x.len() as f64;
+        assert!((0.02..0.08).contains(&gaps), "{} gaps per letter", gaps);
+    }
+
+    #[test]
+    fn instances(){
+        let rates = MutationRates::default();
+        assert_eq!(generate_instance(None, 500, rates, 7), generate_instance(None, 500, rates, 7));
+        assert_ne!(generate_instance(None, 500, rates, 7), generate_instance(None, 500, rates, 8));
+        let base = read_test_inst("Inst_0000010_44.adn").unwrap().0;
+        assert_eq!(generate_instance(Some(&base), 0, rates, 1).0, base);
+
+        assert_eq!(instance_file_name(10, 44), "Inst_0000010_44.adn");
+        let dir = std::env::temp_dir().join("runa_generate");
+        std::fs::create_dir_all(&dir).unwrap();
+        let b = generate_instance(None, 1234, rates, 3);
+        let path = write_instance(&dir, &b, 3).unwrap();
+        assert_eq!(path, dir.join("Inst_0001234_3.adn"));
+        assert_eq!(std::fs::read_to_string(path).unwrap().parse::<DnaBlock>().unwrap(), b);
+    }
+}
@@ -1,8 +1,11 @@
 //! Input/Output library of the crate.
 
-use crate::dna::DnaBlock;
+use crate::dna::{Dna, DnaBlock};
+use crate::matrix::SubstMatrix;
 use std::error::Error;
-use std::fs::read_to_string;
+use std::fmt::Display;
+use std::fs::{File, read_to_string};
+use std::io::{self, BufRead, Read, Write};
 use std::env;
 
 
@@ -11,22 +14,356 @@ pub fn read_test_inst_of_size(size: usize) -> Result<DnaBlock, Box<dyn Error>>
 {
     let secsizes = [7, 8, 13, 45, 32, 56, 89, 76, 77, 3, 20, 6];
     let gdata = env::var("GENOME_DATA")?;
-    secsizes
+    let f = secsizes
         .into_iter()
         .map(|size2| format!("{}/Inst_{:07}_{}.adn", gdata, size, size2))
-        .map(read_to_string) // try opening the file
+        .map(File::open) // try opening the file
         .find_map(|x| x.ok()) // open first existing file
-        .ok_or("couldn't read file")? 
-        .parse::<DnaBlock>()
+        .ok_or("couldn't read file")?;
+    AdnReader::new(f)?.read_block()
 }
 
 /// read test instance by filename
 pub fn read_test_inst(filename: &str) -> Result<DnaBlock, Box<dyn Error>>
 {
     let gdata = env::var("GENOME_DATA")?;
-    let f = gdata + "/" + filename;
-    let f = read_to_string(f)?;
-    f.parse::<DnaBlock>()
+    let f = File::open(gdata + "/" + filename)?;
+    AdnReader::new(f)?.read_block()
+}
+
+/// read a substitution matrix from a file, see [`crate::matrix`] for the format
+pub fn read_subst_matrix(path: &str) -> Result<SubstMatrix, Box<dyn Error>>
+{
+    read_to_string(path)?.parse::<SubstMatrix>()
+}
+
+/// A named sequence of a FASTA file
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FastaRecord {
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::seq"))]
+    pub seq: Vec<Dna>,
+}
+
+/// parse the records of a FASTA file. The name of a record is its header line without the '>',
+/// the letters of the sequence may be split over several lines and be lower case
+pub fn parse_fasta(s: &str) -> Result<Vec<FastaRecord>, Box<dyn Error>>
+{
+    read_fasta(s.as_bytes())
+}
+
+/// write an instance in the .adn format, see [`DnaBlock`]
+pub fn write_adn<W: Write>(w: &mut W, block: &DnaBlock) -> io::Result<()>
+{
+    write!(w, "{}", block)
+}
+
+/// The formats of sequence files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqFormat {
+    /// the two sequences of an instance, see [`DnaBlock`]
+    Adn,
+    Fasta,
+}
+
+/// write a FASTA record, the items (letters or gapped letters) are wrapped every 60 of them
+pub fn write_fasta<W: Write, T: Display>(w: &mut W, name: &str, items: &[T]) -> std::io::Result<()>
+{
+    writeln!(w, ">{}", name)?;
+    for line in items.chunks(60) {
+        writeln!(w, "{}", line.iter().map(|x| x.to_string()).collect::<String>())?;
+    }
+    Ok(())
+}
+
+/// The size of the chunks read by the streaming readers
+const CHUNK: usize = 1 << 16;
+
+/// A reader giving its bytes one at a time from a buffer of one chunk, counting the lines
+struct Chunks<R: Read> {
+    r: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    len: usize,
+    line: usize,
+}
+
+impl<R: Read> Chunks<R> {
+    fn new(r: R) -> Self {
+        Chunks { r, buf: vec![0; CHUNK].into_boxed_slice(), pos: 0, len: 0, line: 1 }
+    }
+
+    /// The next byte, without consuming it
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        while self.pos == self.len {
+            match self.r.read(&mut self.buf) {
+                Ok(0) => return Ok(None),
+                Ok(len) => (self.pos, self.len) = (0, len),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Some(self.buf[self.pos]))
+    }
+
+    fn bump(&mut self) -> io::Result<Option<u8>> {
+        let b = self.peek()?;
+        if let Some(b) = b {
+            self.pos += 1;
+            self.line += (b == b'\n') as usize;
+        }
+        Ok(b)
+    }
+
+    /// Skip the whitespace, the next byte
+    fn skip_whitespace(&mut self) -> io::Result<Option<u8>> {
+        while let Some(b) = self.peek()? {
+            if !b.is_ascii_whitespace() { return Ok(Some(b)); }
+            self.bump()?;
+        }
+        Ok(None)
+    }
+}
+
+/// The letter of a byte
+pub(crate) fn letter(b: u8) -> Option<Dna> {
+    match b {
+        b'A' | b'a' => Some(Dna::A),
+        b'C' | b'c' => Some(Dna::C),
+        b'G' | b'g' => Some(Dna::G),
+        b'T' | b't' => Some(Dna::T),
+        _ => None,
+    }
+}
+
+/// A byte as it should appear in an error message
+pub(crate) fn shown(b: u8) -> String {
+    if b.is_ascii_graphic() { (b as char).to_string() } else { format!("\\x{:02x}", b) }
+}
+
+/// Streaming reader of an .adn file (see [`DnaBlock`]), the letters of x then of y are read one at a time
+/// from a buffer of one chunk. `read_x` and then iterating over the reader gives x in memory and y as a stream,
+/// for [`crate::math::dist_2_stream`]; the iterator fails at the end if the file holds more letters than announced
+pub struct AdnReader<R: Read> {
+    chunks: Chunks<R>,
+    n: usize,
+    m: usize,
+    /// the letters read so far
+    read: usize,
+}
+
+impl<R: Read> AdnReader<R> {
+    /// Read the lengths of the sequences
+    pub fn new(r: R) -> Result<Self, Box<dyn Error>> {
+        let mut chunks = Chunks::new(r);
+        let mut number = |what: &str| -> Result<usize, Box<dyn Error>> {
+            chunks.skip_whitespace()?;
+            let mut s = String::new();
+            while let Some(b) = chunks.peek()? {
+                if b.is_ascii_whitespace() { break; }
+                s.push(b as char);
+                chunks.bump()?;
+            }
+            s.parse::<usize>().map_err(|_| format!("adn error: couldn't read {} line {}", what, chunks.line).into())
+        };
+        let n = number("n")?;
+        let m = number("m")?;
+        Ok(AdnReader { chunks, n, m, read: 0 })
+    }
+
+    /// The lengths of x and y
+    pub fn lens(&self) -> (usize, usize) {
+        (self.n, self.m)
+    }
+
+    /// Read the whole sequence x, nothing must have been read before
+    pub fn read_x(&mut self) -> Result<Vec<Dna>, Box<dyn Error>> {
+        assert_eq!(self.read, 0, "some letters were already read!");
+        let mut x = Vec::with_capacity(self.n);
+        for _ in 0..self.n {
+            x.push(self.next().expect("x is not over")?);
+        }
+        Ok(x)
+    }
+
+    /// Read both sequences
+    pub fn read_block(mut self) -> Result<DnaBlock, Box<dyn Error>> {
+        let x = self.read_x()?;
+        let mut y = Vec::with_capacity(self.m);
+        for d in self {
+            y.push(d?);
+        }
+        Ok(DnaBlock(x, y))
+    }
+
+    fn letter(&mut self) -> Result<Option<Dna>, Box<dyn Error>> {
+        let total = self.n + self.m;
+        let b = self.chunks.skip_whitespace()?;
+        if self.read == total {
+            return match b {
+                None => Ok(None),
+                Some(_) => Err(format!("adn error: more letters than the lengths {} and {} line {}", self.n, self.m, self.chunks.line).into()),
+            };
+        }
+        let Some(b) = b else {
+            return Err(format!("adn error: {} letters are missing at the end", total - self.read).into());
+        };
+        self.chunks.bump()?;
+        let Some(d) = letter(b).filter(|_| self.chunks.peek().is_ok_and(|n| n.is_none_or(|n| n.is_ascii_whitespace()))) else {
+            return Err(format!("adn error: invalid letter '{}' line {}", shown(b), self.chunks.line).into());
+        };
+        self.read += 1;
+        Ok(Some(d))
+    }
+}
+
+impl<R: Read> Iterator for AdnReader<R> {
+    type Item = Result<Dna, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.letter().transpose()
+    }
+}
+
+/// read the records of a FASTA file chunk by chunk, see [`parse_fasta`]
+pub fn read_fasta<R: Read>(r: R) -> Result<Vec<FastaRecord>, Box<dyn Error>>
+{
+    let mut chunks = Chunks::new(r);
+    let mut res: Vec<FastaRecord> = vec![];
+    let mut line_start = true;
+    while let Some(b) = chunks.bump()? {
+        if line_start && b == b'>' {
+            let mut name = vec![];
+            while let Some(b) = chunks.bump()? {
+                if b == b'\n' { break; }
+                name.push(b);
+            }
+            let name = String::from_utf8(name).map_err(|_| format!("fasta error: the header line {} is not utf-8", chunks.line - 1))?;
+            res.push(FastaRecord { name: name.trim().to_string(), seq: vec![] });
+            continue;
+        }
+        line_start = b == b'\n';
+        if b.is_ascii_whitespace() { continue; }
+        let line = chunks.line;
+        let Some(r) = res.last_mut() else {
+            return Err(format!("fasta error: line {} comes before the first header", line).into());
+        };
+        r.seq.push(letter(b).ok_or_else(|| format!("fasta error: invalid letter '{}' line {}", shown(b), line))?);
+    }
+    Ok(res)
+}
+
+/// The first bytes of a gzip file
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The reader decompressed if its content starts as a gzip file does, whatever its name, or else the reader itself.
+/// Concatenated gzip files (as written by bgzip) are read as one. Reading compressed inputs needs the gzip feature
+pub fn decompress<'a, R: Read + 'a>(mut r: R) -> io::Result<Box<dyn Read + 'a>>
+{
+    let mut magic = [0; 2];
+    let mut n = 0;
+    while n < magic.len() {
+        match r.read(&mut magic[n..]) {
+            Ok(0) => break,
+            Ok(k) => n += k,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    // give back the bytes read
+    let r = io::Cursor::new(magic[..n].to_vec()).chain(r);
+    if magic[..n] != GZIP_MAGIC {
+        return Ok(Box::new(r));
+    }
+    #[cfg(feature = "gzip")]
+    return Ok(Box::new(flate2::read::MultiGzDecoder::new(r)));
+    #[cfg(not(feature = "gzip"))]
+    return Err(io::Error::other("the input is gzip compressed, runa must be built with the gzip feature to read it"));
+}
+
+/// A writer compressing what it writes with gzip, or not
+pub enum Output<W: Write> {
+    Plain(W),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<W>),
+}
+
+impl<W: Write> Output<W> {
+    /// A writer to w, compressing if gzip is true, which needs the gzip feature
+    pub fn new(w: W, gzip: bool) -> io::Result<Self> {
+        if !gzip {
+            return Ok(Output::Plain(w));
+        }
+        #[cfg(feature = "gzip")]
+        return Ok(Output::Gzip(flate2::write::GzEncoder::new(w, flate2::Compression::default())));
+        #[cfg(not(feature = "gzip"))]
+        return Err(io::Error::other("runa must be built with the gzip feature to compress its output"));
+    }
+
+    /// Write the end of the compressed stream and flush, the underlying writer
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Output::Plain(mut w) => w.flush().map(|_| w),
+            #[cfg(feature = "gzip")]
+            Output::Gzip(g) => g.finish().and_then(|mut w| w.flush().map(|_| w)),
+        }
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(w) => w.write(buf),
+            #[cfg(feature = "gzip")]
+            Output::Gzip(g) => g.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(w) => w.flush(),
+            #[cfg(feature = "gzip")]
+            Output::Gzip(g) => g.flush(),
+        }
+    }
+}
+
+/// read the records of a sequence file chunk by chunk, guessing its format when none is given.
+/// The file may be gzip compressed (see [`decompress`]). The sequences of an .adn file are named x and y
+pub fn read_sequences<R: Read>(r: R, format: Option<SeqFormat>) -> Result<Vec<FastaRecord>, Box<dyn Error>>
+{
+    let mut r = io::BufReader::with_capacity(CHUNK, decompress(r)?);
+    let format = match format {
+        Some(f) => f,
+        None => sniff(&mut r)?,
+    };
+    read_decoded(r, format)
+}
+
+/// read the records of a sequence file which is already decompressed, in the given format, see [`read_sequences`]
+pub fn read_decoded<R: Read>(r: R, format: SeqFormat) -> Result<Vec<FastaRecord>, Box<dyn Error>>
+{
+    match format {
+        SeqFormat::Fasta => read_fasta(r),
+        SeqFormat::Adn => {
+            let b = AdnReader::new(r)?.read_block()?;
+            Ok(vec![FastaRecord { name: "x".to_string(), seq: b.0 }, FastaRecord { name: "y".to_string(), seq: b.1 }])
+        }
+    }
+}
+
+/// Guess the format of a file from its first letter, consuming the whitespace before it
+pub fn sniff<R: BufRead>(r: &mut R) -> io::Result<SeqFormat>
+{
+    loop {
+        let buf = r.fill_buf()?;
+        let Some(&b) = buf.first() else { return Ok(SeqFormat::Adn) };
+        if !b.is_ascii_whitespace() {
+            return Ok(if b == b'>' { SeqFormat::Fasta } else { SeqFormat::Adn });
+        }
+        r.consume(1);
+    }
 }
 
 /// read all test instances lazily, indeed it will load the instances in memory on demand
@@ -125,7 +462,21 @@ pub fn read_test_insts_by_size<'a>() -> impl Iterator<Item = (usize, DnaBlock)>
 #[cfg(test)]
 mod tests{
     use crate::dna::Dna::*;
-    use super::{DnaBlock, read_test_inst};
+    use crate::matrix::SubstMatrix;
+    use crate::math::Gapped;
+    use super::{AdnReader, DnaBlock, FastaRecord, SeqFormat, parse_fasta, read_fasta, read_sequences, read_test_inst, read_subst_matrix, sniff, write_fasta};
+
+    /// A reader giving a few bytes at a time, to cross the ends of the chunks everywhere
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let k = self.1.min(buf.len()).min(self.0.len());
+            buf[..k].copy_from_slice(&self.0[..k]);
+            self.0 = &self.0[k..];
+            Ok(k)
+        }
+    }
 
     #[test]
     fn read_double_dna_block(){
@@ -136,4 +487,113 @@ mod tests{
                 assert_eq!(x, DnaBlock(vec![T, A, T, A, T, G, A ,G ,T, C], vec![T, A, T, T, T]), "the reader is not correct!")
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn read_matrix_file(){
+        let f = std::env::temp_dir().join("runa_read_matrix_file.mat");
+        std::fs::write(&f, SubstMatrix::kimura().to_string()).expect("cannot write matrix!");
+        let m = read_subst_matrix(f.to_str().unwrap()).expect("cannot read matrix!");
+        assert_eq!(m, SubstMatrix::kimura());
+        assert!(read_subst_matrix("this/file/does/not.exist").is_err());
+    }
+
+    #[test]
+    fn fasta(){
+        let f = parse_fasta(">first one\nACGT\nac\n\n>second\nT T\n>empty\n").expect("cannot parse fasta!");
+        assert_eq!(f, vec![
+            FastaRecord { name: "first one".to_string(), seq: vec![A, C, G, T, A, C] },
+            FastaRecord { name: "second".to_string(), seq: vec![T, T] },
+            FastaRecord { name: "empty".to_string(), seq: vec![] },
+        ]);
+        assert!(parse_fasta("ACGT\n>x\nA").is_err(), "letters before the header");
+        assert!(parse_fasta(">x\nACNT").is_err(), "N is not a dna letter");
+
+        let mut out = vec![];
+        let long = vec![A; 70];
+        write_fasta(&mut out, "long", &long).unwrap();
+        write_fasta(&mut out, "gapped", &[Gapped::Item(C), Gapped::Gap]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out, format!(">long\n{}\n{}\n>gapped\nC-\n", "A".repeat(60), "A".repeat(10)));
+        assert_eq!(parse_fasta(&out.replace('-', "")).unwrap()[0].seq, long);
+    }
+
+    #[test]
+    fn streaming(){
+        // the chunked reader agrees with the parser of whole files
+        let gdata = std::env::var("GENOME_DATA").unwrap();
+        for e in std::fs::read_dir(gdata).unwrap() {
+            let path = e.unwrap().path();
+            let size = path.file_name().unwrap().to_str().unwrap().split('_').nth(1).and_then(|n| n.parse::<usize>().ok());
+            if size.is_none_or(|size| size > 5000) { continue; }
+            let s = std::fs::read_to_string(&path).unwrap();
+            let b = s.parse::<DnaBlock>().unwrap();
+            let mut r = AdnReader::new(Trickle(s.as_bytes(), 7)).unwrap();
+            assert_eq!(r.lens(), (b.0.len(), b.1.len()));
+            assert_eq!(r.read_x().unwrap(), b.0);
+            assert_eq!(r.collect::<Result<Vec<_>, _>>().unwrap(), b.1);
+        }
+
+        let block = |s: &str| AdnReader::new(Trickle(s.as_bytes(), 3)).and_then(|r| r.read_block());
+        assert_eq!(block("2\n1\nA c\n\tG\n").unwrap(), DnaBlock(vec![A, C], vec![G]));
+        assert_eq!(block("2 0 AC").unwrap_err().to_string(), "adn error: invalid letter 'A' line 1");
+        assert_eq!(block("2\n1\nA C\nG T\n").unwrap_err().to_string(), "adn error: more letters than the lengths 2 and 1 line 4");
+        assert_eq!(block("2\n2\nA C\nG").unwrap_err().to_string(), "adn error: 1 letters are missing at the end");
+        assert_eq!(block("2\nm\n").unwrap_err().to_string(), "adn error: couldn't read m line 2");
+        assert!(block("1\n1\nN\nA").is_err());
+
+        let f = read_fasta(Trickle(b">a b\r\nAC\r\ngt\n>c\n\n>d\nT", 2)).unwrap();
+        assert_eq!(f, vec![
+            FastaRecord { name: "a b".to_string(), seq: vec![A, C, G, T] },
+            FastaRecord { name: "c".to_string(), seq: vec![] },
+            FastaRecord { name: "d".to_string(), seq: vec![T] },
+        ]);
+        assert_eq!(parse_fasta(">x\nAC\nA>C").unwrap_err().to_string(), "fasta error: invalid letter '>' line 3");
+        assert_eq!(parse_fasta("\n\nA").unwrap_err().to_string(), "fasta error: line 3 comes before the first header");
+
+        for (s, format) in [("  \n>x\nA", SeqFormat::Fasta), ("\n1\n0\nA\n\n", SeqFormat::Adn), ("", SeqFormat::Adn)] {
+            let mut r = std::io::BufReader::with_capacity(1, s.as_bytes());
+            assert_eq!(sniff(&mut r).unwrap(), format);
+        }
+        assert_eq!(read_sequences(Trickle(b"\n\n>x\nAC", 1), None).unwrap()[0].seq, vec![A, C]);
+        assert_eq!(read_sequences("1\n0\nA\n\n".as_bytes(), None).unwrap()[1].name, "y");
+    }
+
+    #[test]
+    fn gzip(){
+        use std::io::Read;
+        use super::{Output, decompress};
+        for s in ["", ">", ">x\nACGT\n"] {
+            let mut back = String::new();
+            decompress(Trickle(s.as_bytes(), 1)).unwrap().read_to_string(&mut back).unwrap();
+            assert_eq!(back, s, "plain inputs are read as they are");
+        }
+
+        #[cfg(not(feature = "gzip"))]
+        {
+            assert!(decompress(&[0x1f, 0x8b, 8, 0][..]).is_err());
+            assert!(Output::new(vec![], true).is_err());
+        }
+
+        #[cfg(feature = "gzip")]
+        {
+            use std::io::Write;
+            let gz = |s: &str| {
+                let mut w = Output::new(vec![], true).unwrap();
+                w.write_all(s.as_bytes()).unwrap();
+                w.finish().unwrap()
+            };
+            let mut both = gz(">a\nAC\n");
+            assert_eq!(&both[..2], &[0x1f, 0x8b]);
+            both.extend(gz(">b\nGT\n"));
+            // concatenated members are one file, read a few bytes at a time
+            let f = read_sequences(Trickle(&both, 5), None).unwrap();
+            assert_eq!(f, vec![
+                FastaRecord { name: "a".to_string(), seq: vec![A, C] },
+                FastaRecord { name: "b".to_string(), seq: vec![G, T] },
+            ]);
+            let big = format!("{}\n{}\n{} \n{} \n", 20000, 10000, "A C ".repeat(10000), "T ".repeat(10000));
+            let b = read_sequences(&gz(&big)[..], Some(SeqFormat::Adn)).unwrap();
+            assert_eq!((b[0].seq.len(), b[1].seq.len()), (20000, 10000));
+        }
+    }
+}
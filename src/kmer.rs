@@ -0,0 +1,262 @@
+//! A minimizer index over a reference sequence, to align reads against long references
+//! without filling the whole dp table (seed, chain and extend).
+//!
+//! - seed: the minimizers of the read (the smallest k-mers of every window of w consecutive k-mers)
+//!   are looked up in the index, every hit is an anchor, an exact match of k letters;
+//! - chain: the best colinear chain of anchors is found by dynamic programming;
+//! - extend: the gaps between consecutive anchors (and the ends of the read) are aligned with sol_2.
+//!
+//! Only the forward strand of the reference is indexed.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::dna::Dna;
+use crate::math::*;
+
+/// How many previous anchors are tried as predecessors of an anchor when chaining
+const LOOKBACK: usize = 50;
+
+/// The 2 bits code of a letter
+fn code(d: Dna) -> u64 {
+    match d {
+        Dna::A => 0,
+        Dna::C => 1,
+        Dna::G => 2,
+        Dna::T => 3,
+    }
+}
+
+/// An invertible mix of the bits of a k-mer, so that the minimizers are not biased towards poly-A
+fn hash(key: u64, mask: u64) -> u64 {
+    let mut key = (!key).wrapping_add(key << 21) & mask;
+    key ^= key >> 24;
+    key = key.wrapping_add(key << 3).wrapping_add(key << 8) & mask;
+    key ^= key >> 14;
+    key = key.wrapping_add(key << 2).wrapping_add(key << 4) & mask;
+    key ^= key >> 28;
+    key.wrapping_add(key << 31) & mask
+}
+
+/// The minimizers of s as (k-mer, position) pairs, in the order of the positions
+pub fn minimizers(s: &[Dna], k: usize, w: usize) -> Vec<(u64, usize)> {
+    assert!((1..=32).contains(&k), "k must be between 1 and 32!");
+    assert!(w > 0, "the windows can't be empty!");
+    if s.len() < k { return vec![]; }
+    let mask = if k == 32 { u64::MAX } else { (1 << (2 * k)) - 1 };
+    let mut kmers = Vec::with_capacity(s.len() - k + 1);
+    let mut key = 0;
+    for (i, &d) in s.iter().enumerate() {
+        key = (key << 2 | code(d)) & mask;
+        if i + 1 >= k { kmers.push((hash(key, mask), key)); }
+    }
+
+    let mut res: Vec<(u64, usize)> = vec![];
+    for start in 0..=kmers.len().saturating_sub(w) {
+        let end = (start + w).min(kmers.len());
+        // the first smallest of the window
+        let p = (start..end).min_by_key(|&p| kmers[p].0).expect("empty window!");
+        if res.last().is_none_or(|&(_, q)| q != p) {
+            res.push((kmers[p].1, p));
+        }
+    }
+    res
+}
+
+/// An exact match of k letters between the reference at r and the read at q
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Anchor {
+    pub r: usize,
+    pub q: usize,
+}
+
+/// The positions of the minimizers of a reference sequence
+pub struct KmerIndex<'a> {
+    reference: &'a [Dna],
+    k: usize,
+    w: usize,
+    positions: HashMap<u64, Vec<usize>>,
+}
+
+impl<'a> KmerIndex<'a> {
+    /// Index the minimizers of the reference, k being the length of the k-mers (at most 32)
+    /// and w the number of k-mers of the windows. O(n w) time
+    pub fn new(reference: &'a [Dna], k: usize, w: usize) -> Self {
+        let mut positions: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (key, p) in minimizers(reference, k, w) {
+            positions.entry(key).or_default().push(p);
+        }
+        KmerIndex { reference, k, w, positions }
+    }
+
+    pub fn reference(&self) -> &'a [Dna] {
+        self.reference
+    }
+
+    /// The anchors of a read, every position of the reference sharing a minimizer with it,
+    /// sorted by reference and then read position
+    pub fn seeds(&self, read: &[Dna]) -> Vec<Anchor> {
+        let mut res = minimizers(read, self.k, self.w)
+            .into_iter()
+            .flat_map(|(key, q)| self.positions.get(&key).into_iter().flatten().map(move |&r| Anchor { r, q }))
+            .collect::<Vec<_>>();
+        res.sort_by_key(|a| (a.r, a.q));
+        res
+    }
+
+    /// The best colinear chain of anchors, every anchor after the previous one in both sequences.
+    /// The chain scores the matched letters minus the difference of the gaps between consecutive anchors.
+    /// O(h LOOKBACK) time for h anchors
+    pub fn chain(&self, anchors: &[Anchor]) -> Vec<Anchor> {
+        let k = self.k as i64;
+        let mut score = vec![0i64; anchors.len()];
+        let mut prev = vec![None; anchors.len()];
+        for (j, b) in anchors.iter().enumerate() {
+            score[j] = k;
+            for i in (j.saturating_sub(LOOKBACK)..j).rev() {
+                let a = anchors[i];
+                if a.r >= b.r || a.q >= b.q { continue; }
+                let (dr, dq) = ((b.r - a.r) as i64, (b.q - a.q) as i64);
+                let s = score[i] + dr.min(dq).min(k) - (dr - dq).abs();
+                if s > score[j] {
+                    score[j] = s;
+                    prev[j] = Some(i);
+                }
+            }
+        }
+        let Some(mut j) = (0..anchors.len()).max_by_key(|&j| (score[j], std::cmp::Reverse(j))) else { return vec![] };
+        let mut res = vec![anchors[j]];
+        while let Some(i) = prev[j] {
+            res.push(anchors[i]);
+            j = i;
+        }
+        res.reverse();
+        res
+    }
+}
+
+/// The alignment of a read against a region of the reference
+pub struct Mapping<M: MetricSpace> {
+    /// the aligned region of the reference
+    pub r: Range<usize>,
+    /// the cost of the alignment
+    pub cost: M::Cost,
+    /// the number of anchors of the chain
+    pub anchors: usize,
+    /// the alignment of the reference region (first row) with the whole read (second row)
+    pub align: Align<M>,
+}
+
+/// Align a read against the reference of the index by seed, chain and extend.
+/// The parts of the read before the first anchor and after the last one are aligned
+/// with the parts of the reference of the same length, the gaps between the anchors are aligned with sol_2.
+/// None if the read shares no minimizer with the reference
+pub fn map_read<M>(ms: &M, index: &KmerIndex, read: &[Dna]) -> Option<Mapping<M>>
+where M: MetricSpace<Item = Dna>
+{
+    let chain = index.chain(&index.seeds(read));
+    let first = *chain.first()?;
+    let reference = index.reference;
+    let k = index.k;
+    let (mut xb, mut yb) = (vec![], vec![]);
+    let mut push = |Align::<M>(a, b)| {
+        xb.extend(a);
+        yb.extend(b);
+    };
+
+    let rs = first.r.saturating_sub(first.q);
+    push(sol_2(ms, &reference[rs..first.r], &read[..first.q]));
+    // the end of the matched letters so far
+    let (mut r, mut q) = (first.r, first.q);
+    let anchors = chain.len();
+    for a in chain {
+        if a.r >= r && a.q >= q {
+            push(sol_2(ms, &reference[r..a.r], &read[q..a.q]));
+            push(sol_2(ms, &reference[a.r..a.r + k], &read[a.q..a.q + k]));
+            (r, q) = (a.r + k, a.q + k);
+        } else if a.r + q == a.q + r && a.r + k > r {
+            // the anchor overlaps the previous match on the same diagonal, it extends it
+            let n = a.r + k - r;
+            push(sol_2(ms, &reference[r..r + n], &read[q..q + n]));
+            (r, q) = (r + n, q + n);
+        }
+        // the other overlapping anchors are dropped
+    }
+    let re = (r + read.len() - q).min(reference.len());
+    push(sol_2(ms, &reference[r..re], &read[q..]));
+
+    let align = Align(xb, yb);
+    Some(Mapping { r: rs..re, cost: align.cost(ms), anchors, align })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{Dna, DnaMetricSpace as Dms};
+    use crate::math::{cout_align, dist_2, rm_gaps};
+    use crate::rng::Rng;
+    use super::{Anchor, KmerIndex, map_read, minimizers};
+
+    fn random(n: usize, rng: &mut Rng) -> Vec<Dna> {
+        let letters = [Dna::A, Dna::C, Dna::G, Dna::T];
+        (0..n).map(|_| letters[rng.below(4) as usize]).collect()
+    }
+
+    #[test]
+    fn minimizer_windows(){
+        let s = random(1000, &mut Rng::new(36));
+        let (k, w) = (11, 5);
+        let m = minimizers(&s, k, w);
+        // every window of w k-mers contains a minimizer
+        for start in 0..=s.len() - k - w + 1 {
+            assert!(m.iter().any(|&(_, p)| (start..start + w).contains(&p)), "window {} has no minimizer", start);
+        }
+        for w in m.windows(2) {
+            assert!(w[0].1 < w[1].1);
+        }
+        // the same k-mers give the same minimizers anywhere
+        let shifted = minimizers(&s[100..], k, w);
+        assert!(shifted.iter().filter(|&&(_, p)| p > w).all(|&(key, p)| m.contains(&(key, p + 100))));
+        assert!(minimizers(&s[..5], k, w).is_empty());
+    }
+
+    #[test]
+    fn chaining(){
+        let reference = random(100, &mut Rng::new(1));
+        let index = KmerIndex::new(&reference, 5, 3);
+        let a = |r, q| Anchor { r, q };
+        // a repeat far away and a crossing anchor are left out
+        let chain = index.chain(&[a(10, 0), a(20, 10), a(25, 5), a(30, 20), a(90, 21)]);
+        assert_eq!(chain, vec![a(10, 0), a(20, 10), a(30, 20)]);
+        assert!(index.chain(&[]).is_empty());
+    }
+
+    #[test]
+    fn map_reads(){
+        let mut rng = Rng::new(7);
+        let reference = random(20000, &mut rng);
+        let index = KmerIndex::new(&reference, 15, 10);
+        for _ in 0..20 {
+            let start = rng.below(19000) as usize;
+            let len = 200 + rng.below(500) as usize;
+            let mut read = reference[start..start + len].to_vec();
+            // a few substitutions and indels, away from the ends
+            for _ in 0..len / 50 {
+                let p = 20 + rng.below(len as u64 - 40) as usize;
+                match rng.below(3) {
+                    0 => read[p] = if read[p] == Dna::A { Dna::C } else { Dna::A },
+                    1 => { read.remove(p); },
+                    _ => read.insert(p, Dna::G),
+                }
+            }
+            let m = map_read(&Dms, &index, &read).expect("the read was not mapped!");
+            assert_eq!(m.r, start..start + len);
+            assert!(m.anchors > 1);
+            assert_eq!(rm_gaps::<Dms>(m.align.0.clone()), reference[m.r.clone()].to_vec(), "some letters 🍪 got eaten");
+            assert_eq!(rm_gaps::<Dms>(m.align.1.clone()), read, "some letters 🍪 got eaten");
+            assert_eq!(cout_align(&Dms, &m.align.0, &m.align.1), m.cost);
+            assert_eq!(m.cost, dist_2(&Dms, &reference[m.r.clone()], &read));
+        }
+        // an unrelated read has no seed
+        assert!(map_read(&Dms, &index, &random(300, &mut rng)).is_none());
+    }
+}
@@ -3,4 +3,27 @@
 
 pub mod math;
 pub mod dna;
-pub mod io;
\ No newline at end of file
+pub mod matrix;
+pub mod msa;
+pub mod profile;
+pub mod phylo;
+pub mod cooptimal;
+pub mod suboptimal;
+pub mod rng;
+pub mod generate;
+pub mod simulate;
+pub mod stats;
+pub mod kmer;
+pub mod fmindex;
+pub mod search;
+pub mod assembly;
+pub mod variants;
+pub mod output;
+pub mod batch;
+pub mod cli;
+pub mod io;
+pub mod ffi;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "serde")]
+pub mod serialize;
\ No newline at end of file
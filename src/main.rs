@@ -0,0 +1,12 @@
+//! The `runa` command line aligner, see [`runa::cli`]
+
+use std::process;
+
+fn main(){
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let (stdin, stdout) = (std::io::stdin(), std::io::stdout());
+    if let Err(e) = runa::cli::run(&args, &mut stdin.lock(), &mut stdout.lock()) {
+        eprintln!("runa: {}", e);
+        process::exit(e.exit_code());
+    }
+}
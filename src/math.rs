@@ -1,29 +1,175 @@
 //! The Math crate of the project
 
-use std::{clone::Clone, fmt::Display, collections::LinkedList};
+use std::{clone::Clone, fmt::Display, collections::LinkedList, ops::Range};
 
 /// A structure meant to be passed as a generic parameter to other functions.
 /// it is meant to ensapsulate all the information related to 
 /// - the types used 
-/// (notice that the types might be anything, you could for example have sequences of strings,
-/// all you have to do is to define a MetricSpace and the rest of the code is guaranteed to work), 
+///   (notice that the types might be anything, you could for example have sequences of strings,
+///   all you have to do is to define a MetricSpace and the rest of the code is guaranteed to work), 
 /// - the distance constants 
 /// - the usual values for zero and infinity.
+///
+/// The costs are minimised by default, but a MetricSpace with `Goal = Maximise` is a similarity
+/// scoring scheme and all the algorithms will then maximise them instead (see [`Similarity`]).
+///
+/// The items never include gaps, the gaps only exist in the rows of an alignment (see [`Gapped`]),
+/// so `sub` is only ever called on two actual items.
+///
+/// The constants that depend on the scoring scheme (substitution and gap costs) are read through
+/// a value of the MetricSpace, so that schemes can also be built at runtime, for example from a file.
 /// This makes it possible to use multiple MetricSpaces at once in the same program
 /// and makes the distance functions reusable with a large variety of sequence types.
 pub trait MetricSpace {
     type Item: Copy + Display;
     type Cost: Ord + std::ops::Add<Output = Self::Cost> + Copy + std::fmt::Debug;
+    /// whether the algorithms look for the lowest cost (a distance) or the highest (a similarity)
+    type Goal: Objective;
 
     const ZEROCOST: Self::Cost;
+    /// the worst possible cost, infinity for a distance and minus infinity for a similarity
     const INFCOST: Self::Cost;
-    const GAP: Self::Item;
-    const DEL: Self::Cost;
-    const INS: Self::Cost;
-    fn sub(a: Self::Item, b: Self::Item) -> Self::Cost;
+    /// cost of deleting an item of x
+    fn del(&self) -> Self::Cost;
+    /// cost of inserting an item of y
+    fn ins(&self) -> Self::Cost;
+    /// cost of substituting a by b
+    fn sub(&self, a: Self::Item, b: Self::Item) -> Self::Cost;
 }
+
+/// The direction in which the costs of a [`MetricSpace`] are optimised
+pub trait Objective {
+    /// true if the cost a is strictly better than b
+    fn better<C: Ord>(a: &C, b: &C) -> bool;
+
+    /// the best of the two costs, a if they are equal
+    fn best<C: Ord>(a: C, b: C) -> C {
+        if Self::better(&b, &a) { b } else { a }
+    }
+
+    /// turn a cost into a distance, for which lower is always better
+    fn distance(c: f64) -> f64;
+}
+
+/// The objective of distances, lower costs are better
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Minimise;
+impl Objective for Minimise {
+    fn better<C: Ord>(a: &C, b: &C) -> bool { a < b }
+    fn distance(c: f64) -> f64 { c }
+}
+
+/// The objective of similarity scores, higher scores are better
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Maximise;
+impl Objective for Maximise {
+    fn better<C: Ord>(a: &C, b: &C) -> bool { a > b }
+    fn distance(c: f64) -> f64 { -c }
+}
+
+/// Costs which can be turned into floating point numbers, 
+/// this is needed by the algorithms working with averages of costs (trees, statistics...)
+pub trait ToF64: Copy {
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! impl_to_f64 {
+    ($($t:ty),*) => { $(impl ToF64 for $t { fn to_f64(self) -> f64 { self as f64 } })* };
+}
+impl_to_f64!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// Add c to itself k times, zero being the cost of the empty sum. O(log k) additions
+pub fn times<C>(c: C, k: usize, zero: C) -> C
+where C: std::ops::Add<Output = C> + Copy
+{
+    let (mut acc, mut pow, mut k) = (zero, c, k);
+    while k > 0 {
+        if k & 1 == 1 { acc = acc + pow; }
+        k >>= 1;
+        if k > 0 { pow = pow + pow; }
+    }
+    acc
+}
+
+/// A similarity scoring scheme, similar items get high (usually positive) scores 
+/// and gaps get penalties (usually negative). 
+/// Every Similarity is a [`MetricSpace`] which maximises the scores, 
+/// so all the distance and alignment functions work with it as well.
+pub trait Similarity {
+    type Item: Copy + Display;
+    type Score: Ord + std::ops::Add<Output = Self::Score> + Copy + std::fmt::Debug;
+
+    const ZEROSCORE: Self::Score;
+    /// the worst possible score
+    const MINSCORE: Self::Score;
+    /// score of a column with a gap
+    fn gap(&self) -> Self::Score;
+    /// score of aligning a with b
+    fn score(&self, a: Self::Item, b: Self::Item) -> Self::Score;
+}
+
+impl<S: Similarity> MetricSpace for S {
+    type Item = S::Item;
+    type Cost = S::Score;
+    type Goal = Maximise;
+
+    const ZEROCOST: Self::Cost = S::ZEROSCORE;
+    const INFCOST: Self::Cost = S::MINSCORE;
+
+    fn del(&self) -> Self::Cost { self.gap() }
+    fn ins(&self) -> Self::Cost { self.gap() }
+    fn sub(&self, a: Self::Item, b: Self::Item) -> Self::Cost { self.score(a, b) }
+}
+
+/// A column entry of an alignment row, either an item of the sequence or a gap
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Gapped<T> {
+    Item(T),
+    Gap,
+}
+
+impl<T> Gapped<T> {
+    /// The item of this entry, None for a gap
+    pub fn item(self) -> Option<T> {
+        match self {
+            Gapped::Item(x) => Some(x),
+            Gapped::Gap => None,
+        }
+    }
+
+    pub fn is_gap(&self) -> bool {
+        matches!(self, Gapped::Gap)
+    }
+}
+
+impl<T> From<T> for Gapped<T> {
+    fn from(x: T) -> Self {
+        Gapped::Item(x)
+    }
+}
+
+impl<T: Display> Display for Gapped<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Gapped::Item(x) => write!(f, "{}", x),
+            Gapped::Gap => write!(f, "-"),
+        }
+    }
+}
+
+impl<T: std::str::FromStr> std::str::FromStr for Gapped<T> {
+    type Err = T::Err;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "-" | "_" => Ok(Gapped::Gap),
+            x => x.parse::<T>().map(Gapped::Item),
+        }
+    }
+}
+
+/// An alignment of two sequences, the rows hold the items of x and y interleaved with gaps
 #[derive(Debug, PartialEq, Eq)]
-pub struct Align<M: MetricSpace>(Vec<M::Item>, Vec<M::Item>);
+pub struct Align<M: MetricSpace>(pub Vec<Gapped<M::Item>>, pub Vec<Gapped<M::Item>>);
 
 impl<M: MetricSpace> Display for Align<M> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -39,65 +185,80 @@ impl<M: MetricSpace> Display for Align<M> {
     }
 }
 
+impl<M: MetricSpace> Align<M> {
+    /// The cost of the alignment, as cout_align but without recursion so that long alignments
+    /// do not overflow the stack
+    pub fn cost(&self, ms: &M) -> M::Cost {
+        assert_eq!(self.0.len(), self.1.len(), "the rows of the alignment differ in length!");
+        self.0.iter().zip(&self.1).fold(M::ZEROCOST, |c, (&a, &b)| c + match (a, b) {
+            (Gapped::Gap, Gapped::Gap) => ms.ins() + ms.del(),
+            (Gapped::Gap, _) => ms.ins(),
+            (_, Gapped::Gap) => ms.del(),
+            (Gapped::Item(a), Gapped::Item(b)) => ms.sub(a, b),
+        })
+    }
+}
+
 /// Calculate the cost of the alignment (x, y) passed as parameter
-pub fn cout_align<M>(x: &[M::Item], y: &[M::Item]) -> M::Cost
-where M: MetricSpace, <M as MetricSpace>::Item: PartialEq + std::fmt::Debug
+pub fn cout_align<M>(ms: &M, x: &[Gapped<M::Item>], y: &[Gapped<M::Item>]) -> M::Cost
+where M: MetricSpace, <M as MetricSpace>::Item: std::fmt::Debug
 {
     assert_eq!(x.len(), y.len(), "{:?} : {:?}", x, y);
     if x.is_empty() && y.is_empty() { M::ZEROCOST }
     else {
-        cout_align::<M>(&x[1..], &y[1..]) + 
+        cout_align(ms, &x[1..], &y[1..]) + 
         match (x[0], y[0]) {
-            (x, y) if x == M::GAP && y == M::GAP => M::INS + M::DEL,
-            (x, _) if x == M::GAP => M::INS,
-            (_, y) if y == M::GAP => M::DEL,
-            (x, y) => M::sub(x, y)
+            (Gapped::Gap, Gapped::Gap) => ms.ins() + ms.del(),
+            (Gapped::Gap, _) => ms.ins(),
+            (_, Gapped::Gap) => ms.del(),
+            (Gapped::Item(x), Gapped::Item(y)) => ms.sub(x, y)
         } 
     }
 }
 
 /// Calculate distance between sequences x and y in the MetricSpace M
+/// (the best score if M is a similarity, same for all the other distance functions)
 /// O(exp(n)) time and memory
-pub fn dist_naif<M>(x: &[M::Item], y: &[M::Item]) -> M::Cost
+pub fn dist_naif<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> M::Cost
 where M: MetricSpace
 {
-    dist_naif_rec::<M, _>(x.iter().copied(), y.iter().copied(), M::ZEROCOST, M::INFCOST)
+    dist_naif_rec(ms, x.iter().copied(), y.iter().copied(), M::ZEROCOST, M::INFCOST)
 }
 
 /// auxiliary function for dist_naif
-pub fn dist_naif_rec<T, I>(mut xi: I, mut yi: I, c: T::Cost, mut dist: T::Cost) -> T::Cost
+pub fn dist_naif_rec<T, I>(ms: &T, mut xi: I, mut yi: I, c: T::Cost, mut dist: T::Cost) -> T::Cost
 where T: MetricSpace, I: Iterator<Item = T::Item> + Clone
 {
     let (xo, yo) = (xi.clone(), yi.clone());
-    let m = (xi.next(), yi.next());
-    if let (None, None) = m { return c.min(dist); }
-    if let (Some(xj), Some(yj)) = m { dist = dist_naif_rec::<T, I>(xi.clone(), yi.clone(), c + T::sub(xj, yj), dist); }
-    if let (Some(_), _) = m { dist = dist_naif_rec::<T, I>(xi, yo, c + T::DEL, dist); }
-    if let (_, Some(_)) = m { dist = dist_naif_rec::<T, I>(xo, yi, c + T::INS, dist); }
+    let nx = (xi.next(), yi.next());
+    if let (None, None) = nx { return T::Goal::best(c, dist); }
+    if let (Some(xj), Some(yj)) = nx { dist = dist_naif_rec(ms, xi.clone(), yi.clone(), c + ms.sub(xj, yj), dist); }
+    if let (Some(_), _) = nx { dist = dist_naif_rec(ms, xi, yo, c + ms.del(), dist); }
+    if let (_, Some(_)) = nx { dist = dist_naif_rec(ms, xo, yi, c + ms.ins(), dist); }
     dist
 }
 
 /// Compute the 2D dynamic-programming table for the sequences x and y
-pub fn dist_dp_full<M>(x: &[M::Item], y: &[M::Item]) -> Vec<Vec<M::Cost>>
+pub fn dist_dp_full<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> Vec<Vec<M::Cost>>
 where M: MetricSpace, 
 {
-    use std::cmp::min;
+    let best = M::Goal::best::<M::Cost>;
 
     let n = x.len() + 1;
     let m = y.len() + 1;
     let mut dp = vec![vec![M::ZEROCOST; m]; n];
     for i in 1..n {
-        dp[i][0] = dp[i-1][0] + M::DEL;
+        dp[i][0] = dp[i-1][0] + ms.del();
     }
     for j in 1..m {
-        dp[0][j] = dp[0][j-1] + M::INS;
+        dp[0][j] = dp[0][j-1] + ms.ins();
     }
     for i in 1..n {
         for j in 1..m {
-            dp[i][j] = min(
-                dp[i-1][j-1] + M::sub(x[i-1], y[j-1]), min(
-                dp[i][j-1] + M::INS,
-                dp[i-1][j] + M::DEL
+            dp[i][j] = best(
+                dp[i-1][j-1] + ms.sub(x[i-1], y[j-1]), best(
+                dp[i][j-1] + ms.ins(),
+                dp[i-1][j] + ms.del()
             ))
         }
     }
@@ -105,23 +266,69 @@ where M: MetricSpace,
 }
 
 /// Compute distance using a 2D table O(n^2) time O(n^2) memory 
-pub fn dist_1<M>(x: &[M::Item], y: &[M::Item]) -> M::Cost 
+pub fn dist_1<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> M::Cost 
 where M: MetricSpace
 {
-    let dp = dist_dp_full::<M>(x, y);
+    let dp = dist_dp_full(ms, x, y);
     dp[x.len()][y.len()]
 }
 
 /// Compute the optimal alignment using a 2D table O(n^2) time and memory
-pub fn sol_1<M>(x: &[M::Item], y: &[M::Item]) -> Align<M>
+pub fn sol_1<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> Align<M>
 where M: MetricSpace
 {
-    let t = dist_dp_full::<M>(x, y);
-    sol_1_tab(x, y, t.as_slice())
+    let t = dist_dp_full(ms, x, y);
+    sol_1_tab(ms, x, y, t.as_slice())
 }
 
 /// Same as sol_1 but you pass in the table manually
-pub fn sol_1_tab<M>(x: &[M::Item], y: &[M::Item], t: &[Vec<M::Cost>]) -> Align<M>
+pub fn sol_1_tab<M>(ms: &M, x: &[M::Item], y: &[M::Item], t: &[Vec<M::Cost>]) -> Align<M>
+where M: MetricSpace
+{
+    sol_1_tab_with(ms, x, y, t, TieBreak::default())
+}
+
+/// How the ties between optimal moves are broken when tracing back an alignment.
+/// The traceback goes from the end of the sequences to their start and takes the first optimal
+/// move in the order of the policy. sol_1 and sol_2 give the same alignment for the same policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TieBreak {
+    /// substitution, then insertion, then deletion. The historical order of sol_1,
+    /// the gaps end up on the left
+    #[default]
+    Diagonal,
+    /// substitution, then deletion, then insertion. The gaps end up on the left,
+    /// with the insertions before the deletions
+    LeftGaps,
+    /// insertion, then deletion, then substitution. The gaps end up on the right
+    RightGaps,
+}
+
+/// A move of the traceback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Move { Sub, Ins, Del }
+
+impl TieBreak {
+    /// The moves in the order they are tried
+    fn order(self) -> [Move; 3] {
+        match self {
+            TieBreak::Diagonal => [Move::Sub, Move::Ins, Move::Del],
+            TieBreak::LeftGaps => [Move::Sub, Move::Del, Move::Ins],
+            TieBreak::RightGaps => [Move::Ins, Move::Del, Move::Sub],
+        }
+    }
+}
+
+/// Compute the optimal alignment using a 2D table, ties broken by the given policy
+pub fn sol_1_with<M>(ms: &M, x: &[M::Item], y: &[M::Item], tie: TieBreak) -> Align<M>
+where M: MetricSpace
+{
+    let t = dist_dp_full(ms, x, y);
+    sol_1_tab_with(ms, x, y, t.as_slice(), tie)
+}
+
+/// Same as sol_1_with but you pass in the table manually
+pub fn sol_1_tab_with<M>(ms: &M, x: &[M::Item], y: &[M::Item], t: &[Vec<M::Cost>], tie: TieBreak) -> Align<M>
 where M: MetricSpace
 {
     let n = x.len();
@@ -133,67 +340,65 @@ where M: MetricSpace
 
     let mut i = n;
     let mut j = m;
-    while i > 0 && j > 0 {
-        if t[i][j] == t[i-1][j-1] + M::sub(x[i-1], y[j-1]) {
-            xb.push(x[i-1]);
-            yb.push(y[j-1]);
-            i -= 1;
-            j -= 1;
-        } else if t[i][j] == t[i][j-1] + M::INS {
-            xb.push(M::GAP);
-            yb.push(y[j-1]);
-            j -= 1;
-        } else {
-            // assert_eq!(if t[i][j], t[i-1][j] + M::DEL);
-            xb.push(x[i-1]);
-            yb.push(M::GAP);
-            i -= 1;
+    while i > 0 || j > 0 {
+        let mv = tie.order().into_iter().find(|mv| match mv {
+            Move::Sub => i > 0 && j > 0 && t[i][j] == t[i-1][j-1] + ms.sub(x[i-1], y[j-1]),
+            Move::Ins => j > 0 && t[i][j] == t[i][j-1] + ms.ins(),
+            Move::Del => i > 0 && t[i][j] == t[i-1][j] + ms.del(),
+        }).expect("t is not the dp table of x and y!");
+        match mv {
+            Move::Sub => {
+                xb.push(Gapped::Item(x[i-1]));
+                yb.push(Gapped::Item(y[j-1]));
+                i -= 1;
+                j -= 1;
+            }
+            Move::Ins => {
+                xb.push(Gapped::Gap);
+                yb.push(Gapped::Item(y[j-1]));
+                j -= 1;
+            }
+            Move::Del => {
+                xb.push(Gapped::Item(x[i-1]));
+                yb.push(Gapped::Gap);
+                i -= 1;
+            }
         }
     }
-    while i > 0 {
-        xb.push(x[i-1]);
-        yb.push(M::GAP);
-        i -= 1;
-    }
-    while j > 0 {
-        xb.push(M::GAP);
-        yb.push(y[j-1]);
-        j -= 1;
-    }
     xb.reverse();
     yb.reverse();
     Align(xb, yb)
 }
 
 /// Calculate the optimal alignment and the distance between the sequences at once
-pub fn prog_dyn<M>(x: &[M::Item], y: &[M::Item]) -> (M::Cost, Align<M>)
+pub fn prog_dyn<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> (M::Cost, Align<M>)
 where M: MetricSpace
 {
-    let dp = dist_dp_full::<M>(x, y);
-    (dp[x.len()][y.len()], sol_1_tab::<M>(x, y, dp.as_slice()))
+    let dp = dist_dp_full(ms, x, y);
+    (dp[x.len()][y.len()], sol_1_tab(ms, x, y, dp.as_slice()))
 }
 
 /// Calculate the distance between two sequences O(n^2) time O(n) memory
-pub fn dist_2<M>(x: &[M::Item], y: &[M::Item]) -> M::Cost 
+pub fn dist_2<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> M::Cost 
 where M: MetricSpace
 {
-    use std::cmp::min;
+    let best = M::Goal::best::<M::Cost>;
 
     let n = x.len() + 1;
     let m = y.len() + 1;
     let mut dp = vec![vec![M::ZEROCOST; m]; 2];
 
     for j in 1..m {
-        dp[0][j] = dp[0][j-1] + M::INS;
+        dp[0][j] = dp[0][j-1] + ms.ins();
     }
     for i in 1..n {
-        dp[1][0] = dp[0][0] + M::DEL;
+        dp[1][0] = dp[0][0] + ms.del();
         for j in 1..m {
-            dp[1][j] = min(
-                dp[0][j-1] + M::sub(x[i-1], y[j-1]),
-                min(
-                    dp[1][j-1] + M::INS,
-                    dp[0][j] + M::DEL
+            dp[1][j] = best(
+                dp[0][j-1] + ms.sub(x[i-1], y[j-1]),
+                best(
+                    dp[1][j-1] + ms.ins(),
+                    dp[0][j] + ms.del()
                 )
             )
         }
@@ -202,12 +407,52 @@ where M: MetricSpace
     dp[0][y.len()]
 }
 
+/// Same as dist_2, the letters of y being given one at a time so that y never needs to be in memory
+/// (see [`crate::io::AdnReader`]). The first error of the letters is returned.
+/// O(nm) time, O(n) memory
+pub fn dist_2_stream<M, E>(ms: &M, x: &[M::Item], y: impl IntoIterator<Item = Result<M::Item, E>>) -> Result<M::Cost, E>
+where M: MetricSpace
+{
+    let best = M::Goal::best::<M::Cost>;
+
+    // the column of the last letter of y read, over the prefixes of x
+    let mut col = vec![M::ZEROCOST; x.len() + 1];
+    for i in 1..col.len() {
+        col[i] = col[i-1] + ms.del();
+    }
+    for b in y {
+        let b = b?;
+        let mut diag = col[0];
+        col[0] = col[0] + ms.ins();
+        for i in 1..col.len() {
+            let c = best(
+                diag + ms.sub(x[i-1], b),
+                best(
+                    col[i] + ms.ins(),
+                    col[i-1] + ms.del()
+                )
+            );
+            diag = col[i];
+            col[i] = c;
+        }
+    }
+    Ok(col[x.len()])
+}
+
 /// Calculate the optimal cutting point in sequence y for the corresponding 
 /// cutting point |x|/2 in sequence x 
-pub fn coupure<M>(x: &[M::Item], y: &[M::Item]) -> usize 
+pub fn coupure<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> usize 
 where M: MetricSpace
 {
-    use std::cmp::min;
+    coupure_with(ms, x, y, TieBreak::default())
+}
+
+/// Same as coupure, the cutting point is the one of the alignment sol_1_with would give with this policy:
+/// q holds the column where the traceback from every cell leaves the row |x|/2
+pub fn coupure_with<M>(ms: &M, x: &[M::Item], y: &[M::Item], tie: TieBreak) -> usize 
+where M: MetricSpace
+{
+    let best = M::Goal::best::<M::Cost>;
     let n = x.len() + 1;
     let m = y.len() + 1;
 
@@ -219,23 +464,30 @@ where M: MetricSpace
     q[0][0] = 0;
 
     for j in 1..m {
-        t[0][j] = t[0][j-1] + M::INS;
+        t[0][j] = t[0][j-1] + ms.ins();
         q[0][j] = j;
     }
 
     for i in 1..n {
-        t[1][0] = t[0][0] + M::DEL;
+        t[1][0] = t[0][0] + ms.del();
         for j in 1..m {
-            let op1 = t[0][j-1] + M::sub(x[i-1], y[j-1]);
-            let op2 = t[0][j] + M::DEL;
-            let op3 = t[1][j-1] + M::INS;
+            let op1 = t[0][j-1] + ms.sub(x[i-1], y[j-1]);
+            let op2 = t[0][j] + ms.del();
+            let op3 = t[1][j-1] + ms.ins();
 
-            t[1][j] = min(op1, min(op2, op3));
+            t[1][j] = best(op1, best(op2, op3));
 
-            if i <= i_star          { continue; }
-            if t[1][j] == op1       { q[1][j] = q[0][j-1]; }
-            else if t[1][j] == op2  { q[1][j] = q[0][j]; }
-            else                    { q[1][j] = q[1][j-1]; }
+            if i <= i_star { continue; }
+            let optimal = |mv: &Move| t[1][j] == match mv {
+                Move::Sub => op1,
+                Move::Del => op2,
+                Move::Ins => op3,
+            };
+            q[1][j] = match tie.order().into_iter().find(optimal) {
+                Some(Move::Sub) => q[0][j-1],
+                Some(Move::Del) => q[0][j],
+                _ => q[1][j-1],
+            };
         }
         t.swap(0, 1);
         if i > i_star { q.swap(0, 1); }
@@ -244,52 +496,82 @@ where M: MetricSpace
     q[0][y.len()]
 }
 
+/// The two rows of an alignment under construction, as used by sol_2
+pub type AlignLL<T> = (LinkedList<Gapped<T>>, LinkedList<Gapped<T>>);
+
 /// Generate a word composed of gaps of length n
-pub fn mot_gaps<M>(n: usize) -> LinkedList<M::Item>
+pub fn mot_gaps<M>(n: usize) -> LinkedList<Gapped<M::Item>>
 where M: MetricSpace
 {
     let mut ret = LinkedList::from([]);
-    for _ in 0..n { ret.push_back(M::GAP); }
+    for _ in 0..n { ret.push_back(Gapped::Gap); }
     ret
 }
 
+/// Turn a sequence into an alignment row without gaps
+pub fn mot_items<M>(x: &[M::Item]) -> LinkedList<Gapped<M::Item>>
+where M: MetricSpace
+{
+    x.iter().copied().map(Gapped::Item).collect()
+}
+
 
 /// Remove all gaps from the sequence
-pub fn rm_gaps<M>(a: Vec<M::Item>) -> Vec<M::Item>
-where M: MetricSpace,  <M as MetricSpace>::Item: PartialEq
+pub fn rm_gaps<M>(a: Vec<Gapped<M::Item>>) -> Vec<M::Item>
+where M: MetricSpace
 {
-    a.into_iter().filter(|&x| x != M::GAP).collect::<Vec<_>>()
+    a.into_iter().filter_map(Gapped::item).collect::<Vec<_>>()
 }
 
 /// Align a letter with a word in the optimal way. O(n) time
-pub fn align_lettre_mot<M>(x: M::Item, y: &[M::Item]) -> (LinkedList<M::Item>, LinkedList<M::Item>)
+pub fn align_lettre_mot<M>(ms: &M, x: M::Item, y: &[M::Item]) -> AlignLL<M::Item>
 where M: MetricSpace
 {
-    let (i, _) = y
+    let (i, c) = y
         .iter()
         .enumerate()
-        .min_by_key(|&(_, &yk)| M::sub(x, yk))
+        .map(|(k, &yk)| (k, ms.sub(x, yk)))
+        .reduce(|a, b| if M::Goal::better(&b.1, &a.1) { b } else { a })
         .expect("y is empty!");
+    if M::Goal::better(&(ms.del() + ms.ins()), &c) {
+        // substituting costs more than deleting x and inserting y[i]
+        let mut xb = LinkedList::from([Gapped::Item(x)]);
+        xb.append(&mut mot_gaps::<M>(y.len()));
+        let mut yb = LinkedList::from([Gapped::Gap]);
+        yb.append(&mut mot_items::<M>(y));
+        return (xb, yb);
+    }
     let mut xb = mot_gaps::<M>(i);
-    xb.push_back(x);
+    xb.push_back(Gapped::Item(x));
     xb.append(&mut mot_gaps::<M>(y.len() - 1 - i));
-    (xb, LinkedList::from_iter(y.iter().copied()))
+    (xb, mot_items::<M>(y))
 } 
 
 /// Auxiliary function for sol_2 O(n^2) time and O((n+m)log n) memory
-pub fn sol_2_ll<M>(x: &[M::Item], y: &[M::Item]) -> (LinkedList<M::Item>, LinkedList<M::Item>) 
+pub fn sol_2_ll<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> AlignLL<M::Item> 
+where M: MetricSpace
+{
+    sol_2_ll_with(ms, x, y, TieBreak::default())
+}
+
+/// Same as sol_2_ll, ties broken by the given policy
+pub fn sol_2_ll_with<M>(ms: &M, x: &[M::Item], y: &[M::Item], tie: TieBreak) -> AlignLL<M::Item> 
 where M: MetricSpace
 {
     match (x.len(), y.len()) {
-        (0, _) => (mot_gaps::<M>(y.len()), LinkedList::from_iter(y.iter().copied())),
-        (_, 0) => (LinkedList::from_iter(x.iter().copied()), mot_gaps::<M>(x.len())),
-        (1, _) => align_lettre_mot::<M>(x[0], y),
+        (0, _) => (mot_gaps::<M>(y.len()), mot_items::<M>(y)),
+        (_, 0) => (mot_items::<M>(x), mot_gaps::<M>(x.len())),
+        (1, _) => {
+            // the table only has two rows, and tracing it back keeps the tie breaking of sol_1
+            let Align(a, b) = sol_1_with(ms, x, y, tie);
+            (a.into_iter().collect(), b.into_iter().collect())
+        }
         (_, _) => {
             let i = x.len()/2;
-            let j = coupure::<M>(x, y);
+            let j = coupure_with(ms, x, y, tie);
     
-            let (mut x1, mut y1) = sol_2_ll::<M>(&x[0..i], &y[0..j]);
-            let (mut x2, mut y2) = sol_2_ll::<M>(&x[i..], &y[j..]);
+            let (mut x1, mut y1) = sol_2_ll_with(ms, &x[0..i], &y[0..j], tie);
+            let (mut x2, mut y2) = sol_2_ll_with(ms, &x[i..], &y[j..], tie);
     
             x1.append(&mut x2);
             y1.append(&mut y2);
@@ -300,11 +582,168 @@ where M: MetricSpace
 }
 
 /// Compute the alignement of two sequences in O(n^2) time and O((n+m)log n) memory
-pub fn sol_2<M>(x: &[M::Item], y: &[M::Item]) -> Align<M> 
+pub fn sol_2<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> Align<M> 
 where M: MetricSpace
 {
-    let (a, b) = sol_2_ll::<M>(x, y);
-    Align(Vec::from_iter(a.into_iter()), Vec::from_iter(b.into_iter()))
+    sol_2_with(ms, x, y, TieBreak::default())
+}
+
+/// Same as sol_2, ties broken by the given policy. The alignment is the same as the one of sol_1_with
+pub fn sol_2_with<M>(ms: &M, x: &[M::Item], y: &[M::Item], tie: TieBreak) -> Align<M> 
+where M: MetricSpace
+{
+    let (a, b) = sol_2_ll_with(ms, x, y, tie);
+    Align(Vec::from_iter(a), Vec::from_iter(b))
+}
+
+/// The best cell of the dp table of x and y. If `free_start` the alignments may start anywhere 
+/// (the scores never go below zero), otherwise they start at (0, 0), in both cases they end anywhere.
+/// O(n^2) time O(n) memory
+fn best_end<M>(ms: &M, x: &[M::Item], y: &[M::Item], free_start: bool) -> (M::Cost, usize, usize)
+where M: MetricSpace<Goal = Maximise>
+{
+    let floor = |c| if free_start { Maximise::best(c, M::ZEROCOST) } else { c };
+    let best = Maximise::best::<M::Cost>;
+    
+    let n = x.len() + 1;
+    let m = y.len() + 1;
+    let mut dp = vec![vec![M::ZEROCOST; m]; 2];
+    let mut end = (M::ZEROCOST, 0, 0);
+
+    for j in 1..m {
+        dp[0][j] = floor(dp[0][j-1] + ms.ins());
+        if dp[0][j] > end.0 { end = (dp[0][j], 0, j); }
+    }
+    for i in 1..n {
+        dp[1][0] = floor(dp[0][0] + ms.del());
+        if dp[1][0] > end.0 { end = (dp[1][0], i, 0); }
+        for j in 1..m {
+            dp[1][j] = floor(best(
+                dp[0][j-1] + ms.sub(x[i-1], y[j-1]),
+                best(
+                    dp[1][j-1] + ms.ins(),
+                    dp[0][j] + ms.del()
+                )
+            ));
+            if dp[1][j] > end.0 { end = (dp[1][j], i, j); }
+        }
+        dp.swap(0, 1);
+    }
+    end
+}
+
+/// Calculate the score of the best local alignment (Smith-Waterman) of two sequences,
+/// this only makes sense for a similarity. O(n^2) time O(n) memory
+pub fn dist_local<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> M::Cost 
+where M: MetricSpace<Goal = Maximise>
+{
+    best_end(ms, x, y, true).0
+}
+
+/// A local alignment, the alignment of the parts x[x] and y[y] of the two sequences
+pub struct LocalAlign<M: MetricSpace> {
+    pub score: M::Cost,
+    pub x: Range<usize>,
+    pub y: Range<usize>,
+    pub align: Align<M>,
+}
+
+/// Compute the best local alignment of two sequences in O(n^2) time and O((n+m)log n) memory.
+/// The end of the aligned parts is found with dist_local, their start with a second pass 
+/// over the reversed prefixes, and then they are aligned with sol_2.
+pub fn sol_local<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> LocalAlign<M> 
+where M: MetricSpace<Goal = Maximise>
+{
+    let (score, ie, je) = best_end(ms, x, y, true);
+    let xr = x[..ie].iter().rev().copied().collect::<Vec<_>>();
+    let yr = y[..je].iter().rev().copied().collect::<Vec<_>>();
+    let (_, di, dj) = best_end(ms, &xr, &yr, false);
+    let (is, js) = (ie - di, je - dj);
+
+    LocalAlign { score, x: is..ie, y: js..je, align: sol_2(ms, &x[is..ie], &y[js..je]) }
+}
+
+/// The best overlap of a suffix of x with a prefix of y: its score and its end in y.
+/// The letters of x before the overlap and the letters of y after it are free. O(n^2) time O(n) memory
+fn overlap_end<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> (M::Cost, usize)
+where M: MetricSpace<Goal = Maximise>
+{
+    let best = Maximise::best::<M::Cost>;
+    let m = y.len() + 1;
+    let mut dp = vec![vec![M::ZEROCOST; m]; 2];
+
+    for j in 1..m {
+        dp[0][j] = dp[0][j-1] + ms.ins();
+    }
+    for i in 1..=x.len() {
+        dp[1][0] = M::ZEROCOST;
+        for j in 1..m {
+            dp[1][j] = best(
+                dp[0][j-1] + ms.sub(x[i-1], y[j-1]),
+                best(
+                    dp[1][j-1] + ms.ins(),
+                    dp[0][j] + ms.del()
+                )
+            );
+        }
+        dp.swap(0, 1);
+    }
+    let mut end = (dp[0][0], 0);
+    for (j, &c) in dp[0].iter().enumerate() {
+        if c > end.0 { end = (c, j); }
+    }
+    end
+}
+
+/// Calculate the score of the best overlap alignment of two sequences, the alignment of a suffix of x 
+/// with a prefix of y (the start of x and the end of y are free).
+/// This only makes sense for a similarity. O(n^2) time O(n) memory
+pub fn dist_overlap<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> M::Cost 
+where M: MetricSpace<Goal = Maximise>
+{
+    overlap_end(ms, x, y).0
+}
+
+/// An overlap alignment, the alignment of the suffix x[x] of x with the prefix y[y] of y
+pub struct Overlap<M: MetricSpace> {
+    pub score: M::Cost,
+    pub x: Range<usize>,
+    pub y: Range<usize>,
+    pub align: Align<M>,
+}
+
+/// Compute the best overlap alignment of two sequences in O(n^2) time and O((n+m)log n) memory.
+/// The end of the overlap in y is found with dist_overlap, its start in x (the shortest suffix of x reaching
+/// the best score) with a second pass over the suffixes of x, and then they are aligned with sol_2.
+pub fn sol_overlap<M>(ms: &M, x: &[M::Item], y: &[M::Item]) -> Overlap<M> 
+where M: MetricSpace<Goal = Maximise>
+{
+    let best = Maximise::best::<M::Cost>;
+    let (score, je) = overlap_end(ms, x, y);
+    let n = x.len();
+    let m = je + 1;
+    // dp[0][j] is the score of the suffix of x of length i aligned with y[j..je]
+    let mut dp = vec![vec![M::ZEROCOST; m]; 2];
+    for j in (0..je).rev() {
+        dp[0][j] = dp[0][j+1] + ms.ins();
+    }
+    let mut i = 0;
+    while dp[0][0] != score {
+        assert!(i < n, "no suffix of x reaches the best score!");
+        i += 1;
+        dp[1][je] = dp[0][je] + ms.del();
+        for j in (0..je).rev() {
+            dp[1][j] = best(
+                dp[0][j+1] + ms.sub(x[n-i], y[j]),
+                best(
+                    dp[1][j+1] + ms.ins(),
+                    dp[0][j] + ms.del()
+                )
+            );
+        }
+        dp.swap(0, 1);
+    }
+    Overlap { score, x: n-i..n, y: 0..je, align: sol_2(ms, &x[n-i..], &y[..je]) }
 }
 
 #[cfg(test)]
@@ -319,7 +758,11 @@ mod tests {
     use super::MetricSpace;
     use super::dist_2;
     use super::sol_2;
-    use super::{Align, cout_align};
+    use super::{Align, Gapped, cout_align};
+
+    fn row(s: &str) -> Vec<Gapped<Dna>> {
+        s.split_ascii_whitespace().map(|c| c.parse().expect("invalid row!")).collect()
+    }
 
     fn test_dist_3<F>(f: F, name: &str) // manual tests
     where F: Fn(&Vec<Dna>, &Vec<Dna>) -> u64
@@ -359,36 +802,57 @@ mod tests {
     #[test]
     fn cout_align_dna(){
         use Dna::*;
-        assert_eq!(cout_align::<Dms>(&[A, T, Gap, A, C], &[Gap, T, G, A, C]), 4);
+        assert_eq!(cout_align(&Dms, &row("A T - A C"), &row("- T G A C")), 4);
+        assert_eq!(cout_align(&Dms, &row("A -"), &row("- -")), 2 + 2 + 2);
+        assert_eq!(cout_align(&Dms, &[Gapped::Item(A)], &[Gapped::Item(T)]), 3);
     }
 
     #[test]
     fn dist_naif_dna(){
         test_dist_3(|l: &Vec<Dna>, r: &Vec<Dna>| -> u64 {
-            super::dist_naif::<Dms>(l, r)
+            super::dist_naif(&Dms, l, r)
         }, "dist_naif");
     }
 
     #[test]
     fn dist_1_dna(){
         test_dist_3(|l: &Vec<Dna>, r: &Vec<Dna>| -> u64 {
-            super::dist_1::<Dms>(l, r)
+            super::dist_1(&Dms, l, r)
         }, "dist_1");
     }
 
     #[test]
     fn dist_2_dna(){
         test_dist_3(|l: &Vec<Dna>, r: &Vec<Dna>| -> u64 {
-            super::dist_2::<Dms>(l, r)
+            super::dist_2(&Dms, l, r)
         }, "dist_2");
 
         test_against(|l: &Vec<Dna>, r: &Vec<Dna>| -> u64 {
-            super::dist_2::<Dms>(l, r)
+            super::dist_2(&Dms, l, r)
         }, |l: &Vec<Dna>, r: &Vec<Dna>| -> u64 {
-            super::dist_1::<Dms>(l, r)
+            super::dist_1(&Dms, l, r)
         }, |a, b| a == b, "dist_2")
     }
 
+    #[test]
+    fn dist_2_stream(){
+        use crate::dna::DnaSimilarity;
+        use super::dist_2_stream;
+        let stream = |y: &[Dna]| y.iter().map(|&b| Ok::<_, ()>(b)).collect::<Vec<_>>();
+        test_against(|l: &Vec<Dna>, r: &Vec<Dna>| -> u64 {
+            dist_2_stream(&Dms, l, stream(r)).unwrap()
+        }, |l: &Vec<Dna>, r: &Vec<Dna>| -> u64 {
+            super::dist_2(&Dms, l, r)
+        }, |a, b| a == b, "dist_2_stream");
+        let sim = DnaSimilarity::default();
+        for (_, DnaBlock(l, r)) in read_test_insts_all().take_while(|&(size, _)| size <= 1000) {
+            assert_eq!(dist_2_stream(&sim, &l, stream(&r)), Ok(dist_2(&sim, &l, &r)));
+            assert_eq!(dist_2_stream(&sim, &[], stream(&r)), Ok(dist_2(&sim, &[], &r)));
+        }
+        let failing = [Ok(Dna::A), Err("broken"), Ok(Dna::C)];
+        assert_eq!(dist_2_stream(&Dms, &[Dna::A], failing), Err("broken"));
+    }
+
     #[test]
     fn sol1_2(){ // sanity tests
         use super::{dist_2, sol_1, sol_2, rm_gaps};
@@ -396,31 +860,30 @@ mod tests {
         let testcases = read_test_insts_all() // this does not read all files at once
             .take_while(|&(size, _)| size <= 500)
             .map(|(_, b)| b)
-            .map(|b| (dist_2::<Dms>(b.0.as_slice(), b.1.as_slice()), b))
+            .map(|b| (dist_2(&Dms, b.0.as_slice(), b.1.as_slice()), b))
             .map(|(a, b)| (b, a));
 
         for (DnaBlock(l, r), d) in testcases {
-            let al = sol_1::<Dms>(l.as_slice(), r.as_slice());
+            let al = sol_1(&Dms, l.as_slice(), r.as_slice());
             assert_eq!(rm_gaps::<Dms>(al.0.clone()), *l, "some letters 🍪 got eaten in sol_1");
             assert_eq!(rm_gaps::<Dms>(al.1.clone()), *r, "some letters 🍪 got eaten in sol_1");
-            let x = cout_align::<Dms>(al.0.as_slice(), al.1.as_slice());
+            let x = cout_align(&Dms, al.0.as_slice(), al.1.as_slice());
             assert_eq!(x, d);
             
             ///////////////
             
-            let al = sol_2::<Dms>(l.as_slice(), r.as_slice());
+            let al = sol_2(&Dms, l.as_slice(), r.as_slice());
             assert_eq!(rm_gaps::<Dms>(al.0.clone()), *l, "some letters 🍪 got eaten in sol_2");
             assert_eq!(rm_gaps::<Dms>(al.1.clone()), *r, "some letters 🍪 got eaten in sol_2");
-            let x = cout_align::<Dms>(al.0.as_slice(), al.1.as_slice());
+            let x = cout_align(&Dms, al.0.as_slice(), al.1.as_slice());
             assert_eq!(x, d);
         }
     }
 
     #[test]
     fn sol1(){ // additional manual tests
-        use Dna::*;
         let filenames = &[
-            (Align(vec![T, A, T, A, T, G, A, G, T, C], vec![T, A, T, Gap, T, Gap, Gap, Gap, T, Gap]), "Inst_0000010_44.adn"),
+            (Align(row("T A T A T G A G T C"), row("T A T - T - - - T -")), "Inst_0000010_44.adn"),
         ];
 
         let testcases = filenames
@@ -428,11 +891,11 @@ mod tests {
             .map(|(t, p)| (t, read_test_inst(p).expect("couldn't read instance!")));
 
         for (result, DnaBlock(l, r)) in testcases {
-            let t = dist_dp_full::<Dms>(l.as_slice(), r.as_slice());
+            let t = dist_dp_full(&Dms, l.as_slice(), r.as_slice());
             for line in &t {
                 println!("tableau: {:?}", line);
             }
-            let d = sol_1_tab::<Dms>(l.as_slice(), r.as_slice(), t.as_slice());
+            let d = sol_1_tab(&Dms, l.as_slice(), r.as_slice(), t.as_slice());
 
             assert_eq!(rm_gaps::<Dms>(d.0.clone()), *l, "some letters 🍪 got eaten in sol_1");
             assert_eq!(rm_gaps::<Dms>(d.1.clone()), *r, "some letters 🍪 got eaten in sol_1");
@@ -446,22 +909,186 @@ mod tests {
     fn sol2(){
         use super::rm_gaps;
         test_against(|l: &Vec<Dna>, r: &Vec<Dna>| -> Align<Dms>  {
-            let Align::<Dms>(a, b) = super::sol_2(l, r);
+            let Align::<Dms>(a, b) = super::sol_2(&Dms, l, r);
             assert_eq!(rm_gaps::<Dms>(a.clone()), *l, "some letters 🍪 got eaten in sol_2");
             assert_eq!(rm_gaps::<Dms>(b.clone()), *r, "some letters 🍪 got eaten in sol_2");
             Align(a, b)
         }, |l: &Vec<Dna>, r: &Vec<Dna>| -> Align<Dms> {
-            let Align::<Dms>(a, b) = super::sol_1(l, r);
+            let Align::<Dms>(a, b) = super::sol_1(&Dms, l, r);
             assert_eq!(rm_gaps::<Dms>(a.clone()), *l, "some letters 🍪 got eaten in sol_1");
             assert_eq!(rm_gaps::<Dms>(b.clone()), *r, "some letters 🍪 got eaten in sol_1");
             Align(a, b)
         }, |a, b| {
-            let l = super::cout_align::<Dms>(a.0.as_slice(), a.1.as_slice());
-            let r = super::cout_align::<Dms>(b.0.as_slice(), b.1.as_slice());
+            let l = super::cout_align(&Dms, a.0.as_slice(), a.1.as_slice());
+            let r = super::cout_align(&Dms, b.0.as_slice(), b.1.as_slice());
             l == r
         }, "sol_2")
     }
 
+    #[test]
+    fn tie_break(){
+        use super::{TieBreak, dist_1, sol_1, sol_1_with, sol_2_with};
+        use crate::dna::DnaSimilarity;
+        let policies = [TieBreak::Diagonal, TieBreak::LeftGaps, TieBreak::RightGaps];
+
+        let (x, y) = (seq("AAT"), seq("AT"));
+        assert_eq!(sol_1_with(&Dms, &x, &y, TieBreak::Diagonal), Align(row("A A T"), row("- A T")));
+        assert_eq!(sol_1_with(&Dms, &x, &y, TieBreak::RightGaps), Align(row("A A T"), row("A - T")));
+        let (x, y) = (seq("AC"), seq("AG"));
+        assert_eq!(sol_1_with(&Dms, &x, &y, TieBreak::RightGaps), Align(row("A C"), row("A G")));
+        assert_eq!(sol_1_with(&crate::matrix::SubstMatrix::unit(), &x, &y, TieBreak::RightGaps),
+            Align(row("A C"), row("A G")));
+        let (x, y) = (seq("A"), seq("AAA"));
+        assert_eq!(sol_2_with(&Dms, &x, &y, TieBreak::Diagonal), Align(row("- - A"), row("A A A")));
+        assert_eq!(sol_2_with(&Dms, &x, &y, TieBreak::RightGaps), Align(row("A - -"), row("A A A")));
+
+        // both paths must give the very same alignment, not only the same cost
+        let sim = DnaSimilarity::default();
+        let testcases = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 500)
+            .map(|(_, b)| b);
+        for DnaBlock(l, r) in testcases {
+            for tie in policies {
+                let al = sol_1_with(&Dms, &l, &r, tie);
+                assert_eq!(cout_align(&Dms, &al.0, &al.1), dist_1(&Dms, &l, &r));
+                assert_eq!(al, sol_2_with(&Dms, &l, &r, tie), "sol_1 and sol_2 disagree with {:?}", tie);
+                assert_eq!(sol_1_with(&sim, &l, &r, tie), sol_2_with(&sim, &l, &r, tie),
+                    "sol_1 and sol_2 disagree with {:?}", tie);
+            }
+            assert_eq!(sol_1(&Dms, &l, &r), sol_2(&Dms, &l, &r));
+        }
+    }
+
+    #[test]
+    fn gapped(){
+        let r = row("A - c _ T");
+        assert_eq!(r, vec![Gapped::Item(Dna::A), Gapped::Gap, Gapped::Item(Dna::C), Gapped::Gap, Gapped::Item(Dna::T)]);
+        assert_eq!(r.iter().map(|g| g.to_string()).collect::<String>(), "A-C-T");
+        assert_eq!(rm_gaps::<Dms>(r), vec![Dna::A, Dna::C, Dna::T]);
+        assert!("-".parse::<Dna>().is_err(), "a gap is not a dna element");
+    }
+
+    #[test]
+    fn expensive_substitutions(){
+        // substituting costs more than a deletion and an insertion, so sol_2 must never substitute
+        let mat = crate::matrix::SubstMatrix::transition_transversion(5, 9, 1).expect("invalid matrix!");
+        let testcases = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 100)
+            .map(|(_, b)| b);
+
+        for DnaBlock(l, r) in testcases {
+            let d = dist_2(&mat, &l, &r);
+            let al = sol_2(&mat, &l, &r);
+            assert_eq!(cout_align(&mat, &al.0, &al.1), d);
+            let al = super::sol_1(&mat, &l, &r);
+            assert_eq!(cout_align(&mat, &al.0, &al.1), d);
+        }
+    }
+
+    fn seq(s: &str) -> Vec<Dna> {
+        s.chars().map(|c| c.to_string().parse().expect("invalid sequence!")).collect()
+    }
+
+    #[test]
+    fn similarity_global(){
+        use crate::dna::DnaSimilarity;
+        let sim = DnaSimilarity::default();
+        assert_eq!(dist_2(&sim, &seq("ACGT"), &seq("ACGT")), 4);
+        assert_eq!(dist_2(&sim, &seq("AAA"), &seq("A")), 1 - 2 - 2);
+        assert_eq!(dist_2(&sim, &seq("ACGT"), &seq("AGGT")), 3 - 1);
+
+        let testcases = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 100)
+            .map(|(_, b)| b);
+        for DnaBlock(l, r) in testcases {
+            let d = dist_2(&sim, &l, &r);
+            assert_eq!(d, super::dist_1(&sim, &l, &r));
+            if l.len() <= 10 { assert_eq!(d, super::dist_naif(&sim, &l, &r)); }
+            let al = super::sol_1(&sim, &l, &r);
+            assert_eq!(cout_align(&sim, &al.0, &al.1), d);
+            let al = sol_2(&sim, &l, &r);
+            assert_eq!(cout_align(&sim, &al.0, &al.1), d);
+            assert_eq!(rm_gaps::<DnaSimilarity>(al.0), l);
+        }
+    }
+
+    #[test]
+    fn similarity_local(){
+        use crate::dna::DnaSimilarity;
+        use super::{dist_local, sol_local};
+        let sim = DnaSimilarity::default();
+
+        let (x, y) = (seq("TTTTACGTACGTTTTT"), seq("GGACGTACGGG"));
+        let al = sol_local(&sim, &x, &y);
+        assert_eq!(al.score, 7);
+        assert_eq!((al.x, al.y), (4..11, 2..9));
+        assert_eq!(al.align, Align(row("A C G T A C G"), row("A C G T A C G")));
+        assert_eq!(sol_local(&sim, &seq("AAAA"), &seq("TTTT")).score, 0);
+
+        // against every global alignment of every pair of substrings
+        let testcases = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 12)
+            .map(|(_, b)| b);
+        for DnaBlock(l, r) in testcases {
+            let mut brute = 0;
+            for i0 in 0..=l.len() { for i1 in i0..=l.len() {
+                for j0 in 0..=r.len() { for j1 in j0..=r.len() {
+                    brute = brute.max(dist_2(&sim, &l[i0..i1], &r[j0..j1]));
+                }}
+            }}
+            assert_eq!(dist_local(&sim, &l, &r), brute);
+            let al = sol_local(&sim, &l, &r);
+            assert_eq!(al.score, brute);
+            assert_eq!(cout_align(&sim, &al.align.0, &al.align.1), brute);
+            assert_eq!(rm_gaps::<DnaSimilarity>(al.align.0), l[al.x].to_vec());
+            assert_eq!(rm_gaps::<DnaSimilarity>(al.align.1), r[al.y].to_vec());
+        }
+    }
+
+    #[test]
+    fn similarity_overlap(){
+        use crate::dna::DnaSimilarity;
+        use super::{dist_overlap, sol_overlap};
+        let sim = DnaSimilarity::default();
+
+        let (x, y) = (seq("TTTTACGTACGT"), seq("ACGTACGTGGGG"));
+        let ov = sol_overlap(&sim, &x, &y);
+        assert_eq!(ov.score, 8);
+        assert_eq!((ov.x, ov.y), (4..12, 0..8));
+        assert_eq!(ov.align, Align(row("A C G T A C G T"), row("A C G T A C G T")));
+        // the overlap goes from the end of x to the start of y, not the other way around
+        assert_eq!(dist_overlap(&sim, &y, &x), 0);
+
+        // against every global alignment of a suffix of x with a prefix of y
+        let testcases = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 15)
+            .map(|(_, b)| b);
+        for DnaBlock(l, r) in testcases {
+            let mut brute = 0;
+            for i in 0..=l.len() {
+                for j in 0..=r.len() {
+                    brute = brute.max(dist_2(&sim, &l[i..], &r[..j]));
+                }
+            }
+            assert_eq!(dist_overlap(&sim, &l, &r), brute);
+            let ov = sol_overlap(&sim, &l, &r);
+            assert_eq!(ov.score, brute);
+            assert_eq!(ov.x.end, l.len());
+            assert_eq!(ov.y.start, 0);
+            assert_eq!(cout_align(&sim, &ov.align.0, &ov.align.1), brute);
+            assert_eq!(rm_gaps::<DnaSimilarity>(ov.align.0), l[ov.x].to_vec());
+            assert_eq!(rm_gaps::<DnaSimilarity>(ov.align.1), r[ov.y].to_vec());
+        }
+    }
+
+    #[test]
+    fn times(){
+        for k in 0..100 {
+            assert_eq!(super::times(3u64, k, 0), 3 * k as u64);
+            assert_eq!(super::times(-2i64, k, 0), -2 * k as i64);
+        }
+    }
+
     #[test]
     fn prog_dyn_dna(){
         // tests for prog_dyn are not needed since we already test sol_1 and dist_dp_full in other tests.
@@ -470,11 +1097,11 @@ mod tests {
     #[test]
     fn bonus_q30(){
         let DnaBlock(x, y) = read_test_inst("Instance_long_short.adn").expect("cannot read data");
-        let d = dist_2::<Dms>(x.as_slice(), y.as_slice());
-        let al = sol_2::<Dms>(x.as_slice(), y.as_slice());
-        let d2 = cout_align::<Dms>(al.0.as_slice(), al.1.as_slice());
+        let d = dist_2(&Dms, x.as_slice(), y.as_slice());
+        let al = sol_2(&Dms, x.as_slice(), y.as_slice());
+        let d2 = cout_align(&Dms, al.0.as_slice(), al.1.as_slice());
 
-        let ref_d = (x.len()-y.len()) as u64 *Dms::DEL;
+        let ref_d = (x.len()-y.len()) as u64 *Dms.del();
 
         assert_eq!(d, ref_d);
         assert_eq!(d2, ref_d);
@@ -0,0 +1,301 @@
+//! Substitution matrices for the DNA metric space, they can be written in code, loaded from a file
+//! or taken from one of the presets.
+//!
+//! The text format is the usual square table, with `#` comments and optional gap cost lines:
+//! ```text
+//! # transitions cost 1, transversions cost 2
+//!    A  C  G  T
+//! A  0  2  1  2
+//! C  2  0  2  1
+//! G  1  2  0  2
+//! T  2  1  2  0
+//! del 3
+//! ins 3
+//! ```
+//! The symbols may be any IUPAC nucleotide codes, as long as A, C, G and T are present.
+
+use simple_error::{SimpleError, bail};
+use std::error::Error;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::dna::{Dna, DnaSimilarity};
+use crate::math::{MetricSpace, Minimise};
+
+/// The nucleotide codes accepted as matrix symbols
+pub const IUPAC: &str = "ACGTURYSWKMBDHVN";
+
+/// A square matrix of substitution costs between nucleotide symbols, together with the gap costs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubstMatrix {
+    symbols: Vec<char>,
+    costs: Vec<u64>,
+    del: u64,
+    ins: u64,
+    /// position of A, C, T and G (in the order of [`Dna`]) among the symbols
+    lookup: [usize; 4],
+}
+
+impl SubstMatrix {
+    /// Build a matrix from its symbols and rows, `costs[i][j]` being the cost of substituting
+    /// `symbols[i]` by `symbols[j]`. The matrix is validated, it has to be square, symmetric,
+    /// cover A, C, G and T, and keeping a symbol can't cost more than substituting it.
+    pub fn new(symbols: &[char], costs: Vec<Vec<u64>>, del: u64, ins: u64) -> Result<Self, SimpleError> {
+        let symbols = symbols.iter().map(|c| c.to_ascii_uppercase()).collect::<Vec<_>>();
+        let n = symbols.len();
+
+        for (i, &s) in symbols.iter().enumerate() {
+            if !IUPAC.contains(s) { bail!("matrix error: \"{}\" is not a nucleotide code", s); }
+            if symbols[..i].contains(&s) { bail!("matrix error: symbol \"{}\" appears twice", s); }
+        }
+        let mut lookup = [0; 4];
+        for (k, s) in ['A', 'C', 'T', 'G'].into_iter().enumerate() {
+            lookup[k] = symbols.iter().position(|&x| x == s)
+                .ok_or_else(|| SimpleError::new(format!("matrix error: symbol \"{}\" is missing", s)))?;
+        }
+        if costs.len() != n { bail!("matrix error: {} rows given for {} symbols", costs.len(), n); }
+        for (i, row) in costs.iter().enumerate() {
+            if row.len() != n { bail!("matrix error: row \"{}\" has {} columns instead of {}", symbols[i], row.len(), n); }
+        }
+        for i in 0..n {
+            for j in 0..n {
+                if costs[i][j] != costs[j][i] {
+                    bail!("matrix error: not symmetric, {}/{} costs {} but {}/{} costs {}",
+                        symbols[i], symbols[j], costs[i][j], symbols[j], symbols[i], costs[j][i]);
+                }
+                if costs[i][j] < costs[i][i] {
+                    bail!("matrix error: substituting {} by {} is cheaper than keeping it", symbols[i], symbols[j]);
+                }
+            }
+        }
+
+        Ok(SubstMatrix { symbols, costs: costs.concat(), del, ins, lookup })
+    }
+
+    /// The scheme of the assignement, the same costs as [`crate::dna::DnaMetricSpace`]
+    pub fn course() -> Self {
+        Self::new(&['A', 'C', 'G', 'T'], vec![
+            vec![0, 4, 4, 3],
+            vec![4, 0, 3, 4],
+            vec![4, 3, 0, 4],
+            vec![3, 4, 4, 0],
+        ], 2, 2).expect("invalid preset!")
+    }
+
+    /// Transitions (A/G and C/T) cost `ts`, transversions cost `tv` and gaps cost `gap`
+    pub fn transition_transversion(ts: u64, tv: u64, gap: u64) -> Result<Self, SimpleError> {
+        Self::new(&['A', 'C', 'G', 'T'], vec![
+            vec![0, tv, ts, tv],
+            vec![tv, 0, tv, ts],
+            vec![ts, tv, 0, tv],
+            vec![tv, ts, tv, 0],
+        ], gap, gap)
+    }
+
+    /// Transition/transversion weighted preset, transitions cost 1, transversions 2 and gaps 3
+    pub fn kimura() -> Self {
+        Self::transition_transversion(1, 2, 3).expect("invalid preset!")
+    }
+
+    /// Every edit costs 1, which gives the edit (Levenshtein) distance
+    pub fn unit() -> Self {
+        Self::transition_transversion(1, 1, 1).expect("invalid preset!")
+    }
+
+    /// The symbols of the matrix, in the order of its rows
+    pub fn symbols(&self) -> &[char] {
+        &self.symbols
+    }
+
+    /// Cost of substituting the symbol a by b, if both are part of the matrix
+    pub fn cost(&self, a: char, b: char) -> Option<u64> {
+        let n = self.symbols.len();
+        let i = self.symbols.iter().position(|&x| x == a.to_ascii_uppercase())?;
+        let j = self.symbols.iter().position(|&x| x == b.to_ascii_uppercase())?;
+        Some(self.costs[i * n + j])
+    }
+
+    fn index(&self, d: Dna) -> usize {
+        match d {
+            Dna::A => self.lookup[0],
+            Dna::C => self.lookup[1],
+            Dna::T => self.lookup[2],
+            Dna::G => self.lookup[3],
+        }
+    }
+}
+
+impl MetricSpace for SubstMatrix {
+    type Cost = u64;
+    type Item = Dna;
+    type Goal = Minimise;
+
+    const ZEROCOST: Self::Cost = 0;
+    const INFCOST: Self::Cost = Self::Cost::MAX;
+
+    fn del(&self) -> Self::Cost { self.del }
+    fn ins(&self) -> Self::Cost { self.ins }
+
+    fn sub(&self, a: Self::Item, b: Self::Item) -> Self::Cost {
+        self.costs[self.index(a) * self.symbols.len() + self.index(b)]
+    }
+}
+
+impl FromStr for SubstMatrix {
+    type Err = Box<dyn Error>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut symbols: Option<Vec<char>> = None;
+        let mut rows = vec![];
+        let (mut del, mut ins) = (2, 2);
+
+        let lines = s
+            .lines()
+            .map(|l| l.split('#').next().unwrap_or("").trim())
+            .filter(|l| !l.is_empty());
+
+        for l in lines {
+            let mut ws = l.split_ascii_whitespace();
+            let first = ws.next().ok_or("matrix error: empty line")?;
+            match (first, &symbols) {
+                ("del", _) => del = ws.next().ok_or("matrix error: del cost is missing")?.parse()?,
+                ("ins", _) => ins = ws.next().ok_or("matrix error: ins cost is missing")?.parse()?,
+                (_, None) => symbols = Some(l.split_ascii_whitespace().map(parse_symbol).collect::<Result<_, _>>()?),
+                (_, Some(syms)) => {
+                    let label = parse_symbol(first)?;
+                    if syms.get(rows.len()) != Some(&label) {
+                        bail!(format!("matrix error: row \"{}\" is out of order", label));
+                    }
+                    rows.push(ws.map(|c| c.parse::<u64>()).collect::<Result<Vec<_>, _>>()?);
+                }
+            }
+        }
+
+        let symbols = symbols.ok_or("matrix error: the header line is missing")?;
+        Ok(SubstMatrix::new(&symbols, rows, del, ins)?)
+    }
+}
+
+fn parse_symbol(s: &str) -> Result<char, SimpleError> {
+    let mut cs = s.chars();
+    match (cs.next(), cs.next()) {
+        (Some(c), None) => Ok(c.to_ascii_uppercase()),
+        _ => bail!("matrix error: invalid symbol \"{}\"", s),
+    }
+}
+
+impl Display for SubstMatrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let n = self.symbols.len();
+        write!(f, " ")?;
+        for s in self.symbols.iter() {
+            write!(f, " {}", s)?;
+        }
+        writeln!(f)?;
+        for (i, s) in self.symbols.iter().enumerate() {
+            write!(f, "{}", s)?;
+            for c in &self.costs[i * n..(i + 1) * n] {
+                write!(f, " {}", c)?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "del {}", self.del)?;
+        writeln!(f, "ins {}", self.ins)
+    }
+}
+
+/// A scoring scheme chosen at run time, a distance given by a substitution matrix or a similarity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scheme {
+    Distance(SubstMatrix),
+    Similarity(DnaSimilarity),
+}
+
+impl Scheme {
+    /// The scheme of the given name: course, unit, kimura or similarity (with the default scores)
+    pub fn preset(name: &str) -> Result<Self, SimpleError> {
+        Ok(match name {
+            "course" => Scheme::Distance(SubstMatrix::course()),
+            "unit" => Scheme::Distance(SubstMatrix::unit()),
+            "kimura" => Scheme::Distance(SubstMatrix::kimura()),
+            "similarity" => Scheme::Similarity(DnaSimilarity::default()),
+            n => bail!("unknown scheme {}", n),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{Dna, DnaBlock, DnaMetricSpace as Dms};
+    use crate::io::read_test_insts_all;
+    use crate::math::{MetricSpace, dist_2};
+    use super::SubstMatrix;
+
+    #[test]
+    fn course_matches_dna_metric_space(){
+        use Dna::*;
+        let mat = SubstMatrix::course();
+        for a in [A, C, T, G] {
+            for b in [A, C, T, G] {
+                assert_eq!(mat.sub(a, b), Dms.sub(a, b), "{} / {}", a, b);
+            }
+        }
+        assert_eq!((mat.del(), mat.ins()), (Dms.del(), Dms.ins()));
+
+        for (_, DnaBlock(l, r)) in read_test_insts_all().take_while(|&(size, _)| size <= 100) {
+            assert_eq!(dist_2(&mat, &l, &r), dist_2(&Dms, &l, &r));
+        }
+    }
+
+    #[test]
+    fn parse_and_print(){
+        let txt = "
+            # transitions cost 1, transversions cost 2
+               A  C  G  T   N
+            A  0  2  1  2   1
+            C  2  0  2  1   1
+            G  1  2  0  2   1
+            T  2  1  2  0   1 # trailing comment
+            N  1  1  1  1   0
+            del 3
+            ins 4
+        ";
+        let mat = txt.parse::<SubstMatrix>().expect("cannot parse matrix!");
+        assert_eq!(mat.symbols(), &['A', 'C', 'G', 'T', 'N']);
+        assert_eq!(mat.cost('a', 'g'), Some(1));
+        assert_eq!(mat.cost('T', 'N'), Some(1));
+        assert_eq!(mat.cost('T', 'X'), None);
+        assert_eq!(mat.sub(Dna::C, Dna::T), 1);
+        assert_eq!((mat.del(), mat.ins()), (3, 4));
+
+        let again = mat.to_string().parse::<SubstMatrix>().expect("cannot parse printed matrix!");
+        assert_eq!(mat, again);
+    }
+
+    #[test]
+    fn presets(){
+        let k = SubstMatrix::kimura();
+        assert_eq!(k.sub(Dna::A, Dna::G), 1);
+        assert_eq!(k.sub(Dna::A, Dna::T), 2);
+        assert_eq!(k.del(), 3);
+        assert_eq!(dist_2(&SubstMatrix::unit(), &[Dna::A, Dna::C, Dna::G], &[Dna::C, Dna::G, Dna::G]), 2);
+    }
+
+    #[test]
+    fn malformed(){
+        let bad = [
+            "A C G\nA 0 1 1\nC 1 0 1\nG 1 1 0",                         // T is missing
+            "A C G T\nA 0 1 1 1\nC 1 0 1 1\nG 1 1 0 1",                 // a row is missing
+            "A C G T\nA 0 1 1 1\nC 1 0 1\nG 1 1 0 1\nT 1 1 1 0",       // short row
+            "A C G T\nA 0 1 1 1\nC 2 0 1 1\nG 1 1 0 1\nT 1 1 1 0",     // not symmetric
+            "A C G T\nA 5 1 1 1\nC 1 0 1 1\nG 1 1 0 1\nT 1 1 1 0",     // keeping A is too expensive
+            "A C G T\nA 0 1 1 1\nG 1 1 0 1\nC 1 0 1 1\nT 1 1 1 0",     // rows out of order
+            "A C G X\nA 0 1 1 1\nC 1 0 1 1\nG 1 1 0 1\nX 1 1 1 0",     // not a nucleotide
+            "A C G T A\nA 0 1 1 1 0",                                    // duplicated symbol
+            "A C G T\nA 0 1 1 -1\nC 1 0 1 1\nG 1 1 0 1\nT 1 1 1 0",    // negative cost
+            "del 2",                                                     // no header
+        ];
+        for s in bad {
+            assert!(s.parse::<SubstMatrix>().is_err(), "{:?} should be rejected", s);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! Multiple sequence alignment, computed by progressive alignment along a guide tree.
+//!
+//! The pairwise distances of the sequences are computed with dist_2, they give a guide tree 
+//! (UPGMA, see [`crate::phylo`])
+//! and the profiles of the groups of sequences are then aligned together from the leaves of the tree 
+//! up to its root (see [`crate::profile`]).
+
+use std::fmt::Display;
+
+use crate::math::*;
+use crate::phylo::{DistMatrix, Tree, upgma};
+use crate::profile::{Profile, align_profiles};
+
+/// A multiple alignment, the rows are the sequences (in the order they were given) interleaved with gaps.
+/// All the rows have the same length.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MultiAlign<M: MetricSpace>(pub Vec<Vec<Gapped<M::Item>>>);
+
+impl<M: MetricSpace> MultiAlign<M> {
+    /// The number of columns of the alignment
+    pub fn len(&self) -> usize {
+        self.0.first().map_or(0, |r| r.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The entries of the column j, one per row
+    pub fn column(&self, j: usize) -> Vec<Gapped<M::Item>> {
+        self.0.iter().map(|r| r[j]).collect()
+    }
+
+    /// The sum-of-pairs cost of the alignment, the sum of the costs of all the pairs of rows,
+    /// where a pair of gaps costs nothing
+    pub fn sp_cost(&self, ms: &M) -> M::Cost {
+        let mut c = M::ZEROCOST;
+        for (k, a) in self.0.iter().enumerate() {
+            for b in &self.0[k + 1..] {
+                for (&x, &y) in a.iter().zip(b) {
+                    c = c + pair_cost(ms, x, y);
+                }
+            }
+        }
+        c
+    }
+}
+
+impl<M: MetricSpace> MultiAlign<M>
+where M::Item: PartialEq
+{
+    /// The most common entry of the column j (gaps included) and its number of occurences,
+    /// the first one in the order of the rows wins ties
+    fn majority(&self, j: usize, with_gaps: bool) -> Option<(Gapped<M::Item>, usize)> {
+        let mut counts: Vec<(Gapped<M::Item>, usize)> = vec![];
+        for e in self.column(j).into_iter().filter(|e| with_gaps || !e.is_gap()) {
+            match counts.iter_mut().find(|(x, _)| *x == e) {
+                Some((_, c)) => *c += 1,
+                None => counts.push((e, 1)),
+            }
+        }
+        counts.into_iter().reduce(|a, b| if b.1 > a.1 { b } else { a })
+    }
+
+    /// The consensus row, the most common entry of every column (which may be a gap)
+    pub fn consensus(&self) -> Vec<Gapped<M::Item>> {
+        (0..self.len())
+            .map(|j| self.majority(j, true).expect("empty column!").0)
+            .collect()
+    }
+
+    /// The conservation of the column j, the fraction of the rows holding its most common item.
+    /// Gaps are never conserved, a column of gaps has a conservation of 0.
+    pub fn conservation(&self, j: usize) -> f64 {
+        self.majority(j, false).map_or(0.0, |(_, c)| c as f64 / self.0.len() as f64)
+    }
+}
+
+impl<M: MetricSpace> Display for MultiAlign<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for r in self.0.iter() {
+            write!(f, "\n| ")?;
+            for a in r.iter() {
+                write!(f, "{} ", a)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Cost of a pair of entries in the sum-of-pairs cost
+fn pair_cost<M: MetricSpace>(ms: &M, a: Gapped<M::Item>, b: Gapped<M::Item>) -> M::Cost {
+    match (a, b) {
+        (Gapped::Gap, Gapped::Gap) => M::ZEROCOST,
+        (Gapped::Gap, _) => ms.ins(),
+        (_, Gapped::Gap) => ms.del(),
+        (Gapped::Item(x), Gapped::Item(y)) => ms.sub(x, y),
+    }
+}
+
+/// A rooted binary tree whose leaves are the indices of the sequences,
+/// the sequences are aligned in the order given by the tree
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum GuideTree {
+    Leaf(usize),
+    Node(Box<GuideTree>, Box<GuideTree>),
+}
+
+/// Build the guide tree of a distance matrix with UPGMA (see [`upgma`]),
+/// the leaves are the indices of the rows of the matrix
+pub fn guide_tree(d: &DistMatrix) -> GuideTree {
+    fn convert(t: &Tree) -> GuideTree {
+        match t.children.as_slice() {
+            [] => GuideTree::Leaf(t.name.as_ref().and_then(|s| s.parse().ok()).expect("invalid leaf!")),
+            [l, r] => GuideTree::Node(Box::new(convert(l)), Box::new(convert(r))),
+            _ => panic!("UPGMA trees are binary!"),
+        }
+    }
+    convert(&upgma(&d.clone().with_names(&(0..d.len()).collect::<Vec<_>>())))
+}
+
+/// Align the sequences of a subtree, the indices of the sequences are returned in the order of the rows
+fn align_tree<M>(ms: &M, seqs: &[Vec<M::Item>], t: &GuideTree) -> (Vec<usize>, Profile<M>)
+where M: MetricSpace, M::Item: PartialEq
+{
+    match t {
+        GuideTree::Leaf(i) => (vec![*i], Profile::from_seq(&seqs[*i])),
+        GuideTree::Node(l, r) => {
+            let (mut li, lp) = align_tree(ms, seqs, l);
+            let (mut ri, rp) = align_tree(ms, seqs, r);
+            li.append(&mut ri);
+            (li, align_profiles(ms, &lp, &rp))
+        }
+    }
+}
+
+/// Align several sequences by progressive alignment along the guide tree of their dist_2 distances
+pub fn msa<M>(ms: &M, seqs: &[Vec<M::Item>]) -> MultiAlign<M>
+where M: MetricSpace + Sync, M::Cost: ToF64, M::Item: PartialEq + Sync
+{
+    if seqs.is_empty() { return MultiAlign(vec![]); }
+    let tree = guide_tree(&DistMatrix::compute(ms, seqs));
+    msa_tree(ms, seqs, &tree)
+}
+
+/// Align several sequences by progressive alignment along the given guide tree
+pub fn msa_tree<M>(ms: &M, seqs: &[Vec<M::Item>], tree: &GuideTree) -> MultiAlign<M>
+where M: MetricSpace, M::Item: PartialEq
+{
+    let (order, p) = align_tree(ms, seqs, tree);
+    let mut rows = order.into_iter().zip(p.into_multi_align().0).collect::<Vec<_>>();
+    rows.sort_by_key(|&(i, _)| i);
+    MultiAlign(rows.into_iter().map(|(_, r)| r).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{Dna, DnaBlock, DnaMetricSpace as Dms, DnaSimilarity};
+    use crate::io::read_test_insts_all;
+    use crate::math::{Gapped, dist_2, rm_gaps};
+    use super::{GuideTree, MultiAlign, msa, guide_tree};
+
+    fn seq(s: &str) -> Vec<Dna> {
+        s.chars().map(|c| c.to_string().parse().expect("invalid sequence!")).collect()
+    }
+
+    fn check<M>(ms: &M, seqs: &[Vec<Dna>], al: &MultiAlign<M>)
+    where M: crate::math::MetricSpace<Item = Dna>
+    {
+        assert_eq!(al.0.len(), seqs.len());
+        for (r, s) in al.0.iter().zip(seqs) {
+            assert_eq!(r.len(), al.len(), "rows of different lengths");
+            assert_eq!(&rm_gaps::<M>(r.clone()), s, "some letters 🍪 got eaten in msa");
+        }
+        for j in 0..al.len() {
+            assert!(al.column(j).iter().any(|e| !e.is_gap()), "column {} only has gaps", j);
+        }
+        let _ = al.sp_cost(ms);
+    }
+
+    #[test]
+    fn two_sequences_are_optimal(){
+        let testcases = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 100)
+            .map(|(_, b)| b);
+        for DnaBlock(l, r) in testcases {
+            let seqs = vec![l, r];
+            let al = msa(&Dms, &seqs);
+            check(&Dms, &seqs, &al);
+            assert_eq!(al.sp_cost(&Dms), dist_2(&Dms, &seqs[0], &seqs[1]));
+        }
+    }
+
+    #[test]
+    fn guide_tree_upgma(){
+        let d = crate::phylo::DistMatrix::from_rows(&[
+            vec![0.0, 2.0, 8.0, 9.0],
+            vec![2.0, 0.0, 7.0, 9.0],
+            vec![8.0, 7.0, 0.0, 3.0],
+            vec![9.0, 9.0, 3.0, 0.0],
+        ]).unwrap();
+        use GuideTree::*;
+        let node = |a, b| Node(Box::new(a), Box::new(b));
+        assert_eq!(guide_tree(&d), node(node(Leaf(0), Leaf(1)), node(Leaf(2), Leaf(3))));
+    }
+
+    #[test]
+    fn consensus_and_conservation(){
+        let seqs = vec![seq("ACGTACGT"), seq("ACGTACGT"), seq("ACGAACGT"), seq("ACGTCGT")];
+        let al = msa(&Dms, &seqs);
+        check(&Dms, &seqs, &al);
+        assert_eq!(rm_gaps::<Dms>(al.consensus()), seq("ACGTACGT"));
+        assert_eq!(al.len(), 8);
+        assert_eq!(al.conservation(0), 1.0);
+        assert_eq!(al.conservation(3), 0.75);
+        let gapped = (0..al.len()).find(|&j| al.column(j).contains(&Gapped::Gap)).expect("no gap!");
+        assert_eq!(al.conservation(gapped), 0.75);
+    }
+
+    #[test]
+    fn many_related_sequences(){
+        let seqs = read_test_insts_all()
+            .skip_while(|&(size, _)| size < 50)
+            .take_while(|&(size, _)| size <= 100)
+            .flat_map(|(_, DnaBlock(l, r))| [l, r])
+            .collect::<Vec<_>>();
+        let al = msa(&Dms, &seqs);
+        check(&Dms, &seqs, &al);
+
+        let sim = DnaSimilarity::default();
+        let al = msa(&sim, &seqs);
+        check(&sim, &seqs, &al);
+    }
+}
@@ -0,0 +1,452 @@
+//! Writers of alignments in machine and human readable formats.
+//!
+//! Every writer takes the alignments one at a time as [`AlignRecord`]s and writes them to its output
+//! straight away, so that a batch can be streamed:
+//! - [`PairwiseWriter`], the BLAST-like pairwise layout, the rows wrapped with a match line and coordinates;
+//! - [`TsvWriter`], one tab separated summary line per alignment, after a header line;
+//! - [`JsonLinesWriter`], one JSON object per line, with the CIGAR and the statistics of the alignment.
+//!
+//! The first sequence of an alignment plays the part of the reference: in the CIGAR, a letter of the second
+//! sequence against a gap is an insertion (I) and a letter of the first one against a gap a deletion (D),
+//! matches are written = and mismatches X.
+
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::math::*;
+
+/// An alignment of the parts x[x_range] and y[y_range] of two named sequences, with its cost
+pub struct AlignRecord<'a, M: MetricSpace> {
+    pub x_name: &'a str,
+    pub y_name: &'a str,
+    pub x_range: Range<usize>,
+    pub y_range: Range<usize>,
+    pub cost: M::Cost,
+    pub align: &'a Align<M>,
+}
+
+impl<'a, M: MetricSpace> AlignRecord<'a, M> {
+    /// A record of the alignment of the whole sequences
+    pub fn global(x_name: &'a str, y_name: &'a str, cost: M::Cost, align: &'a Align<M>) -> Self {
+        let x_range = 0..align.0.iter().filter(|g| g.item().is_some()).count();
+        let y_range = 0..align.1.iter().filter(|g| g.item().is_some()).count();
+        AlignRecord { x_name, y_name, x_range, y_range, cost, align }
+    }
+}
+
+/// The counts of the columns of an alignment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AlignStats {
+    pub length: usize,
+    pub matches: usize,
+    pub mismatches: usize,
+    /// the columns holding a gap
+    pub gaps: usize,
+    /// the runs of gaps in either row
+    pub gap_opens: usize,
+}
+
+impl AlignStats {
+    pub fn new<M>(align: &Align<M>) -> Self
+    where M: MetricSpace, M::Item: PartialEq
+    {
+        let mut s = AlignStats { length: align.0.len(), ..Default::default() };
+        let mut prev = (false, false);
+        for (a, b) in align.0.iter().zip(&align.1) {
+            let gaps = (a.item().is_none(), b.item().is_none());
+            match (a, b) {
+                (Gapped::Item(a), Gapped::Item(b)) if a == b => s.matches += 1,
+                (Gapped::Item(_), Gapped::Item(_)) => s.mismatches += 1,
+                _ => s.gaps += 1,
+            }
+            s.gap_opens += (gaps.0 && !prev.0) as usize + (gaps.1 && !prev.1) as usize;
+            prev = gaps;
+        }
+        s
+    }
+
+    /// The proportion of matches among the columns, 0 for an empty alignment
+    pub fn identity(&self) -> f64 {
+        if self.length == 0 { 0. } else { self.matches as f64 / self.length as f64 }
+    }
+}
+
+/// The CIGAR string of the alignment, with the operations =, X, I and D
+pub fn cigar<M>(align: &Align<M>) -> String
+where M: MetricSpace, M::Item: PartialEq
+{
+    let ops = align.0.iter().zip(&align.1).filter_map(|(a, b)| match (a, b) {
+        (Gapped::Item(a), Gapped::Item(b)) => Some(if a == b { '=' } else { 'X' }),
+        (Gapped::Gap, Gapped::Item(_)) => Some('I'),
+        (Gapped::Item(_), Gapped::Gap) => Some('D'),
+        (Gapped::Gap, Gapped::Gap) => None,
+    });
+    let mut res = String::new();
+    let mut run: Option<(char, usize)> = None;
+    for op in ops {
+        match &mut run {
+            Some((c, n)) if *c == op => *n += 1,
+            _ => {
+                if let Some((c, n)) = run { res += &format!("{}{}", n, c); }
+                run = Some((op, 1));
+            }
+        }
+    }
+    if let Some((c, n)) = run { res += &format!("{}{}", n, c); }
+    res
+}
+
+/// A writer of alignments
+pub trait AlignWriter<M: MetricSpace> {
+    /// Write one alignment
+    fn write(&mut self, rec: &AlignRecord<M>) -> io::Result<()>;
+    /// Report that the alignment number `index` of a batch could not be computed
+    fn write_error(&mut self, index: usize, error: &str) -> io::Result<()>;
+    /// Write what remains after the last alignment and flush the output
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// The BLAST-like pairwise layout: a summary of every alignment followed by its rows wrapped
+/// every `width` columns, with a match line between them and the positions (from 1) of the first
+/// and last letters of every line
+pub struct PairwiseWriter<W: Write> {
+    out: W,
+    width: usize,
+}
+
+impl<W: Write> PairwiseWriter<W> {
+    pub fn new(out: W, width: usize) -> Self {
+        assert!(width > 0, "the width must be positive!");
+        PairwiseWriter { out, width }
+    }
+}
+
+impl<W: Write, M> AlignWriter<M> for PairwiseWriter<W>
+where M: MetricSpace, M::Item: PartialEq
+{
+    fn write(&mut self, rec: &AlignRecord<M>) -> io::Result<()> {
+        let (al, s) = (rec.align, AlignStats::new(rec.align));
+        let percent = |n: usize| (100 * n).checked_div(s.length).unwrap_or(0);
+        writeln!(self.out, "Query= {} ({} letters)", rec.x_name, rec.x_range.len())?;
+        writeln!(self.out, "Sbjct= {} ({} letters)", rec.y_name, rec.y_range.len())?;
+        writeln!(
+            self.out,
+            " Cost = {:?}, Identities = {}/{} ({}%), Gaps = {}/{} ({}%)",
+            rec.cost, s.matches, s.length, percent(s.matches), s.gaps, s.length, percent(s.gaps)
+        )?;
+        let digits = rec.x_range.end.max(rec.y_range.end).to_string().len();
+        let (mut px, mut py) = (rec.x_range.start, rec.y_range.start);
+        for (rx, ry) in al.0.chunks(self.width).zip(al.1.chunks(self.width)) {
+            let line = |out: &mut W, label: &str, row: &[Gapped<M::Item>], p: &mut usize| {
+                let n = row.iter().filter(|g| g.item().is_some()).count();
+                let first = if n == 0 { *p } else { *p + 1 };
+                *p += n;
+                writeln!(out, "{}  {:>d$}  {}  {}", label, first, row.iter().map(|g| g.to_string()).collect::<String>(), *p, d = digits)
+            };
+            writeln!(self.out)?;
+            line(&mut self.out, "Query", rx, &mut px)?;
+            let matches = rx.iter().zip(ry).map(|(a, b)| match (a, b) {
+                (Gapped::Item(a), Gapped::Item(b)) if a == b => '|',
+                _ => ' ',
+            });
+            writeln!(self.out, "{}{}", " ".repeat(9 + digits), matches.collect::<String>().trim_end())?;
+            line(&mut self.out, "Sbjct", ry, &mut py)?;
+        }
+        writeln!(self.out)
+    }
+
+    fn write_error(&mut self, index: usize, error: &str) -> io::Result<()> {
+        writeln!(self.out, "Error= alignment {}: {}\n", index, error)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// The columns of [`TsvWriter`]
+pub const TSV_HEADER: &str = "x\ty\tx_start\tx_end\ty_start\ty_end\tcost\tlength\tmatches\tmismatches\tgaps\tgap_opens\tidentity\tcigar";
+
+/// A text as a single TSV field, the tabs and line breaks it holds are turned into spaces
+pub(crate) fn tsv_field(s: &str) -> String {
+    s.replace(['\t', '\n', '\r'], " ")
+}
+
+/// One tab separated line per alignment, see [`TSV_HEADER`], the positions start from 0 and the ends are excluded.
+/// Errors are comment lines starting with '#'
+pub struct TsvWriter<W: Write> {
+    out: W,
+    header: bool,
+}
+
+impl<W: Write> TsvWriter<W> {
+    pub fn new(out: W) -> Self {
+        TsvWriter { out, header: false }
+    }
+
+    fn header(&mut self) -> io::Result<()> {
+        if !self.header {
+            self.header = true;
+            writeln!(self.out, "{}", TSV_HEADER)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write, M> AlignWriter<M> for TsvWriter<W>
+where M: MetricSpace, M::Item: PartialEq
+{
+    fn write(&mut self, rec: &AlignRecord<M>) -> io::Result<()> {
+        self.header()?;
+        let s = AlignStats::new(rec.align);
+        writeln!(
+            self.out,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{:?}\t{}\t{}\t{}\t{}\t{}\t{:.4}\t{}",
+            tsv_field(rec.x_name), tsv_field(rec.y_name), rec.x_range.start, rec.x_range.end, rec.y_range.start, rec.y_range.end,
+            rec.cost, s.length, s.matches, s.mismatches, s.gaps, s.gap_opens, s.identity(), cigar(rec.align)
+        )
+    }
+
+    fn write_error(&mut self, index: usize, error: &str) -> io::Result<()> {
+        self.header()?;
+        writeln!(self.out, "# error\t{}\t{}", index, tsv_field(error))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.header()?;
+        self.out.flush()
+    }
+}
+
+/// A string as a JSON string
+fn json_string(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => res += "\\\"",
+            '\\' => res += "\\\\",
+            '\n' => res += "\\n",
+            '\r' => res += "\\r",
+            '\t' => res += "\\t",
+            c if (c as u32) < 0x20 => res += &format!("\\u{:04x}", c as u32),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// One JSON object per line and per alignment, with the fields of [`TSV_HEADER`] and the two rows.
+/// Errors are objects with the fields index and error
+pub struct JsonLinesWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    pub fn new(out: W) -> Self {
+        JsonLinesWriter { out }
+    }
+}
+
+impl<W: Write, M> AlignWriter<M> for JsonLinesWriter<W>
+where M: MetricSpace, M::Item: PartialEq
+{
+    fn write(&mut self, rec: &AlignRecord<M>) -> io::Result<()> {
+        let s = AlignStats::new(rec.align);
+        let row = |r: &[Gapped<M::Item>]| json_string(&r.iter().map(|g| g.to_string()).collect::<String>());
+        writeln!(
+            self.out,
+            "{{\"x\":{},\"y\":{},\"x_start\":{},\"x_end\":{},\"y_start\":{},\"y_end\":{},\"cost\":{:?},\
+            \"length\":{},\"matches\":{},\"mismatches\":{},\"gaps\":{},\"gap_opens\":{},\"identity\":{:.4},\
+            \"cigar\":\"{}\",\"x_row\":{},\"y_row\":{}}}",
+            json_string(rec.x_name), json_string(rec.y_name), rec.x_range.start, rec.x_range.end, rec.y_range.start,
+            rec.y_range.end, rec.cost, s.length, s.matches, s.mismatches, s.gaps, s.gap_opens, s.identity(),
+            cigar(rec.align), row(&rec.align.0), row(&rec.align.1)
+        )
+    }
+
+    fn write_error(&mut self, index: usize, error: &str) -> io::Result<()> {
+        writeln!(self.out, "{{\"index\":{},\"error\":{}}}", index, json_string(error))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// The formats of the writers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Pairwise,
+    Tsv,
+    JsonLines,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pairwise" => Ok(OutputFormat::Pairwise),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "jsonl" => Ok(OutputFormat::JsonLines),
+            s => Err(format!("unknown output format {}, expected pairwise, tsv or jsonl", s)),
+        }
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            OutputFormat::Pairwise => "pairwise",
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::JsonLines => "jsonl",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A writer of the given format, the pairwise layout being wrapped every 60 columns
+pub fn writer<'w, M, W>(format: OutputFormat, out: W) -> Box<dyn AlignWriter<M> + 'w>
+where M: MetricSpace, M::Item: PartialEq, W: Write + 'w
+{
+    match format {
+        OutputFormat::Pairwise => Box::new(PairwiseWriter::new(out, 60)),
+        OutputFormat::Tsv => Box::new(TsvWriter::new(out)),
+        OutputFormat::JsonLines => Box::new(JsonLinesWriter::new(out)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{Dna, DnaMetricSpace as Dms};
+    use crate::math::{Align, Gapped, sol_2};
+    use crate::rng::Rng;
+    use super::{AlignRecord, AlignStats, AlignWriter, JsonLinesWriter, OutputFormat, PairwiseWriter, TsvWriter, cigar, writer};
+
+    fn row(s: &str) -> Vec<Gapped<Dna>> {
+        s.chars().map(|c| c.to_string().parse().expect("invalid row!")).collect()
+    }
+
+    fn output<F: FnOnce(&mut Vec<u8>)>(f: F) -> String {
+        let mut out = vec![];
+        f(&mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn stats_and_cigar(){
+        let al = Align::<Dms>(row("TATATGAGTC"), row("TAT-T---TA"));
+        assert_eq!(cigar(&al), "3=1D1=3D1=1X");
+        let s = AlignStats::new(&al);
+        assert_eq!(s, AlignStats { length: 10, matches: 5, mismatches: 1, gaps: 4, gap_opens: 2 });
+        assert_eq!(s.identity(), 0.5);
+        assert_eq!(cigar(&Align::<Dms>(row("--AC"), row("GGA-"))), "2I1=1D");
+        assert_eq!(AlignStats::new(&Align::<Dms>(row("A-C-"), row("-G-T"))).gap_opens, 4);
+        assert_eq!(cigar(&Align::<Dms>(vec![], vec![])), "");
+
+        // the CIGAR gives back the lengths of the sequences
+        let mut rng = Rng::new(43);
+        let letters = [Dna::A, Dna::C, Dna::G, Dna::T];
+        for _ in 0..20 {
+            let x = (0..rng.below(50)).map(|_| letters[rng.below(4) as usize]).collect::<Vec<_>>();
+            let y = (0..rng.below(50)).map(|_| letters[rng.below(4) as usize]).collect::<Vec<_>>();
+            let c = cigar(&sol_2(&Dms, &x, &y));
+            let (mut lx, mut ly, mut n) = (0, 0, 0);
+            for ch in c.chars() {
+                match ch {
+                    '0'..='9' => n = 10 * n + ch.to_digit(10).unwrap() as usize,
+                    op => {
+                        if op != 'I' { lx += n; }
+                        if op != 'D' { ly += n; }
+                        n = 0;
+                    }
+                }
+            }
+            assert_eq!((lx, ly), (x.len(), y.len()));
+        }
+    }
+
+    #[test]
+    fn writers(){
+        let al = Align::<Dms>(row("TATATGAGTC"), row("TAT-T---TA"));
+        let rec = AlignRecord::global("x", "y", 11, &al);
+        assert_eq!((rec.x_range.clone(), rec.y_range.clone()), (0..10, 0..6));
+
+        let pairwise = output(|out| {
+            let mut w = PairwiseWriter::new(out, 4);
+            w.write(&rec).unwrap();
+            AlignWriter::<Dms>::finish(&mut w).unwrap();
+        });
+        assert_eq!(pairwise, "\
+Query= x (10 letters)
+Sbjct= y (6 letters)
+ Cost = 11, Identities = 5/10 (50%), Gaps = 4/10 (40%)
+
+Query   1  TATA  4
+           |||
+Sbjct   1  TAT-  3
+
+Query   5  TGAG  8
+           |
+Sbjct   4  T---  4
+
+Query   9  TC  10
+           |
+Sbjct   5  TA  6
+
+");
+
+        let tsv = output(|out| {
+            let mut w = TsvWriter::new(out);
+            w.write(&rec).unwrap();
+            w.write(&AlignRecord { x_name: "a", y_name: "b", x_range: 3..5, y_range: 7..9, cost: 0, align: &Align::<Dms>(row("AC"), row("AC")) }).unwrap();
+            AlignWriter::<Dms>::finish(&mut w).unwrap();
+        });
+        let lines = tsv.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0].split('\t').count(), lines[1].split('\t').count());
+        assert_eq!(lines[1], "x\ty\t0\t10\t0\t6\t11\t10\t5\t1\t4\t2\t0.5000\t3=1D1=3D1=1X");
+        assert_eq!(lines[2], "a\tb\t3\t5\t7\t9\t0\t2\t2\t0\t0\t0\t1.0000\t2=");
+        assert_eq!(output(|out| AlignWriter::<Dms>::finish(&mut TsvWriter::new(out)).unwrap()).lines().count(), 1);
+
+        // names and errors can't break the columns
+        let tsv = output(|out| {
+            let mut w = TsvWriter::new(out);
+            w.write(&AlignRecord::global("x\t1", "y\r\n2", 11, &al)).unwrap();
+            AlignWriter::<Dms>::write_error(&mut w, 1, "bad\tline\n").unwrap();
+        });
+        let lines = tsv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("x 1\ty  2\t0\t10\t"), "{}", lines[1]);
+        assert_eq!(lines[2], "# error\t1\tbad line ");
+
+        let json = output(|out| {
+            let mut w = JsonLinesWriter::new(out);
+            w.write(&AlignRecord::global("x \"1\"", "y\t2", 11, &al)).unwrap();
+        });
+        assert_eq!(json, "{\"x\":\"x \\\"1\\\"\",\"y\":\"y\\t2\",\"x_start\":0,\"x_end\":10,\"y_start\":0,\"y_end\":6,\"cost\":11,\
+            \"length\":10,\"matches\":5,\"mismatches\":1,\"gaps\":4,\"gap_opens\":2,\"identity\":0.5000,\
+            \"cigar\":\"3=1D1=3D1=1X\",\"x_row\":\"TATATGAGTC\",\"y_row\":\"TAT-T---TA\"}\n");
+
+        for f in ["pairwise", "tsv", "jsonl"] {
+            let format = f.parse::<OutputFormat>().unwrap();
+            assert_eq!(format.to_string(), f);
+            let out = output(|out| {
+                let mut w = writer::<Dms, _>(format, out);
+                w.write(&rec).unwrap();
+                w.finish().unwrap();
+            });
+            assert!(out.contains("TATATGAGTC") || out.contains("3=1D1=3D1=1X"), "{}", out);
+        }
+        assert!("xml".parse::<OutputFormat>().is_err());
+
+        let errors = ["pairwise", "tsv", "jsonl"].map(|f| output(|out| {
+            writer::<Dms, _>(f.parse().unwrap(), out).write_error(3, "no \"file\"").unwrap();
+        }));
+        assert_eq!(errors, [
+            "Error= alignment 3: no \"file\"\n\n".to_string(),
+            format!("{}\n# error\t3\tno \"file\"\n", super::TSV_HEADER),
+            "{\"index\":3,\"error\":\"no \\\"file\\\"\"}\n".to_string(),
+        ]);
+    }
+}
@@ -0,0 +1,449 @@
+//! Distance matrices and phylogenetic trees.
+//!
+//! The distances between all the pairs of sequences are computed in parallel with dist_2,
+//! the trees are built from them with UPGMA or neighbor-joining, and they can be written and read
+//! in the Newick format.
+
+use simple_error::{SimpleError, bail};
+use std::error::Error;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::math::*;
+
+/// A symmetric matrix of distances between named sequences, with zeros on the diagonal.
+/// Only the lower triangle is stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistMatrix {
+    names: Vec<String>,
+    d: Vec<f64>,
+}
+
+impl DistMatrix {
+    /// A matrix of zeros, the sequences are named by their index
+    pub fn new(n: usize) -> Self {
+        DistMatrix { names: (0..n).map(|i| i.to_string()).collect(), d: vec![0.0; n * n.saturating_sub(1) / 2] }
+    }
+
+    /// Build a matrix from its rows, they have to form a symmetric matrix with zeros on the diagonal
+    pub fn from_rows(rows: &[Vec<f64>]) -> Result<Self, SimpleError> {
+        let mut m = Self::new(rows.len());
+        for (i, r) in rows.iter().enumerate() {
+            if r.len() != rows.len() { bail!("distance matrix error: row {} has {} columns instead of {}", i, r.len(), rows.len()); }
+            if r[i] != 0.0 { bail!("distance matrix error: the distance from {} to itself is not 0", i); }
+            for (j, &x) in r.iter().enumerate().take(i) {
+                if x != rows[j][i] { bail!("distance matrix error: not symmetric, {}/{} is {} but {}/{} is {}", i, j, x, j, i, rows[j][i]); }
+                m.set(i, j, x);
+            }
+        }
+        Ok(m)
+    }
+
+    /// Compute the distances between all the pairs of sequences with dist_2, on all the available cores.
+    /// For a similarity the scores are turned into distances (see [`Objective::distance`]).
+    pub fn compute<M>(ms: &M, seqs: &[Vec<M::Item>]) -> Self
+    where M: MetricSpace + Sync, M::Item: Sync, M::Cost: ToF64
+    {
+        let n = seqs.len();
+        let workers = thread::available_parallelism().map_or(1, |w| w.get()).min(n.max(1));
+        let next = AtomicUsize::new(0);
+
+        let rows = thread::scope(|s| {
+            let handles = (0..workers)
+                .map(|_| s.spawn(|| {
+                    // every worker takes the next row, the rows are the pairs (i, j) with j < i
+                    let mut done = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= n { break done; }
+                        let row = (0..i)
+                            .map(|j| M::Goal::distance(dist_2(ms, &seqs[i], &seqs[j]).to_f64()))
+                            .collect::<Vec<_>>();
+                        done.push((i, row));
+                    }
+                }))
+                .collect::<Vec<_>>();
+            handles.into_iter().flat_map(|h| h.join().expect("a worker panicked!")).collect::<Vec<_>>()
+        });
+
+        let mut m = Self::new(n);
+        for (i, row) in rows {
+            for (j, x) in row.into_iter().enumerate() {
+                m.set(i, j, x);
+            }
+        }
+        m
+    }
+
+    /// Same matrix with the given names for the sequences
+    pub fn with_names<S: ToString>(mut self, names: &[S]) -> Self {
+        assert_eq!(names.len(), self.len(), "one name per sequence is required!");
+        self.names = names.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// The number of sequences
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn index(i: usize, j: usize) -> usize {
+        let (i, j) = if i > j { (i, j) } else { (j, i) };
+        i * (i - 1) / 2 + j
+    }
+
+    /// The distance between the sequences i and j
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        if i == j { 0.0 } else { self.d[Self::index(i, j)] }
+    }
+
+    /// Set the distance between the sequences i and j (and j and i)
+    pub fn set(&mut self, i: usize, j: usize, x: f64) {
+        assert_ne!(i, j, "the diagonal of a distance matrix is always 0!");
+        self.d[Self::index(i, j)] = x;
+    }
+
+    fn to_rows(&self) -> Vec<Vec<f64>> {
+        (0..self.len()).map(|i| (0..self.len()).map(|j| self.get(i, j)).collect()).collect()
+    }
+}
+
+/// A phylogenetic tree, the leaves have no children.
+/// The names and the lengths of the branches leading to the nodes are optional, as in the Newick format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tree {
+    pub name: Option<String>,
+    pub length: Option<f64>,
+    pub children: Vec<Tree>,
+}
+
+impl Tree {
+    /// A leaf with a name
+    pub fn leaf(name: &str) -> Self {
+        Tree { name: Some(name.to_string()), length: None, children: vec![] }
+    }
+
+    /// An unnamed node with the given children
+    pub fn node(children: Vec<Tree>) -> Self {
+        Tree { name: None, length: None, children }
+    }
+
+    /// Same tree with the given branch length
+    pub fn with_length(mut self, length: f64) -> Self {
+        self.length = Some(length);
+        self
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// The names of the leaves, from left to right
+    pub fn leaves(&self) -> Vec<&str> {
+        if self.is_leaf() { return self.name.iter().map(|s| s.as_str()).collect(); }
+        self.children.iter().flat_map(|c| c.leaves()).collect()
+    }
+}
+
+/// Build a rooted tree with UPGMA, the two closest clusters are merged until only one is left,
+/// the distance to a cluster is the average distance to its sequences.
+/// The tree is ultrametric, every merge happens at half the distance between the clusters. O(n^3) time
+pub fn upgma(dm: &DistMatrix) -> Tree {
+    assert!(!dm.is_empty(), "no sequences given!");
+    let mut d = dm.to_rows();
+    // the clusters with their tree, size and height
+    let mut clusters = dm.names.iter().map(|s| Some((Tree::leaf(s), 1, 0.0))).collect::<Vec<_>>();
+
+    for _ in 1..d.len() {
+        let mut closest: Option<(usize, usize)> = None;
+        for i in 0..d.len() {
+            for j in i + 1..d.len() {
+                if clusters[i].is_none() || clusters[j].is_none() { continue; }
+                if closest.is_none_or(|(a, b)| d[i][j] < d[a][b]) { closest = Some((i, j)); }
+            }
+        }
+        let (i, j) = closest.expect("no clusters left!");
+        let (ti, ni, hi) = clusters[i].take().unwrap();
+        let (tj, nj, hj) = clusters[j].take().unwrap();
+        let h = d[i][j] / 2.0;
+
+        let merged = d[i].iter().zip(&d[j])
+            .map(|(di, dj)| (di * ni as f64 + dj * nj as f64) / (ni + nj) as f64)
+            .collect::<Vec<_>>();
+        for (k, dk) in merged.into_iter().enumerate() {
+            d[i][k] = dk;
+            d[k][i] = dk;
+        }
+        clusters[i] = Some((Tree::node(vec![ti.with_length(h - hi), tj.with_length(h - hj)]), ni + nj, h));
+    }
+
+    clusters.into_iter().flatten().next().unwrap().0
+}
+
+/// Build an unrooted tree with neighbor-joining, the root of the returned tree is the last node created,
+/// it has three children (or less when there are less than three sequences). O(n^3) time
+pub fn neighbor_joining(dm: &DistMatrix) -> Tree {
+    assert!(!dm.is_empty(), "no sequences given!");
+    let mut d = dm.to_rows();
+    let mut nodes = dm.names.iter().map(|s| Some(Tree::leaf(s))).collect::<Vec<_>>();
+
+    loop {
+        let alive = (0..nodes.len()).filter(|&i| nodes[i].is_some()).collect::<Vec<_>>();
+        let r = alive.len();
+        if r <= 3 {
+            let length = |&i: &usize| match r {
+                1 => 0.0,
+                2 => d[alive[0]][alive[1]] / 2.0,
+                _ => {
+                    let others = alive.iter().filter(|&&k| k != i).collect::<Vec<_>>();
+                    (d[i][*others[0]] + d[i][*others[1]] - d[*others[0]][*others[1]]) / 2.0
+                }
+            };
+            let mut children = alive.iter().map(|i| nodes[*i].take().unwrap().with_length(length(i))).collect::<Vec<_>>();
+            if r == 1 { return children.pop().unwrap(); }
+            return Tree::node(children);
+        }
+
+        let total = |i: usize| alive.iter().map(|&k| d[i][k]).sum::<f64>();
+        let sums = (0..d.len()).map(|i| if nodes[i].is_some() { total(i) } else { 0.0 }).collect::<Vec<_>>();
+
+        let mut closest: Option<(usize, usize, f64)> = None;
+        for (a, &i) in alive.iter().enumerate() {
+            for &j in &alive[a + 1..] {
+                let q = (r - 2) as f64 * d[i][j] - sums[i] - sums[j];
+                if closest.is_none_or(|(_, _, b)| q < b) { closest = Some((i, j, q)); }
+            }
+        }
+        let (i, j, _) = closest.unwrap();
+
+        let li = d[i][j] / 2.0 + (sums[i] - sums[j]) / (2 * (r - 2)) as f64;
+        let lj = d[i][j] - li;
+        let ti = nodes[i].take().unwrap().with_length(li);
+        let tj = nodes[j].take().unwrap().with_length(lj);
+
+        let merged = (0..d.len()).map(|k| (d[i][k] + d[j][k] - d[i][j]) / 2.0).collect::<Vec<_>>();
+        for (k, dk) in merged.into_iter().enumerate() {
+            d[i][k] = dk;
+            d[k][i] = dk;
+        }
+        d[i][i] = 0.0;
+        nodes[i] = Some(Tree::node(vec![ti, tj]));
+    }
+}
+
+/// The characters which can't be part of an unquoted Newick name
+const NEWICK_SPECIAL: &[char] = &['(', ')', ',', ':', ';', '\'', '[', ']'];
+
+impl Display for Tree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn node(t: &Tree, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            if !t.is_leaf() {
+                write!(f, "(")?;
+                for (k, c) in t.children.iter().enumerate() {
+                    if k > 0 { write!(f, ",")?; }
+                    node(c, f)?;
+                }
+                write!(f, ")")?;
+            }
+            match &t.name {
+                // unquoted underscores are read back as blanks
+                Some(s) if s.contains(NEWICK_SPECIAL) || s.contains(|c: char| c == '_' || c.is_whitespace()) =>
+                    write!(f, "'{}'", s.replace('\'', "''"))?,
+                Some(s) => write!(f, "{}", s)?,
+                None => {},
+            }
+            if let Some(l) = t.length { write!(f, ":{}", l)?; }
+            Ok(())
+        }
+        node(self, f)?;
+        write!(f, ";")
+    }
+}
+
+/// Recursive descent parser of the Newick format
+struct Newick<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl Newick<'_> {
+    fn skip_ws(&mut self) {
+        while self.pos < self.s.len() && self.s[self.pos].is_ascii_whitespace() { self.pos += 1; }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_ws();
+        self.s.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), SimpleError> {
+        match self.peek() {
+            Some(x) if x == c => { self.pos += 1; Ok(()) },
+            Some(x) => bail!("newick error: expected '{}' but found '{}' at {}", c as char, x as char, self.pos),
+            None => bail!("newick error: expected '{}' but the tree ended", c as char),
+        }
+    }
+
+    fn name(&mut self) -> Result<Option<String>, SimpleError> {
+        if self.peek() == Some(b'\'') {
+            self.pos += 1;
+            let mut name = vec![];
+            loop {
+                match (self.s.get(self.pos), self.s.get(self.pos + 1)) {
+                    (Some(b'\''), Some(b'\'')) => { name.push(b'\''); self.pos += 2; },
+                    (Some(b'\''), _) => { self.pos += 1; break; },
+                    (Some(&c), _) => { name.push(c); self.pos += 1; },
+                    (None, _) => bail!("newick error: unterminated quoted name"),
+                }
+            }
+            return Ok(Some(String::from_utf8_lossy(&name).into_owned()));
+        }
+        let start = self.pos;
+        while self.pos < self.s.len() && !NEWICK_SPECIAL.contains(&(self.s[self.pos] as char)) { self.pos += 1; }
+        let name = String::from_utf8_lossy(&self.s[start..self.pos]).trim().replace('_', " ");
+        Ok(if name.is_empty() { None } else { Some(name) })
+    }
+
+    fn tree(&mut self) -> Result<Tree, Box<dyn Error>> {
+        let mut children = vec![];
+        if self.peek() == Some(b'(') {
+            self.pos += 1;
+            loop {
+                children.push(self.tree()?);
+                match self.peek() {
+                    Some(b',') => self.pos += 1,
+                    _ => break,
+                }
+            }
+            self.expect(b')')?;
+        }
+        let name = self.name()?;
+        let mut length = None;
+        if self.peek() == Some(b':') {
+            self.pos += 1;
+            let start = self.pos;
+            while self.pos < self.s.len() && !NEWICK_SPECIAL.contains(&(self.s[self.pos] as char)) { self.pos += 1; }
+            length = Some(std::str::from_utf8(&self.s[start..self.pos])?.trim().parse::<f64>()?);
+        }
+        Ok(Tree { name, length, children })
+    }
+}
+
+impl FromStr for Tree {
+    type Err = Box<dyn Error>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut p = Newick { s: s.as_bytes(), pos: 0 };
+        let t = p.tree()?;
+        p.expect(b';')?;
+        if p.peek().is_some() { bail!("newick error: unexpected text after the end of the tree"); }
+        Ok(t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{DnaBlock, DnaMetricSpace as Dms};
+    use crate::io::{read_test_inst, read_test_insts_all};
+    use crate::math::dist_2;
+    use super::{DistMatrix, Tree, upgma, neighbor_joining};
+
+    /// the two sequences of Inst_0000010_7 and of Inst_0000010_8
+    fn small_instances() -> DistMatrix {
+        let DnaBlock(a, b) = read_test_inst("Inst_0000010_7.adn").expect("cannot read instance!");
+        let DnaBlock(c, d) = read_test_inst("Inst_0000010_8.adn").expect("cannot read instance!");
+        DistMatrix::compute(&Dms, &[a, b, c, d]).with_names(&["x7", "y7", "x8", "y8"])
+    }
+
+    #[test]
+    fn compute_matches_dist_2(){
+        let seqs = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 50)
+            .flat_map(|(_, DnaBlock(l, r))| [l, r])
+            .collect::<Vec<_>>();
+        let m = DistMatrix::compute(&Dms, &seqs);
+        assert_eq!(m.len(), seqs.len());
+        for i in 0..seqs.len() {
+            for j in 0..seqs.len() {
+                assert_eq!(m.get(i, j), dist_2(&Dms, &seqs[i], &seqs[j]) as f64);
+            }
+        }
+    }
+
+    #[test]
+    fn distances_of_small_instances(){
+        let m = small_instances();
+        let rows = (0..4).map(|i| (0..4).map(|j| m.get(i, j)).collect::<Vec<_>>()).collect::<Vec<_>>();
+        assert_eq!(rows, vec![
+            vec![0.0, 8.0, 15.0, 17.0],
+            vec![8.0, 0.0, 18.0, 17.0],
+            vec![15.0, 18.0, 0.0, 2.0],
+            vec![17.0, 17.0, 2.0, 0.0],
+        ]);
+        assert_eq!(DistMatrix::from_rows(&rows).unwrap().with_names(&["x7", "y7", "x8", "y8"]), m);
+    }
+
+    #[test]
+    fn upgma_small_instances(){
+        // x8/y8 merge at height 1, x7/y7 at 4, 
+        // and the two clusters at (15 + 17 + 18 + 17) / 4 / 2 = 8.375
+        let t = upgma(&small_instances());
+        assert_eq!(t.to_string(), "((x7:4,y7:4):4.375,(x8:1,y8:1):7.375);");
+    }
+
+    #[test]
+    fn neighbor_joining_small_instances(){
+        // q(x7, y7) = 2 * 8 - 40 - 43 = -67 is the lowest (tied with x8/y8),
+        // x7 is at 8/2 + (40 - 43)/4 = 3.25 and y7 at 4.75 of the new node u,
+        // then d(u, x8) = (15 + 18 - 8)/2 = 12.5, d(u, y8) = 13, and the last three nodes are joined:
+        // u at (12.5 + 13 - 2)/2 = 11.75, x8 at (12.5 + 2 - 13)/2 = 0.75 and y8 at 1.25
+        let t = neighbor_joining(&small_instances());
+        assert_eq!(t.to_string(), "((x7:3.25,y7:4.75):11.75,x8:0.75,y8:1.25);");
+    }
+
+    #[test]
+    fn neighbor_joining_additive(){
+        // the classic example, the distances are additive so the tree is recovered exactly
+        let m = DistMatrix::from_rows(&[
+            vec![0.0, 5.0, 9.0, 9.0, 8.0],
+            vec![5.0, 0.0, 10.0, 10.0, 9.0],
+            vec![9.0, 10.0, 0.0, 8.0, 7.0],
+            vec![9.0, 10.0, 8.0, 0.0, 3.0],
+            vec![8.0, 9.0, 7.0, 3.0, 0.0],
+        ]).unwrap().with_names(&["a", "b", "c", "d", "e"]);
+        assert_eq!(neighbor_joining(&m).to_string(), "(((a:2,b:3):3,c:4):2,d:2,e:1);");
+    }
+
+    #[test]
+    fn newick(){
+        let txt = "((x7:4,y7:4):7,(x8:1,'y 8''s':1.5)inner:10)root;";
+        let t = txt.parse::<Tree>().expect("cannot parse tree!");
+        assert_eq!(t.name.as_deref(), Some("root"));
+        assert_eq!(t.leaves(), vec!["x7", "y7", "x8", "y 8's"]);
+        assert_eq!(t.children[1].length, Some(10.0));
+        assert_eq!(t.children[1].children[1].length, Some(1.5));
+        assert_eq!(t.to_string(), txt);
+
+        let t2 = " ( A , B_c , ( D , E ) ) ; ".parse::<Tree>().expect("cannot parse tree!");
+        assert_eq!(t2.leaves(), vec!["A", "B c", "D", "E"]);
+        assert_eq!(t2.children.len(), 3);
+
+        let t3 = Tree::node(vec![Tree::leaf("seq_1"), Tree::leaf("seq 2")]);
+        assert_eq!(t3.to_string(), "('seq_1','seq 2');");
+        assert_eq!(t3.to_string().parse::<Tree>().expect("cannot parse tree!").leaves(), vec!["seq_1", "seq 2"]);
+
+        for bad in ["(a,b)", "(a,b;", "(a:x,b);", "(a,b);c", "'a;"] {
+            assert!(bad.parse::<Tree>().is_err(), "{:?} should be rejected", bad);
+        }
+        assert!(DistMatrix::from_rows(&[vec![0.0, 1.0], vec![2.0, 0.0]]).is_err());
+    }
+}
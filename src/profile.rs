@@ -0,0 +1,334 @@
+//! Profiles of aligned sequences, and the alignment of sequences and profiles with profiles.
+//!
+//! A profile sums up a set of aligned rows by the number of occurences of every item (and of the gaps)
+//! in each of its columns. Two profiles are aligned as two sequences of columns, the cost of a pair of
+//! columns being the sum-of-pairs cost of all their entries, so aligning two profiles gives the same
+//! result as if all the pairs of rows were compared. The alignment uses linear memory, as in sol_2.
+
+use std::ops::Range;
+
+use crate::math::*;
+use crate::msa::MultiAlign;
+
+/// The content of a column of a profile, the occurences of every item and the number of gaps
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Column<T> {
+    counts: Vec<(T, usize)>,
+    gaps: usize,
+}
+
+/// A profile of aligned rows, all the rows have the same length
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Profile<M: MetricSpace> {
+    rows: Vec<Vec<Gapped<M::Item>>>,
+    cols: Vec<Column<M::Item>>,
+}
+
+impl<M: MetricSpace> Profile<M>
+where M::Item: PartialEq
+{
+    /// Build the profile of aligned rows
+    pub fn new(rows: Vec<Vec<Gapped<M::Item>>>) -> Self {
+        let n = rows.first().map_or(0, |r| r.len());
+        assert!(rows.iter().all(|r| r.len() == n), "the rows of a profile must have the same length!");
+
+        let mut cols = vec![Column { counts: vec![], gaps: 0 }; n];
+        for r in rows.iter() {
+            for (col, e) in cols.iter_mut().zip(r) {
+                match e {
+                    Gapped::Gap => col.gaps += 1,
+                    Gapped::Item(x) => match col.counts.iter_mut().find(|(y, _)| y == x) {
+                        Some((_, c)) => *c += 1,
+                        None => col.counts.push((*x, 1)),
+                    },
+                }
+            }
+        }
+        Profile { rows, cols }
+    }
+
+    /// The profile of a single sequence
+    pub fn from_seq(x: &[M::Item]) -> Self {
+        Self::new(vec![x.iter().copied().map(Gapped::Item).collect()])
+    }
+}
+
+impl<M: MetricSpace> Profile<M> {
+    /// The number of columns
+    pub fn len(&self) -> usize {
+        self.cols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cols.is_empty()
+    }
+
+    /// The number of rows
+    pub fn depth(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// The aligned rows of the profile
+    pub fn rows(&self) -> &[Vec<Gapped<M::Item>>] {
+        &self.rows
+    }
+
+    /// The fraction of the rows holding each item in the column j
+    pub fn frequencies(&self, j: usize) -> Vec<(M::Item, f64)> {
+        self.cols[j].counts
+            .iter()
+            .map(|&(x, c)| (x, c as f64 / self.depth() as f64))
+            .collect()
+    }
+
+    /// The fraction of the rows holding a gap in the column j
+    pub fn gap_fraction(&self, j: usize) -> f64 {
+        self.cols[j].gaps as f64 / self.depth() as f64
+    }
+
+    /// Turn the profile back into a multiple alignment
+    pub fn into_multi_align(self) -> MultiAlign<M> {
+        MultiAlign(self.rows)
+    }
+}
+
+impl<M: MetricSpace> From<MultiAlign<M>> for Profile<M>
+where M::Item: PartialEq
+{
+    fn from(al: MultiAlign<M>) -> Self {
+        Profile::new(al.0)
+    }
+}
+
+/// The sum-of-pairs costs of aligning the columns of a with the columns of b,
+/// the costs of the gap columns are computed once and for all
+struct Costs<'a, M: MetricSpace> {
+    ms: &'a M,
+    a: &'a Profile<M>,
+    b: &'a Profile<M>,
+    del: Vec<M::Cost>,
+    ins: Vec<M::Cost>,
+}
+
+impl<'a, M: MetricSpace> Costs<'a, M> {
+    fn new(ms: &'a M, a: &'a Profile<M>, b: &'a Profile<M>) -> Self {
+        let z = M::ZEROCOST;
+        // a column of gaps costs nothing against the gaps of the other profile
+        let del = a.cols.iter().map(|c| times(ms.del(), (a.depth() - c.gaps) * b.depth(), z)).collect();
+        let ins = b.cols.iter().map(|c| times(ms.ins(), a.depth() * (b.depth() - c.gaps), z)).collect();
+        Costs { ms, a, b, del, ins }
+    }
+
+    fn sub(&self, i: usize, j: usize) -> M::Cost {
+        let z = M::ZEROCOST;
+        let (ca, cb) = (&self.a.cols[i], &self.b.cols[j]);
+        let (na, nb) = (self.a.depth() - ca.gaps, self.b.depth() - cb.gaps);
+        let mut c = times(self.ms.del(), na * cb.gaps, z) + times(self.ms.ins(), ca.gaps * nb, z);
+        for &(x, kx) in ca.counts.iter() {
+            for &(y, ky) in cb.counts.iter() {
+                c = c + times(self.ms.sub(x, y), kx * ky, z);
+            }
+        }
+        c
+    }
+
+    /// Last row of the dp table of the columns xs of a and ys of b. O(n*m) time O(m) memory
+    fn dist(&self, xs: Range<usize>, ys: Range<usize>) -> M::Cost {
+        let best = M::Goal::best::<M::Cost>;
+        let m = ys.len() + 1;
+        let mut dp = vec![vec![M::ZEROCOST; m]; 2];
+
+        for j in 1..m {
+            dp[0][j] = dp[0][j-1] + self.ins[ys.start + j - 1];
+        }
+        for i in xs {
+            dp[1][0] = dp[0][0] + self.del[i];
+            for j in 1..m {
+                let y = ys.start + j - 1;
+                dp[1][j] = best(dp[0][j-1] + self.sub(i, y), best(dp[1][j-1] + self.ins[y], dp[0][j] + self.del[i]));
+            }
+            dp.swap(0, 1);
+        }
+        dp[0][m - 1]
+    }
+
+    /// Same as coupure, the optimal cutting point in ys for the middle of xs
+    fn coupure(&self, xs: Range<usize>, ys: Range<usize>) -> usize {
+        let best = M::Goal::best::<M::Cost>;
+        let m = ys.len() + 1;
+        let i_star = xs.start + xs.len() / 2;
+        let mut t = vec![vec![M::ZEROCOST; m]; 2];
+        let mut q = vec![(0..m).collect::<Vec<_>>(); 2];
+
+        for j in 1..m {
+            t[0][j] = t[0][j-1] + self.ins[ys.start + j - 1];
+        }
+        for i in xs {
+            t[1][0] = t[0][0] + self.del[i];
+            for j in 1..m {
+                let y = ys.start + j - 1;
+                let op1 = t[0][j-1] + self.sub(i, y);
+                let op2 = t[0][j] + self.del[i];
+                let op3 = t[1][j-1] + self.ins[y];
+                t[1][j] = best(op1, best(op2, op3));
+
+                if i < i_star            { continue; }
+                if t[1][j] == op1        { q[1][j] = q[0][j-1]; }
+                else if t[1][j] == op2   { q[1][j] = q[0][j]; }
+                else                     { q[1][j] = q[1][j-1]; }
+            }
+            t.swap(0, 1);
+            if i >= i_star { q.swap(0, 1); }
+        }
+        ys.start + q[0][m - 1]
+    }
+
+    /// Full table alignment of the columns xs of a and ys of b, for the small cases of sol
+    fn sol_full(&self, xs: Range<usize>, ys: Range<usize>, out: &mut Vec<(Option<usize>, Option<usize>)>) {
+        let best = M::Goal::best::<M::Cost>;
+        let (n, m) = (xs.len(), ys.len());
+        let (x0, y0) = (xs.start, ys.start);
+        let mut t = vec![vec![M::ZEROCOST; m + 1]; n + 1];
+        for i in 1..=n { t[i][0] = t[i-1][0] + self.del[x0 + i - 1]; }
+        for j in 1..=m { t[0][j] = t[0][j-1] + self.ins[y0 + j - 1]; }
+        for i in 1..=n {
+            for j in 1..=m {
+                let (x, y) = (x0 + i - 1, y0 + j - 1);
+                t[i][j] = best(t[i-1][j-1] + self.sub(x, y), best(t[i][j-1] + self.ins[y], t[i-1][j] + self.del[x]));
+            }
+        }
+
+        let start = out.len();
+        let (mut i, mut j) = (n, m);
+        while i > 0 || j > 0 {
+            let (x, y) = ((x0 + i).wrapping_sub(1), (y0 + j).wrapping_sub(1));
+            if i > 0 && j > 0 && t[i][j] == t[i-1][j-1] + self.sub(x, y) {
+                out.push((Some(x), Some(y)));
+                i -= 1;
+                j -= 1;
+            } else if j > 0 && t[i][j] == t[i][j-1] + self.ins[y] {
+                out.push((None, Some(y)));
+                j -= 1;
+            } else {
+                out.push((Some(x), None));
+                i -= 1;
+            }
+        }
+        out[start..].reverse();
+    }
+
+    /// Same as sol_2_ll, the pairs of columns of the optimal alignment of xs and ys,
+    /// None standing for a column of gaps
+    fn sol(&self, xs: Range<usize>, ys: Range<usize>, out: &mut Vec<(Option<usize>, Option<usize>)>) {
+        match (xs.len(), ys.len()) {
+            (0, _) => out.extend(ys.map(|y| (None, Some(y)))),
+            (_, 0) => out.extend(xs.map(|x| (Some(x), None))),
+            (1, _) => self.sol_full(xs, ys, out),
+            (_, _) => {
+                let i = xs.start + xs.len() / 2;
+                let j = self.coupure(xs.clone(), ys.clone());
+                self.sol(xs.start..i, ys.start..j, out);
+                self.sol(i..xs.end, j..ys.end, out);
+            }
+        }
+    }
+}
+
+/// The cost of the optimal alignment of two profiles, without the costs within each profile.
+/// O(n*m) column comparisons and O(m) memory
+pub fn dist_profiles<M>(ms: &M, a: &Profile<M>, b: &Profile<M>) -> M::Cost
+where M: MetricSpace
+{
+    Costs::new(ms, a, b).dist(0..a.len(), 0..b.len())
+}
+
+/// Align two profiles, the rows of the result are the rows of a followed by the rows of b.
+/// O(n*m) column comparisons and O(n+m) memory
+pub fn align_profiles<M>(ms: &M, a: &Profile<M>, b: &Profile<M>) -> Profile<M>
+where M: MetricSpace, M::Item: PartialEq
+{
+    let mut cols = vec![];
+    Costs::new(ms, a, b).sol(0..a.len(), 0..b.len(), &mut cols);
+
+    let pick = |r: &Vec<Gapped<M::Item>>, k: Option<usize>| k.map_or(Gapped::Gap, |k| r[k]);
+    let rows = a.rows.iter().map(|r| cols.iter().map(|&(k, _)| pick(r, k)).collect())
+        .chain(b.rows.iter().map(|r| cols.iter().map(|&(_, k)| pick(r, k)).collect()))
+        .collect();
+    Profile::new(rows)
+}
+
+/// Align a sequence against a profile, the sequence is added as the last row
+pub fn align_sequence<M>(ms: &M, a: &Profile<M>, x: &[M::Item]) -> Profile<M>
+where M: MetricSpace, M::Item: PartialEq
+{
+    align_profiles(ms, a, &Profile::from_seq(x))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{Dna, DnaBlock, DnaMetricSpace as Dms, DnaSimilarity};
+    use crate::io::read_test_insts_all;
+    use crate::math::{Gapped, MetricSpace, dist_2, rm_gaps};
+    use crate::msa::{MultiAlign, msa};
+    use super::{Profile, align_profiles, align_sequence, dist_profiles};
+
+    fn row(s: &str) -> Vec<Gapped<Dna>> {
+        s.chars().map(|c| c.to_string().parse().expect("invalid row!")).collect()
+    }
+
+    #[test]
+    fn frequencies(){
+        let p = Profile::<Dms>::new(vec![row("AC-T"), row("AG-T"), row("TG-T"), row("AGC-")]);
+        assert_eq!((p.len(), p.depth()), (4, 4));
+        assert_eq!(p.frequencies(0), vec![(Dna::A, 0.75), (Dna::T, 0.25)]);
+        assert_eq!(p.frequencies(1), vec![(Dna::C, 0.25), (Dna::G, 0.75)]);
+        assert_eq!(p.frequencies(2), vec![(Dna::C, 0.25)]);
+        assert_eq!(p.gap_fraction(2), 0.75);
+        assert_eq!(p.gap_fraction(0), 0.0);
+    }
+
+    #[test]
+    fn single_rows_are_pairwise(){
+        let testcases = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 500)
+            .map(|(_, b)| b);
+        for DnaBlock(l, r) in testcases {
+            let d = dist_2(&Dms, &l, &r);
+            let (a, b) = (Profile::<Dms>::from_seq(&l), Profile::from_seq(&r));
+            assert_eq!(dist_profiles(&Dms, &a, &b), d);
+            let al = align_sequence(&Dms, &a, &r).into_multi_align();
+            assert_eq!(al.sp_cost(&Dms), d);
+        }
+    }
+
+    fn check_profiles<M>(ms: &M, a: &Profile<M>, b: &Profile<M>)
+    where M: MetricSpace<Item = Dna>, M::Cost: std::ops::Sub<Output = M::Cost>
+    {
+        let d = dist_profiles(ms, a, b);
+        let p = align_profiles(ms, a, b);
+        assert_eq!(p.depth(), a.depth() + b.depth());
+        for (r, s) in p.rows().iter().zip(a.rows().iter().chain(b.rows())) {
+            assert_eq!(rm_gaps::<M>(r.clone()), rm_gaps::<M>(s.clone()), "some letters 🍪 got eaten");
+        }
+        let (sa, sb) = (MultiAlign::<M>(a.rows().to_vec()).sp_cost(ms), MultiAlign::<M>(b.rows().to_vec()).sp_cost(ms));
+        assert_eq!(p.into_multi_align().sp_cost(ms) - sa - sb, d);
+    }
+
+    #[test]
+    fn profile_profile(){
+        let seqs = read_test_insts_all()
+            .skip_while(|&(size, _)| size < 100)
+            .take_while(|&(size, _)| size <= 500)
+            .flat_map(|(_, DnaBlock(l, r))| [l, r])
+            .collect::<Vec<_>>();
+        let (left, right) = seqs.split_at(seqs.len() / 2);
+
+        let (a, b) = (Profile::from(msa(&Dms, left)), Profile::from(msa(&Dms, right)));
+        check_profiles(&Dms, &a, &b);
+        check_profiles(&Dms, &b, &a);
+
+        let sim = DnaSimilarity::default();
+        let (a, b) = (Profile::from(msa(&sim, left)), Profile::from(msa(&sim, right)));
+        check_profiles(&sim, &a, &b);
+    }
+}
@@ -0,0 +1,331 @@
+//! The Python extension module `runa`, with the `python` feature (built with maturin, see pyproject.toml).
+//!
+//! The distance and alignment functions keep their names, `dist_2(x, y, scheme=None)` or
+//! `sol_2(x, y, scheme=None)` for instance, the scheme being a [`Scheme`] (the course costs by default).
+//! The sequences are `str` or `bytes` of A, C, G and T in any case, and the GIL is released while the
+//! sequences are aligned, so that several alignments can run on several Python threads.
+//! The alignments are `Align` objects, with their rows, cost, aligned ranges and CIGAR string.
+//! The parsers give the sequences as `(name, sequence)` tuples of strings.
+//!
+//! ```python
+//! import runa
+//! al = runa.sol_2("ATTGTA", b"ATCTTA")
+//! print(al.cost, al.cigar(), al.x, al.y)
+//! runa.dist_local("TTACGTT", "GACGTG", runa.Scheme.similarity(matched=2))
+//! ```
+
+use std::fs::File;
+use std::ops::Range;
+
+use pyo3::exceptions::{PyOSError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyString};
+
+use crate::dna::{Dna, DnaBlock, DnaMetricSpace, DnaSimilarity};
+use crate::io::{FastaRecord, letter, parse_fasta, read_sequences, shown};
+use crate::math::*;
+use crate::matrix::{Scheme, SubstMatrix};
+use crate::output::{AlignStats, cigar};
+
+/// The letters of a str or bytes sequence
+fn letters(s: &Bound<'_, PyAny>, what: &str) -> PyResult<Vec<Dna>> {
+    let parse = |b: &[u8]| b.iter().enumerate()
+        .map(|(i, &c)| letter(c).ok_or_else(|| PyValueError::new_err(format!("invalid letter '{}' at {} of {}", shown(c), i, what))))
+        .collect();
+    if let Ok(s) = s.downcast::<PyString>() {
+        parse(s.to_str()?.as_bytes())
+    } else if let Ok(b) = s.downcast::<PyBytes>() {
+        parse(b.as_bytes())
+    } else {
+        Err(PyTypeError::new_err(format!("{} must be str or bytes", what)))
+    }
+}
+
+/// A str or UTF-8 bytes text
+fn text(s: &Bound<'_, PyAny>) -> PyResult<String> {
+    if let Ok(s) = s.downcast::<PyString>() {
+        Ok(s.to_str()?.to_string())
+    } else if let Ok(b) = s.downcast::<PyBytes>() {
+        String::from_utf8(b.as_bytes().to_vec()).map_err(|_| PyValueError::new_err("the text is not UTF-8"))
+    } else {
+        Err(PyTypeError::new_err("the text must be str or bytes"))
+    }
+}
+
+fn string(s: &[Dna]) -> String {
+    s.iter().map(|d| d.to_string()).collect()
+}
+
+fn cost<C: TryInto<i64>>(c: C) -> i64 {
+    c.try_into().unwrap_or(i64::MAX)
+}
+
+fn records(r: Vec<FastaRecord>) -> Vec<(String, String)> {
+    r.into_iter().map(|r| (r.name, string(&r.seq))).collect()
+}
+
+/// A scoring scheme: Scheme(name) with name course (the default), unit, kimura or similarity,
+/// Scheme.similarity(matched=1, mismatch=-1, gap=-2) or Scheme.matrix(text) for a substitution matrix
+#[pyclass(name = "Scheme", module = "runa", frozen, eq)]
+#[derive(PartialEq)]
+pub struct PyScheme(Scheme);
+
+#[pymethods]
+impl PyScheme {
+    #[new]
+    #[pyo3(signature = (name = "course"))]
+    fn new(name: &str) -> PyResult<Self> {
+        Scheme::preset(name).map(PyScheme).map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (matched = 1, mismatch = -1, gap = -2))]
+    fn similarity(matched: i64, mismatch: i64, gap: i64) -> Self {
+        PyScheme(Scheme::Similarity(DnaSimilarity { matched, mismatch, gap }))
+    }
+
+    #[staticmethod]
+    fn matrix(matrix: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let m = text(matrix)?.parse::<SubstMatrix>().map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyScheme(Scheme::Distance(m)))
+    }
+
+    /// Whether the best alignments have the highest score rather than the lowest cost
+    #[getter]
+    fn is_similarity(&self) -> bool {
+        matches!(self.0, Scheme::Similarity(_))
+    }
+
+    fn __repr__(&self) -> String {
+        match &self.0 {
+            Scheme::Distance(m) => format!("Scheme.matrix({:?})", m.to_string()),
+            Scheme::Similarity(s) => format!("Scheme.similarity(matched={}, mismatch={}, gap={})", s.matched, s.mismatch, s.gap),
+        }
+    }
+}
+
+fn scheme_of(s: Option<PyRef<'_, PyScheme>>) -> Scheme {
+    s.map_or(Scheme::Distance(SubstMatrix::course()), |s| s.0.clone())
+}
+
+fn similarity_of(s: Option<PyRef<'_, PyScheme>>) -> PyResult<DnaSimilarity> {
+    match scheme_of(s) {
+        Scheme::Similarity(s) => Ok(s),
+        Scheme::Distance(_) => Err(PyValueError::new_err("the local and overlap alignments need a similarity scheme")),
+    }
+}
+
+/// The same expression for the two kinds of scheme
+macro_rules! on_scheme {
+    ($s:expr, $m:ident => $e:expr) => {
+        match &$s {
+            Scheme::Distance($m) => $e,
+            Scheme::Similarity($m) => $e,
+        }
+    };
+}
+
+/// An alignment of x[x_range] and y[y_range] with its cost (or score), the whole sequences for a global alignment.
+/// Align(x, y, scheme=None) is the alignment of the two rows, the gaps being '-'
+#[pyclass(name = "Align", module = "runa", frozen, eq)]
+#[derive(PartialEq)]
+pub struct PyAlign {
+    al: Align<DnaMetricSpace>,
+    #[pyo3(get)]
+    cost: i64,
+    x_range: Range<usize>,
+    y_range: Range<usize>,
+}
+
+impl PyAlign {
+    fn new<M: MetricSpace<Item = Dna>>(al: Align<M>, cost: i64, x_range: Range<usize>, y_range: Range<usize>) -> Self {
+        PyAlign { al: Align(al.0, al.1), cost, x_range, y_range }
+    }
+
+    fn global<M: MetricSpace<Item = Dna>>(ms: &M, al: Align<M>) -> Self
+    where M::Cost: TryInto<i64>
+    {
+        let c = cost(al.cost(ms));
+        let n = al.0.iter().filter(|g| !g.is_gap()).count();
+        let m = al.1.iter().filter(|g| !g.is_gap()).count();
+        PyAlign::new(al, c, 0..n, 0..m)
+    }
+}
+
+#[pymethods]
+impl PyAlign {
+    #[new]
+    #[pyo3(signature = (x, y, scheme = None))]
+    fn py_new(x: &str, y: &str, scheme: Option<PyRef<'_, PyScheme>>) -> PyResult<Self> {
+        let row = |r: &str| r.chars()
+            .map(|c| if c == '-' { Ok(Gapped::Gap) } else { c.to_string().parse::<Dna>().map(Gapped::Item).map_err(|e| PyValueError::new_err(e.to_string())) })
+            .collect::<PyResult<Vec<Gapped<Dna>>>>();
+        let al = Align::<DnaMetricSpace>(row(x)?, row(y)?);
+        if al.0.len() != al.1.len() { return Err(PyValueError::new_err("the rows of the alignment differ in length")); }
+        if al.0.iter().zip(&al.1).any(|(a, b)| a.is_gap() && b.is_gap()) { return Err(PyValueError::new_err("a column of the alignment only holds gaps")); }
+        Ok(on_scheme!(scheme_of(scheme), m => PyAlign::global(m, Align(al.0, al.1))))
+    }
+
+    /// The row of x, with '-' for the gaps
+    #[getter]
+    fn x(&self) -> String {
+        string_row(&self.al.0)
+    }
+
+    /// The row of y, with '-' for the gaps
+    #[getter]
+    fn y(&self) -> String {
+        string_row(&self.al.1)
+    }
+
+    #[getter]
+    fn x_range(&self) -> (usize, usize) {
+        (self.x_range.start, self.x_range.end)
+    }
+
+    #[getter]
+    fn y_range(&self) -> (usize, usize) {
+        (self.y_range.start, self.y_range.end)
+    }
+
+    /// The CIGAR string, with =, X, I and D, x being the reference
+    fn cigar(&self) -> String {
+        cigar(&self.al)
+    }
+
+    /// The proportion of matches among the columns
+    fn identity(&self) -> f64 {
+        AlignStats::new(&self.al).identity()
+    }
+
+    fn __len__(&self) -> usize {
+        self.al.0.len()
+    }
+
+    fn __str__(&self) -> String {
+        self.al.to_string().trim_start().to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Align({:?}, {:?}, cost={})", self.x(), self.y(), self.cost)
+    }
+}
+
+fn string_row(r: &[Gapped<Dna>]) -> String {
+    r.iter().map(|g| g.to_string()).collect()
+}
+
+/// dist_naif(x, y, scheme=None), the distance by enumeration, for tiny sequences only
+#[pyfunction(name = "dist_naif")]
+#[pyo3(signature = (x, y, scheme = None))]
+fn py_dist_naif(py: Python<'_>, x: &Bound<'_, PyAny>, y: &Bound<'_, PyAny>, scheme: Option<PyRef<'_, PyScheme>>) -> PyResult<i64> {
+    let (x, y, s) = (letters(x, "x")?, letters(y, "y")?, scheme_of(scheme));
+    Ok(py.allow_threads(|| on_scheme!(s, m => cost(dist_naif(m, &x, &y)))))
+}
+
+/// dist_1(x, y, scheme=None), the distance with the full table
+#[pyfunction(name = "dist_1")]
+#[pyo3(signature = (x, y, scheme = None))]
+fn py_dist_1(py: Python<'_>, x: &Bound<'_, PyAny>, y: &Bound<'_, PyAny>, scheme: Option<PyRef<'_, PyScheme>>) -> PyResult<i64> {
+    let (x, y, s) = (letters(x, "x")?, letters(y, "y")?, scheme_of(scheme));
+    Ok(py.allow_threads(|| on_scheme!(s, m => cost(dist_1(m, &x, &y)))))
+}
+
+/// dist_2(x, y, scheme=None), the distance in linear memory
+#[pyfunction(name = "dist_2")]
+#[pyo3(signature = (x, y, scheme = None))]
+fn py_dist_2(py: Python<'_>, x: &Bound<'_, PyAny>, y: &Bound<'_, PyAny>, scheme: Option<PyRef<'_, PyScheme>>) -> PyResult<i64> {
+    let (x, y, s) = (letters(x, "x")?, letters(y, "y")?, scheme_of(scheme));
+    Ok(py.allow_threads(|| on_scheme!(s, m => cost(dist_2(m, &x, &y)))))
+}
+
+/// sol_1(x, y, scheme=None), an optimal alignment with the full table
+#[pyfunction(name = "sol_1")]
+#[pyo3(signature = (x, y, scheme = None))]
+fn py_sol_1(py: Python<'_>, x: &Bound<'_, PyAny>, y: &Bound<'_, PyAny>, scheme: Option<PyRef<'_, PyScheme>>) -> PyResult<PyAlign> {
+    let (x, y, s) = (letters(x, "x")?, letters(y, "y")?, scheme_of(scheme));
+    Ok(py.allow_threads(|| on_scheme!(s, m => PyAlign::global(m, sol_1(m, &x, &y)))))
+}
+
+/// sol_2(x, y, scheme=None), an optimal alignment in linear memory
+#[pyfunction(name = "sol_2")]
+#[pyo3(signature = (x, y, scheme = None))]
+fn py_sol_2(py: Python<'_>, x: &Bound<'_, PyAny>, y: &Bound<'_, PyAny>, scheme: Option<PyRef<'_, PyScheme>>) -> PyResult<PyAlign> {
+    let (x, y, s) = (letters(x, "x")?, letters(y, "y")?, scheme_of(scheme));
+    Ok(py.allow_threads(|| on_scheme!(s, m => PyAlign::global(m, sol_2(m, &x, &y)))))
+}
+
+/// dist_local(x, y, scheme), the best score of a part of x with a part of y, for a similarity scheme
+#[pyfunction(name = "dist_local")]
+#[pyo3(signature = (x, y, scheme = None))]
+fn py_dist_local(py: Python<'_>, x: &Bound<'_, PyAny>, y: &Bound<'_, PyAny>, scheme: Option<PyRef<'_, PyScheme>>) -> PyResult<i64> {
+    let (x, y, s) = (letters(x, "x")?, letters(y, "y")?, similarity_of(scheme)?);
+    Ok(py.allow_threads(|| dist_local(&s, &x, &y)))
+}
+
+/// sol_local(x, y, scheme), the best local alignment, for a similarity scheme
+#[pyfunction(name = "sol_local")]
+#[pyo3(signature = (x, y, scheme = None))]
+fn py_sol_local(py: Python<'_>, x: &Bound<'_, PyAny>, y: &Bound<'_, PyAny>, scheme: Option<PyRef<'_, PyScheme>>) -> PyResult<PyAlign> {
+    let (x, y, s) = (letters(x, "x")?, letters(y, "y")?, similarity_of(scheme)?);
+    let l = py.allow_threads(|| sol_local(&s, &x, &y));
+    Ok(PyAlign::new(l.align, l.score, l.x, l.y))
+}
+
+/// dist_overlap(x, y, scheme), the best score of a suffix of x with a prefix of y, for a similarity scheme
+#[pyfunction(name = "dist_overlap")]
+#[pyo3(signature = (x, y, scheme = None))]
+fn py_dist_overlap(py: Python<'_>, x: &Bound<'_, PyAny>, y: &Bound<'_, PyAny>, scheme: Option<PyRef<'_, PyScheme>>) -> PyResult<i64> {
+    let (x, y, s) = (letters(x, "x")?, letters(y, "y")?, similarity_of(scheme)?);
+    Ok(py.allow_threads(|| dist_overlap(&s, &x, &y)))
+}
+
+/// sol_overlap(x, y, scheme), the best overlap alignment, for a similarity scheme
+#[pyfunction(name = "sol_overlap")]
+#[pyo3(signature = (x, y, scheme = None))]
+fn py_sol_overlap(py: Python<'_>, x: &Bound<'_, PyAny>, y: &Bound<'_, PyAny>, scheme: Option<PyRef<'_, PyScheme>>) -> PyResult<PyAlign> {
+    let (x, y, s) = (letters(x, "x")?, letters(y, "y")?, similarity_of(scheme)?);
+    let l = py.allow_threads(|| sol_overlap(&s, &x, &y));
+    Ok(PyAlign::new(l.align, l.score, l.x, l.y))
+}
+
+/// parse_adn(text), the two sequences of an instance in the .adn format
+#[pyfunction]
+fn parse_adn(text: &Bound<'_, PyAny>) -> PyResult<(String, String)> {
+    let b = self::text(text)?.parse::<DnaBlock>().map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok((string(&b.0), string(&b.1)))
+}
+
+/// parse_fasta(text), the (name, sequence) records of a FASTA text
+#[pyfunction(name = "parse_fasta")]
+fn py_parse_fasta(text: &Bound<'_, PyAny>) -> PyResult<Vec<(String, String)>> {
+    parse_fasta(&self::text(text)?).map(records).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// read_sequences(path), the (name, sequence) records of an .adn or FASTA file, gzip compressed with the gzip feature
+#[pyfunction(name = "read_sequences")]
+fn py_read_sequences(py: Python<'_>, path: std::path::PathBuf) -> PyResult<Vec<(String, String)>> {
+    let f = File::open(&path).map_err(|e| PyOSError::new_err(format!("cannot read {}: {}", path.display(), e)))?;
+    py.allow_threads(|| read_sequences(f, None).map(records).map_err(|e| e.to_string()))
+        .map_err(|e| PyValueError::new_err(format!("{}: {}", path.display(), e)))
+}
+
+#[pymodule]
+fn runa(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add_class::<PyScheme>()?;
+    m.add_class::<PyAlign>()?;
+    m.add_function(wrap_pyfunction!(py_dist_naif, m)?)?;
+    m.add_function(wrap_pyfunction!(py_dist_1, m)?)?;
+    m.add_function(wrap_pyfunction!(py_dist_2, m)?)?;
+    m.add_function(wrap_pyfunction!(py_sol_1, m)?)?;
+    m.add_function(wrap_pyfunction!(py_sol_2, m)?)?;
+    m.add_function(wrap_pyfunction!(py_dist_local, m)?)?;
+    m.add_function(wrap_pyfunction!(py_sol_local, m)?)?;
+    m.add_function(wrap_pyfunction!(py_dist_overlap, m)?)?;
+    m.add_function(wrap_pyfunction!(py_sol_overlap, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_adn, m)?)?;
+    m.add_function(wrap_pyfunction!(py_parse_fasta, m)?)?;
+    m.add_function(wrap_pyfunction!(py_read_sequences, m)?)?;
+    Ok(())
+}
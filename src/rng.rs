@@ -0,0 +1,105 @@
+//! Seeded pseudo random numbers, so that everything random in the crate is reproducible.
+//!
+//! The generator is xoshiro256++, seeded through splitmix64. It is fast and good enough for
+//! sampling and simulations, but it is of course not meant for cryptography.
+
+/// A seeded pseudo random number generator (xoshiro256++)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    s: [u64; 4],
+}
+
+impl Rng {
+    /// A generator seeded with the given seed, the same seed always gives the same numbers
+    pub fn new(seed: u64) -> Self {
+        let mut x = seed;
+        let mut splitmix = || {
+            x = x.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        };
+        Rng { s: [splitmix(), splitmix(), splitmix(), splitmix()] }
+    }
+
+    /// The next random 64 bits
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.s;
+        let res = s[0].wrapping_add(s[3]).rotate_left(23).wrapping_add(s[0]);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        res
+    }
+
+    /// A uniform number in 0..n, n can't be 0
+    pub fn below(&mut self, n: u64) -> u64 {
+        assert!(n > 0, "empty range!");
+        // reject the end of the range which would make the low numbers more likely
+        let zone = u64::MAX - (u64::MAX % n + 1) % n;
+        loop {
+            let x = self.next_u64();
+            if x <= zone { return x % n; }
+        }
+    }
+
+    /// A uniform number in 0..n, n can't be 0
+    pub fn below_u128(&mut self, n: u128) -> u128 {
+        assert!(n > 0, "empty range!");
+        let zone = u128::MAX - (u128::MAX % n + 1) % n;
+        loop {
+            let x = (self.next_u64() as u128) << 64 | self.next_u64() as u128;
+            if x <= zone { return x % n; }
+        }
+    }
+
+    /// A uniform number in [0, 1)
+    pub fn f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Shuffle the slice uniformly (Fisher-Yates)
+    pub fn shuffle<T>(&mut self, v: &mut [T]) {
+        for i in (1..v.len()).rev() {
+            let j = self.below(i as u64 + 1) as usize;
+            v.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rng;
+
+    #[test]
+    fn reproducible(){
+        let (mut a, mut b) = (Rng::new(42), Rng::new(42));
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+    }
+
+    #[test]
+    fn ranges(){
+        let mut r = Rng::new(7);
+        let mut seen = [0; 6];
+        for _ in 0..6000 {
+            seen[r.below(6) as usize] += 1;
+            assert!(r.below_u128(1 << 100) < 1 << 100);
+            let f = r.f64();
+            assert!((0.0..1.0).contains(&f));
+        }
+        assert!(seen.iter().all(|&c| (850..1150).contains(&c)), "not uniform: {:?}", seen);
+
+        let mut v = (0..10).collect::<Vec<_>>();
+        r.shuffle(&mut v);
+        v.sort();
+        assert_eq!(v, (0..10).collect::<Vec<_>>());
+    }
+}
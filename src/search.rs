@@ -0,0 +1,176 @@
+//! Approximate pattern matching in long texts (Sellers).
+//!
+//! The text is scanned letter by letter with the rolling column of the dp table of the pattern, as in dist_2,
+//! except that the first row is free: a match can start anywhere in the text. The last entry of the column
+//! is then the cost of the best match ending at the current position of the text.
+//! The scan only yields the end positions, the start and the alignment of a match are recovered on demand
+//! (see [`recover`]) with a backward pass from its end.
+
+use crate::math::*;
+
+/// Lazy scan of a text for the matches of a pattern, see [`sellers`]
+pub struct Sellers<'a, M: MetricSpace> {
+    ms: &'a M,
+    pattern: &'a [M::Item],
+    text: &'a [M::Item],
+    k: M::Cost,
+    /// the costs of the best alignments of the prefixes of the pattern ending at the position j of the text
+    col: Vec<M::Cost>,
+    j: usize,
+}
+
+impl<M: MetricSpace> Iterator for Sellers<'_, M> {
+    type Item = (usize, M::Cost);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let best = M::Goal::best::<M::Cost>;
+        loop {
+            if self.j > 0 {
+                if self.j > self.text.len() { return None; }
+                let t = self.text[self.j - 1];
+                // the letter t of the text: inserted, or aligned with a letter of the pattern
+                let mut diag = self.col[0];
+                for i in 1..self.col.len() {
+                    let c = best(
+                        diag + self.ms.sub(self.pattern[i - 1], t),
+                        best(self.col[i] + self.ms.ins(), self.col[i - 1] + self.ms.del()),
+                    );
+                    diag = self.col[i];
+                    self.col[i] = c;
+                }
+            }
+            self.j += 1;
+            let c = self.col[self.pattern.len()];
+            if !M::Goal::better(&self.k, &c) {
+                return Some((self.j - 1, c));
+            }
+        }
+    }
+}
+
+/// Scan the text for the approximate matches of the pattern, yielding every end position j
+/// such that some part text[s..j] aligns with the pattern within the threshold k
+/// (a cost of at most k, or a score of at least k for a similarity), with the cost of the best one.
+/// The pattern is the first sequence of the alignments. O(m) time per letter of the text and O(m) memory
+pub fn sellers<'a, M>(ms: &'a M, pattern: &'a [M::Item], text: &'a [M::Item], k: M::Cost) -> Sellers<'a, M>
+where M: MetricSpace
+{
+    let mut col = vec![M::ZEROCOST; pattern.len() + 1];
+    for i in 1..col.len() {
+        col[i] = col[i - 1] + ms.del();
+    }
+    Sellers { ms, pattern, text, k, col, j: 0 }
+}
+
+/// A match of the pattern, the part text[start..end] of the text aligned with it
+pub struct TextMatch<M: MetricSpace> {
+    pub start: usize,
+    pub end: usize,
+    pub cost: M::Cost,
+    pub align: Align<M>,
+}
+
+/// Recover the match of the pattern ending at the position `end` of the text, (end, cost) being yielded by
+/// [`sellers`], the shortest one if several start positions are as good. The start is found by aligning
+/// the suffixes of the pattern with the text from `end` backwards until the cost is reached,
+/// then the match is aligned with sol_2. O(m (end - start)) time, O(m + end - start) memory
+pub fn recover<M>(ms: &M, pattern: &[M::Item], text: &[M::Item], (end, cost): (usize, M::Cost)) -> TextMatch<M>
+where M: MetricSpace
+{
+    let best = M::Goal::best::<M::Cost>;
+    let mut col = vec![M::ZEROCOST; pattern.len() + 1];
+    for i in 1..col.len() {
+        col[i] = col[i - 1] + ms.del();
+    }
+    let mut start = end;
+    // col holds the costs of the alignments of the suffixes of the pattern with text[start..end]
+    while col[pattern.len()] != cost {
+        assert!(start > 0, "no match ends at {} for this cost!", end);
+        start -= 1;
+        let t = text[start];
+        let mut diag = col[0];
+        col[0] = col[0] + ms.ins();
+        for i in 1..col.len() {
+            let c = best(
+                diag + ms.sub(pattern[pattern.len() - i], t),
+                best(col[i] + ms.ins(), col[i - 1] + ms.del()),
+            );
+            diag = col[i];
+            col[i] = c;
+        }
+    }
+    TextMatch { start, end, cost, align: sol_2(ms, pattern, &text[start..end]) }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{Dna, DnaBlock, DnaMetricSpace as Dms, DnaSimilarity};
+    use crate::io::read_test_insts_all;
+    use crate::math::{MetricSpace, Objective, cout_align, dist_1, rm_gaps};
+    use crate::rng::Rng;
+    use super::{recover, sellers};
+
+    fn random(n: usize, rng: &mut Rng) -> Vec<Dna> {
+        let letters = [Dna::A, Dna::C, Dna::G, Dna::T];
+        (0..n).map(|_| letters[rng.below(4) as usize]).collect()
+    }
+
+    /// The best match ending at every position of the text, by brute force
+    fn brute<M: MetricSpace<Item = Dna>>(ms: &M, p: &[Dna], t: &[Dna]) -> Vec<M::Cost> {
+        (0..=t.len())
+            .map(|e| (0..=e).map(|s| dist_1(ms, p, &t[s..e])).reduce(M::Goal::best).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn planted(){
+        let mut rng = Rng::new(38);
+        let mut text = random(300, &mut rng);
+        let pattern = random(12, &mut rng);
+        text[100..112].copy_from_slice(&pattern);
+        let mut mutated = pattern.clone();
+        mutated.remove(4);
+        text[200..211].copy_from_slice(&mutated);
+
+        let ends = sellers(&Dms, &pattern, &text, 2).collect::<Vec<_>>();
+        assert!(ends.contains(&(112, 0)));
+        assert!(ends.contains(&(211, 2)));
+        let m = recover(&Dms, &pattern, &text, (211, 2));
+        assert_eq!((m.start, m.cost), (200, 2));
+        assert_eq!(rm_gaps::<Dms>(m.align.1.clone()), mutated);
+        assert_eq!(recover(&Dms, &pattern, &text, (112, 0)).start, 100);
+    }
+
+    #[test]
+    fn against_brute_force(){
+        let sim = DnaSimilarity::default();
+        let testcases = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 20)
+            .map(|(_, b)| b);
+        for DnaBlock(l, r) in testcases {
+            let p = &r[..r.len() / 2];
+            let costs = brute(&Dms, p, &l);
+            for k in [0, 4, 10] {
+                let ends = sellers(&Dms, p, &l, k).collect::<Vec<_>>();
+                let expected = costs.iter().enumerate().filter(|&(_, &c)| c <= k).map(|(e, &c)| (e, c)).collect::<Vec<_>>();
+                assert_eq!(ends, expected);
+            }
+            for (e, &c) in costs.iter().enumerate() {
+                let m = recover(&Dms, p, &l, (e, c));
+                assert_eq!(m.cost, c);
+                assert_eq!(cout_align(&Dms, &m.align.0, &m.align.1), c);
+                assert_eq!(rm_gaps::<Dms>(m.align.0), p.to_vec(), "some letters 🍪 got eaten");
+                assert_eq!(rm_gaps::<Dms>(m.align.1), l[m.start..e].to_vec(), "some letters 🍪 got eaten");
+                assert!((m.start..e).all(|s| dist_1(&Dms, p, &l[s..e]) > c || s == m.start), "the match is not the shortest");
+            }
+
+            let scores = brute(&sim, p, &l);
+            let ends = sellers(&sim, p, &l, 2).collect::<Vec<_>>();
+            assert_eq!(ends, scores.iter().enumerate().filter(|&(_, &c)| c >= 2).map(|(e, &c)| (e, c)).collect::<Vec<_>>());
+            for (e, &c) in scores.iter().enumerate() {
+                let m = recover(&sim, p, &l, (e, c));
+                assert_eq!(cout_align(&sim, &m.align.0, &m.align.1), c);
+            }
+        }
+    }
+}
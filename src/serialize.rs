@@ -0,0 +1,115 @@
+//! Serialisation of the core types with serde, with the `serde` feature.
+//!
+//! The forms are compact: a [`Dna`] letter is a one letter string, a sequence a string of letters ("ACGT"),
+//! a [`DnaBlock`] the pair of its sequences and an [`Align`] the pair of its rows, the gaps being '-' ("AC-T").
+//! The costs are plain integers already. Sequences within other types can be serialised as strings too
+//! with `#[serde(with = "runa::serialize::seq")]`, as in [`FastaRecord`](crate::io::FastaRecord).
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::dna::{Dna, DnaBlock};
+use crate::math::*;
+
+fn letter<E: serde::de::Error>(c: char) -> Result<Dna, E> {
+    c.to_string().parse().map_err(E::custom)
+}
+
+fn row<E: serde::de::Error>(s: &str) -> Result<Vec<Gapped<Dna>>, E> {
+    s.chars().map(|c| if c == '-' { Ok(Gapped::Gap) } else { letter(c).map(Gapped::Item) }).collect()
+}
+
+fn string<T: std::fmt::Display>(s: &[T]) -> String {
+    s.iter().map(|d| d.to_string()).collect()
+}
+
+impl Serialize for Dna {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Dna {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d)?.parse().map_err(D::Error::custom)
+    }
+}
+
+/// A sequence as a string of letters, for `#[serde(with = "runa::serialize::seq")]`
+pub mod seq {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(seq: &[Dna], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&string(seq))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Dna>, D::Error> {
+        String::deserialize(d)?.chars().map(letter).collect()
+    }
+}
+
+impl Serialize for DnaBlock {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        (string(&self.0), string(&self.1)).serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for DnaBlock {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let (x, y) = <(String, String)>::deserialize(d)?;
+        let seq = |s: String| s.chars().map(letter).collect::<Result<Vec<_>, D::Error>>();
+        Ok(DnaBlock(seq(x)?, seq(y)?))
+    }
+}
+
+impl<M: MetricSpace<Item = Dna>> Serialize for Align<M> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        (string(&self.0), string(&self.1)).serialize(s)
+    }
+}
+
+impl<'de, M: MetricSpace<Item = Dna>> Deserialize<'de> for Align<M> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let (x, y) = <(String, String)>::deserialize(d)?;
+        let (x, y) = (row::<D::Error>(&x)?, row(&y)?);
+        if x.len() != y.len() {
+            return Err(D::Error::custom(format!("the rows of the alignment differ in length, {} and {}", x.len(), y.len())));
+        }
+        Ok(Align(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{Dna, DnaBlock, DnaMetricSpace as Dms, DnaSimilarity};
+    use crate::io::{FastaRecord, read_test_insts_all};
+    use crate::math::{Align, sol_2};
+
+    #[test]
+    fn round_trips(){
+        assert_eq!(serde_json::to_string(&Dna::G).unwrap(), "\"G\"");
+        assert_eq!(serde_json::from_str::<Dna>("\"t\"").unwrap(), Dna::T);
+        assert!(serde_json::from_str::<Dna>("\"U\"").is_err());
+
+        for (_, b) in read_test_insts_all().take_while(|&(size, _)| size <= 500) {
+            let s = serde_json::to_string(&b).unwrap();
+            assert_eq!(s.len(), b.0.len() + b.1.len() + 7);
+            let al = sol_2(&Dms, &b.0, &b.1);
+            let cost = al.cost(&Dms);
+            let t = serde_json::to_string(&(cost, &al)).unwrap();
+            assert_eq!(serde_json::from_str::<DnaBlock>(&s).unwrap(), b);
+            assert_eq!(serde_json::from_str::<(u64, Align<Dms>)>(&t).unwrap(), (cost, al));
+        }
+
+        let al = serde_json::from_str::<Align<Dms>>("[\"AC-T\",\"A-GT\"]").unwrap();
+        assert_eq!(serde_json::to_string(&al).unwrap(), "[\"AC-T\",\"A-GT\"]");
+        assert!(serde_json::from_str::<Align<Dms>>("[\"AC-T\",\"AGT\"]").is_err());
+
+        let r = FastaRecord { name: "x".to_string(), seq: vec![Dna::A, Dna::C] };
+        let s = serde_json::to_string(&r).unwrap();
+        assert_eq!(s, "{\"name\":\"x\",\"seq\":\"AC\"}");
+        assert_eq!(serde_json::from_str::<FastaRecord>(&s).unwrap(), r);
+        let sim = DnaSimilarity::default();
+        assert_eq!(serde_json::from_str::<DnaSimilarity>(&serde_json::to_string(&sim).unwrap()).unwrap(), sim);
+    }
+}
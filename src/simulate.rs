@@ -0,0 +1,286 @@
+//! Simulated evolution, to measure how accurate the alignments are and not only how fast.
+//!
+//! A sequence evolves along a branch of a given length (in expected substitutions per letter) under a
+//! substitution model, JC69, K80 or HKY, and with insertions and deletions whose lengths are geometric.
+//! As every column is known, the simulation gives the true alignment of the two sequences along with them,
+//! and [`accuracy`] scores a computed alignment against it.
+//!
+//! The substitution probabilities are those of the continuous time Markov chain of the model,
+//! P(t) = exp(Qt) with Q normalised to one substitution per letter and per unit of time.
+
+use std::collections::HashSet;
+
+use crate::dna::Dna;
+use crate::math::*;
+use crate::rng::Rng;
+
+/// The letters in the order of the rows and columns of the matrices, and of the frequencies
+const LETTERS: [Dna; 4] = [Dna::A, Dna::C, Dna::G, Dna::T];
+
+type Matrix = [[f64; 4]; 4];
+
+fn index(d: Dna) -> usize {
+    match d {
+        Dna::A => 0,
+        Dna::C => 1,
+        Dna::G => 2,
+        Dna::T => 3,
+    }
+}
+
+/// A model of the substitutions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubstModel {
+    /// Jukes and Cantor (1969), all the substitutions are equally likely
+    JC69,
+    /// Kimura (1980), the transitions (A/G and C/T) are kappa times more likely than the transversions
+    K80 { kappa: f64 },
+    /// Hasegawa, Kishino and Yano (1985), K80 with the equilibrium frequencies of A, C, G and T
+    HKY { kappa: f64, freqs: [f64; 4] },
+}
+
+impl SubstModel {
+    /// The equilibrium frequencies of A, C, G and T
+    pub fn freqs(&self) -> [f64; 4] {
+        match *self {
+            SubstModel::HKY { freqs, .. } => {
+                let sum = freqs.iter().sum::<f64>();
+                freqs.map(|f| f / sum)
+            }
+            _ => [0.25; 4],
+        }
+    }
+
+    /// The rate matrix Q, normalised so that there is on average one substitution per unit of time
+    pub fn rates(&self) -> Matrix {
+        let kappa = match *self {
+            SubstModel::JC69 => 1.0,
+            SubstModel::K80 { kappa } | SubstModel::HKY { kappa, .. } => kappa,
+        };
+        let pi = self.freqs();
+        let mut q = [[0.0; 4]; 4];
+        for (i, r) in q.iter_mut().enumerate() {
+            for j in (0..4).filter(|&j| j != i) {
+                // A/G and C/T are the transitions
+                r[j] = pi[j] * if i % 2 == j % 2 { kappa } else { 1.0 };
+            }
+            r[i] = -r.iter().sum::<f64>();
+        }
+        let mu = -(0..4).map(|i| pi[i] * q[i][i]).sum::<f64>();
+        q.map(|r| r.map(|x| x / mu))
+    }
+
+    /// The probabilities P(t) of the letters (columns) after a time t, starting from every letter (rows)
+    pub fn probabilities(&self, t: f64) -> Matrix {
+        exp(self.rates(), t)
+    }
+}
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut res = [[0.0; 4]; 4];
+    for (i, r) in res.iter_mut().enumerate() {
+        for (j, x) in r.iter_mut().enumerate() {
+            *x = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    res
+}
+
+/// exp(Qt), by scaling and squaring: the series of exp(Qt / 2^s) converges in a few terms, and is then squared s times
+fn exp(q: Matrix, t: f64) -> Matrix {
+    let norm = q.iter().map(|r| r.iter().map(|x| x.abs()).sum::<f64>()).fold(0.0, f64::max) * t;
+    let s = norm.max(1.0).log2().ceil() as i32 + 4;
+    let a = q.map(|r| r.map(|x| x * t / 2f64.powi(s)));
+    let id = std::array::from_fn(|i| std::array::from_fn(|j| if i == j { 1.0 } else { 0.0 }));
+    let (mut res, mut term) = (id, id);
+    for k in 1..=12 {
+        term = mul(&term, &a).map(|r| r.map(|x| x / k as f64));
+        for (r, u) in res.iter_mut().zip(&term) {
+            for (x, y) in r.iter_mut().zip(u) { *x += y; }
+        }
+    }
+    for _ in 0..s {
+        res = mul(&res, &res);
+    }
+    res
+}
+
+/// A letter drawn with the given probabilities of A, C, G and T
+fn draw(p: &[f64; 4], rng: &mut Rng) -> Dna {
+    let mut u = rng.f64();
+    for (k, &x) in p.iter().enumerate() {
+        if u < x { return LETTERS[k]; }
+        u -= x;
+    }
+    LETTERS[3]
+}
+
+/// The parameters of the evolution along a branch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Evolution {
+    pub model: SubstModel,
+    /// the length of the branch, in expected substitutions per letter
+    pub time: f64,
+    /// the probability that an indel starts before a letter, insertions and deletions being equally likely
+    pub indel_rate: f64,
+    /// the probability that an indel goes on after each of its letters, the mean length is 1 / (1 - indel_extension)
+    pub indel_extension: f64,
+}
+
+impl Default for Evolution {
+    fn default() -> Self {
+        Evolution { model: SubstModel::JC69, time: 0.1, indel_rate: 0.01, indel_extension: 0.5 }
+    }
+}
+
+/// Two sequences and their true alignment
+#[derive(Debug, PartialEq)]
+pub struct Simulation<M: MetricSpace<Item = Dna>> {
+    pub x: Vec<Dna>,
+    pub y: Vec<Dna>,
+    pub align: Align<M>,
+}
+
+/// Evolve x along a branch, the descendant is y
+pub fn evolve<M>(x: &[Dna], evo: &Evolution, rng: &mut Rng) -> Simulation<M>
+where M: MetricSpace<Item = Dna>
+{
+    let p = evo.model.probabilities(evo.time);
+    let freqs = evo.model.freqs();
+    let length = |rng: &mut Rng| {
+        let mut l = 1;
+        while rng.f64() < evo.indel_extension { l += 1; }
+        l
+    };
+    let (mut rx, mut ry) = (vec![], vec![]);
+    let mut deleting = 0;
+    for &d in x {
+        if deleting == 0 && rng.f64() < evo.indel_rate {
+            if rng.below(2) == 0 {
+                for _ in 0..length(rng) {
+                    rx.push(Gapped::Gap);
+                    ry.push(Gapped::Item(draw(&freqs, rng)));
+                }
+            } else {
+                deleting = length(rng);
+            }
+        }
+        rx.push(Gapped::Item(d));
+        if deleting > 0 {
+            deleting -= 1;
+            ry.push(Gapped::Gap);
+        } else {
+            ry.push(Gapped::Item(draw(&p[index(d)], rng)));
+        }
+    }
+    let y = ry.iter().filter_map(|g| g.item()).collect();
+    Simulation { x: x.to_vec(), y, align: Align(rx, ry) }
+}
+
+/// How close an alignment is to the true alignment of the same sequences
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Accuracy {
+    /// the fraction of the pairs of letters aligned by the truth which the alignment aligns too (sum-of-pairs score)
+    pub sum_of_pairs: f64,
+    /// the fraction of the columns of the truth, gapped or not, which are columns of the alignment (column score)
+    pub column: f64,
+}
+
+/// The columns of an alignment, as the positions of their letters in x and y
+fn columns<M: MetricSpace>(al: &Align<M>) -> Vec<(Option<usize>, Option<usize>)> {
+    let (mut i, mut j) = (0, 0);
+    al.0.iter().zip(&al.1).map(|(a, b)| {
+        let c = (a.item().map(|_| i), b.item().map(|_| j));
+        i += !a.is_gap() as usize;
+        j += !b.is_gap() as usize;
+        c
+    }).collect()
+}
+
+/// Score the alignment against the true one, they have to align the same sequences
+pub fn accuracy<M: MetricSpace>(truth: &Align<M>, al: &Align<M>) -> Accuracy {
+    let (t, a) = (columns(truth), columns(al));
+    let count = |c: &[(Option<usize>, Option<usize>)]| c.iter().fold((0, 0), |(n, m), p| (n + p.0.is_some() as usize, m + p.1.is_some() as usize));
+    assert_eq!(count(&t), count(&a), "the alignments are not of the same sequences!");
+    let a = a.into_iter().collect::<HashSet<_>>();
+    let ratio = |n: usize, total: usize| if total == 0 { 1.0 } else { n as f64 / total as f64 };
+    let pairs = t.iter().filter(|c| c.0.is_some() && c.1.is_some());
+    Accuracy {
+        sum_of_pairs: ratio(pairs.clone().filter(|c| a.contains(c)).count(), pairs.count()),
+        column: ratio(t.iter().filter(|c| a.contains(c)).count(), t.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{Dna, DnaMetricSpace as Dms};
+    use crate::generate::random_sequence;
+    use crate::math::{Align, Gapped, sol_2};
+    use crate::rng::Rng;
+    use super::{Accuracy, Evolution, SubstModel, accuracy, evolve};
+
+    #[test]
+    fn models(){
+        let hky = SubstModel::HKY { kappa: 3.0, freqs: [0.1, 0.4, 0.3, 0.2] };
+        for model in [SubstModel::JC69, SubstModel::K80 { kappa: 4.0 }, hky] {
+            let pi = model.freqs();
+            for t in [0.0, 0.01, 0.3, 2.0, 50.0] {
+                let p = model.probabilities(t);
+                for (i, r) in p.iter().enumerate() {
+                    assert!((r.iter().sum::<f64>() - 1.0).abs() < 1e-9, "{:?} {} {:?}", model, t, r);
+                    if t == 0.0 { assert_eq!(r[i], 1.0); }
+                    // the chain forgets where it started
+                    if t == 50.0 { assert!(r.iter().zip(&pi).all(|(x, f)| (x - f).abs() < 1e-6), "{:?}", r); }
+                }
+                // one substitution per unit of time, at the start
+                if t == 0.01 {
+                    let changed = 1.0 - (0..4).map(|i| pi[i] * p[i][i]).sum::<f64>();
+                    assert!((changed - t).abs() < 1e-3, "{:?} {}", model, changed);
+                }
+            }
+        }
+        let t = 0.3f64;
+        let p = SubstModel::JC69.probabilities(t);
+        assert!((p[0][0] - (0.25 + 0.75 * (-4.0 * t / 3.0).exp())).abs() < 1e-12);
+        assert!((p[0][1] - (0.25 - 0.25 * (-4.0 * t / 3.0).exp())).abs() < 1e-12);
+        // A to G is a transition, A to C a transversion
+        let p = SubstModel::K80 { kappa: 4.0 }.probabilities(t);
+        assert!(p[0][2] > 2.0 * p[0][1]);
+    }
+
+    #[test]
+    fn evolution(){
+        let mut rng = Rng::new(47);
+        let x = random_sequence(20000, &mut rng);
+        let evo = Evolution { time: 0.2, indel_rate: 0.0, ..Default::default() };
+        let s = evolve::<Dms>(&x, &evo, &mut rng);
+        assert_eq!((s.x.len(), s.y.len(), s.align.0.len()), (x.len(), x.len(), x.len()));
+        let p = x.iter().zip(&s.y).filter(|(a, b)| a != b).count() as f64 / x.len() as f64;
+        assert!((p - 0.75 * (1.0 - (-4.0 * 0.2f64 / 3.0).exp())).abs() < 0.01, "{}", p);
+
+        let evo = Evolution { indel_rate: 0.02, indel_extension: 0.5, ..Default::default() };
+        let s = evolve::<Dms>(&x, &evo, &mut rng);
+        let row = |r: &[Gapped<Dna>]| r.iter().filter_map(|g| g.item()).collect::<Vec<_>>();
+        assert_eq!((row(&s.align.0), row(&s.align.1)), (s.x.clone(), s.y.clone()));
+        assert!(!s.align.0.iter().zip(&s.align.1).any(|(a, b)| a.is_gap() && b.is_gap()));
+        // about 0.02 indels per letter, of mean length 2
+        let gaps = s.align.0.iter().chain(&s.align.1).filter(|g| g.is_gap()).count() as f64 / x.len() as f64;
+        assert!((0.03..0.05).contains(&gaps), "{} gaps per letter", gaps);
+        assert_eq!(evolve::<Dms>(&x[..100], &evo, &mut Rng::new(1)), evolve::<Dms>(&x[..100], &evo, &mut Rng::new(1)));
+    }
+
+    #[test]
+    fn scores(){
+        let row = |s: &str| s.split_whitespace().map(|g| g.parse::<Gapped<Dna>>().unwrap()).collect::<Vec<_>>();
+        let truth = Align::<Dms>(row("A C G"), row("A - G"));
+        assert_eq!(accuracy(&truth, &truth), Accuracy { sum_of_pairs: 1.0, column: 1.0 });
+        let al = Align::<Dms>(row("A C G -"), row("A - - G"));
+        assert_eq!(accuracy(&truth, &al), Accuracy { sum_of_pairs: 0.5, column: 2.0 / 3.0 });
+
+        let mut rng = Rng::new(7);
+        let x = random_sequence(400, &mut rng);
+        let s = evolve::<Dms>(&x, &Evolution::default(), &mut rng);
+        let a = accuracy(&s.align, &sol_2(&Dms, &s.x, &s.y));
+        assert!(a.sum_of_pairs > 0.8 && a.column > 0.8, "{:?}", a);
+    }
+}
@@ -0,0 +1,358 @@
+//! Statistical significance of alignment scores.
+//!
+//! A score only means something when compared to the scores of unrelated sequences of the same composition,
+//! these are obtained by shuffling the inputs while keeping their dinucleotide counts (Altschul-Erickson).
+//! The empirical distribution of the shuffled scores gives p-values for any score (see [`shuffle_test`]),
+//! and for local alignments the scores follow an extreme value distribution whose Karlin-Altschul
+//! parameters lambda and K can be fitted (see [`Gumbel`]) to get E-values.
+//! Everything random is driven by a seeded [`Rng`] so the results are reproducible.
+
+use crate::math::*;
+use crate::rng::Rng;
+
+/// Shuffle a sequence while keeping the number of occurences of every pair of neighbours
+/// (and thus of every letter), as well as its first and last letters.
+/// Every such sequence is equally likely (Altschul-Erickson). O(n) time
+pub fn dinucleotide_shuffle<T: Copy + PartialEq>(s: &[T], rng: &mut Rng) -> Vec<T> {
+    if s.len() <= 2 { return s.to_vec(); }
+    let mut letters: Vec<T> = vec![];
+    let idx = s
+        .iter()
+        .map(|&c| letters.iter().position(|&l| l == c).unwrap_or_else(|| {
+            letters.push(c);
+            letters.len() - 1
+        }))
+        .collect::<Vec<_>>();
+
+    // the sequence is an eulerian path of the graph of its pairs, from its first to its last letter
+    let mut edges = vec![vec![]; letters.len()];
+    for w in idx.windows(2) {
+        edges[w[0]].push(w[1]);
+    }
+    let last = idx[idx.len() - 1];
+
+    // the last edge leaving every letter is chosen so that they form a random tree towards the last letter (Wilson)
+    let mut in_tree = vec![false; letters.len()];
+    in_tree[last] = true;
+    let mut next = vec![0; letters.len()];
+    for start in 0..letters.len() {
+        let mut u = start;
+        while !in_tree[u] {
+            next[u] = rng.below(edges[u].len() as u64) as usize;
+            u = edges[u][next[u]];
+        }
+        u = start;
+        while !in_tree[u] {
+            in_tree[u] = true;
+            u = edges[u][next[u]];
+        }
+    }
+    // the other edges can then be used in any order
+    for (u, e) in edges.iter_mut().enumerate() {
+        if u == last {
+            rng.shuffle(e);
+        } else {
+            let l = e.swap_remove(next[u]);
+            rng.shuffle(e);
+            e.push(l);
+        }
+        e.reverse();
+    }
+
+    let mut res = vec![s[0]];
+    let mut u = idx[0];
+    while let Some(w) = edges[u].pop() {
+        res.push(letters[w]);
+        u = w;
+    }
+    res
+}
+
+/// The frequencies of the letters of a sequence, in the order they first appear
+pub fn frequencies<T: Copy + PartialEq>(s: &[T]) -> Vec<(T, f64)> {
+    let mut counts: Vec<(T, usize)> = vec![];
+    for &c in s {
+        match counts.iter_mut().find(|(l, _)| *l == c) {
+            Some((_, k)) => *k += 1,
+            None => counts.push((c, 1)),
+        }
+    }
+    counts.into_iter().map(|(l, k)| (l, k as f64 / s.len() as f64)).collect()
+}
+
+/// The mean and standard deviation of some numbers
+fn mean_sd(v: &[f64]) -> (f64, f64) {
+    let n = v.len() as f64;
+    let mean = v.iter().sum::<f64>() / n;
+    let var = v.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1.0);
+    (mean, var.sqrt())
+}
+
+/// The outcome of a [`shuffle_test`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Empirical {
+    /// the score of the actual sequences
+    pub score: f64,
+    /// the mean of the scores of the shuffled sequences
+    pub mean: f64,
+    /// the standard deviation of the scores of the shuffled sequences
+    pub sd: f64,
+    /// how many standard deviations the score is better than the mean, positive when better.
+    /// When all the shuffles score the same (sd is 0, as for a sequence of a single letter)
+    /// it is 0 if the score is the mean too, and infinite otherwise
+    pub z_score: f64,
+    /// the probability of a score at least as good for unrelated sequences
+    pub p_value: f64,
+}
+
+/// Compare the score of x and y to the scores of `shuffles` dinucleotide shuffles of them,
+/// the score being computed by f (for example dist_2 or dist_local).
+/// The p-value is (1 + the number of shuffles scoring at least as well) / (1 + shuffles),
+/// so it can't go below 1 / (1 + shuffles). O(shuffles) calls to f
+pub fn shuffle_test<M, F>(ms: &M, x: &[M::Item], y: &[M::Item], shuffles: usize, rng: &mut Rng, f: F) -> Empirical
+where M: MetricSpace, M::Cost: ToF64, M::Item: PartialEq, F: Fn(&M, &[M::Item], &[M::Item]) -> M::Cost
+{
+    assert!(shuffles >= 2, "not enough shuffles!");
+    let observed = f(ms, x, y);
+    let mut as_good = 0;
+    let mut dists = Vec::with_capacity(shuffles);
+    for _ in 0..shuffles {
+        let (xs, ys) = (dinucleotide_shuffle(x, rng), dinucleotide_shuffle(y, rng));
+        let c = f(ms, &xs, &ys);
+        if !M::Goal::better(&observed, &c) { as_good += 1; }
+        dists.push(M::Goal::distance(c.to_f64()));
+    }
+    let (mean, sd) = mean_sd(&dists);
+    let d = M::Goal::distance(observed.to_f64());
+    Empirical {
+        score: observed.to_f64(),
+        mean: M::Goal::distance(mean),
+        sd,
+        z_score: if sd > 0.0 { (mean - d) / sd } else if mean == d { 0.0 } else { (mean - d).signum() * f64::INFINITY },
+        p_value: (1 + as_good) as f64 / (1 + shuffles) as f64,
+    }
+}
+
+/// The extreme value distribution of the local alignment scores of unrelated sequences of lengths m and n
+/// (Karlin-Altschul): the number of alignments scoring at least S is expected to be K m n exp(-lambda S)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gumbel {
+    pub lambda: f64,
+    pub k: f64,
+}
+
+impl Gumbel {
+    /// Fit the distribution to the best local scores of unrelated sequences of lengths m and n,
+    /// by the method of moments. None if all the scores are the same, there is nothing to fit then
+    pub fn fit(scores: &[f64], m: usize, n: usize) -> Option<Self> {
+        assert!(scores.len() >= 2, "not enough scores!");
+        let (mean, sd) = mean_sd(scores);
+        if sd == 0.0 { return None; }
+        // the Euler-Mascheroni constant
+        let gamma = 0.5772156649015329;
+        let lambda = std::f64::consts::PI / (sd * 6f64.sqrt());
+        let mu = mean - gamma / lambda;
+        Some(Gumbel { lambda, k: (lambda * mu).exp() / (m as f64 * n as f64) })
+    }
+
+    /// The expected number of alignments scoring at least `score` between unrelated sequences of lengths m and n
+    pub fn e_value(&self, score: f64, m: usize, n: usize) -> f64 {
+        self.k * m as f64 * n as f64 * (-self.lambda * score).exp()
+    }
+
+    /// The probability that the best local alignment of unrelated sequences of lengths m and n
+    /// scores at least `score`
+    pub fn p_value(&self, score: f64, m: usize, n: usize) -> f64 {
+        -(-self.e_value(score, m, n)).exp_m1()
+    }
+
+    /// The score normalised to bits, independent of the scoring scheme
+    pub fn bit_score(&self, score: f64) -> f64 {
+        (self.lambda * score - self.k.ln()) / std::f64::consts::LN_2
+    }
+}
+
+/// Fit the Karlin-Altschul parameters of the local scores (dist_local) of `shuffles` dinucleotide
+/// shuffles of x and y, this works for gapped alignments as well.
+/// None if the shuffles all score the same (see [`Gumbel::fit`]). O(shuffles n^2) time
+pub fn fit_local<M>(ms: &M, x: &[M::Item], y: &[M::Item], shuffles: usize, rng: &mut Rng) -> Option<Gumbel>
+where M: MetricSpace<Goal = Maximise>, M::Cost: ToF64, M::Item: PartialEq
+{
+    let scores = (0..shuffles)
+        .map(|_| dist_local(ms, &dinucleotide_shuffle(x, rng), &dinucleotide_shuffle(y, rng)).to_f64())
+        .collect::<Vec<_>>();
+    Gumbel::fit(&scores, x.len(), y.len())
+}
+
+/// The significance of a local alignment score
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Significance {
+    pub score: f64,
+    pub e_value: f64,
+    pub p_value: f64,
+}
+
+/// The significance of the best local alignment of x and y,
+/// against the distribution fitted on `shuffles` shuffles of them (see [`fit_local`]).
+/// None if no distribution can be fitted, when shuffling x and y doesn't change their score
+pub fn local_significance<M>(ms: &M, x: &[M::Item], y: &[M::Item], shuffles: usize, rng: &mut Rng) -> Option<Significance>
+where M: MetricSpace<Goal = Maximise>, M::Cost: ToF64, M::Item: PartialEq
+{
+    let g = fit_local(ms, x, y, shuffles, rng)?;
+    let score = dist_local(ms, x, y).to_f64();
+    Some(Significance {
+        score,
+        e_value: g.e_value(score, x.len(), y.len()),
+        p_value: g.p_value(score, x.len(), y.len()),
+    })
+}
+
+/// The Karlin-Altschul lambda of ungapped local alignments of sequences with the given letter frequencies,
+/// the positive solution of sum p_a p_b exp(lambda s(a, b)) = 1.
+/// None if the expected score of a pair is not negative or if no pair has a positive score
+pub fn ungapped_lambda<M>(ms: &M, freqs: &[(M::Item, f64)]) -> Option<f64>
+where M: MetricSpace<Goal = Maximise>, M::Cost: ToF64
+{
+    let pairs = freqs
+        .iter()
+        .flat_map(|&(a, pa)| freqs.iter().map(move |&(b, pb)| (pa * pb, ms.sub(a, b).to_f64())))
+        .collect::<Vec<_>>();
+    let expected = pairs.iter().map(|(p, s)| p * s).sum::<f64>();
+    if expected >= 0.0 || pairs.iter().all(|&(p, s)| p == 0.0 || s <= 0.0) { return None; }
+
+    let f = |l: f64| pairs.iter().map(|(p, s)| p * (l * s).exp()).sum::<f64>() - 1.0;
+    let mut hi = 1.0;
+    while f(hi) < 0.0 { hi *= 2.0; }
+    let mut lo = 0.0;
+    // f is negative right after 0 and convex, so it only crosses 0 once
+    for _ in 0..100 {
+        let mid = (lo + hi) / 2.0;
+        if f(mid) < 0.0 { lo = mid; } else { hi = mid; }
+    }
+    Some((lo + hi) / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{Dna, DnaBlock, DnaMetricSpace as Dms, DnaSimilarity};
+    use crate::io::{read_test_inst, read_test_insts_all};
+    use crate::math::{dist_2, dist_local};
+    use crate::rng::Rng;
+    use super::*;
+
+    fn pairs(s: &[Dna]) -> Vec<(Dna, Dna)> {
+        let mut p = s.windows(2).map(|w| (w[0], w[1])).collect::<Vec<_>>();
+        p.sort_by_key(|&(a, b)| (a as u8, b as u8));
+        p
+    }
+
+    fn random(n: usize, rng: &mut Rng) -> Vec<Dna> {
+        let letters = [Dna::A, Dna::C, Dna::G, Dna::T];
+        (0..n).map(|_| letters[rng.below(4) as usize]).collect()
+    }
+
+    #[test]
+    fn shuffle(){
+        let mut rng = Rng::new(35);
+        let testcases = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 500)
+            .flat_map(|(_, DnaBlock(l, r))| [l, r]);
+        for s in testcases {
+            let t = dinucleotide_shuffle(&s, &mut rng);
+            assert_eq!(t.len(), s.len(), "some letters 🍪 got eaten in the shuffle");
+            assert_eq!((t[0], t[t.len() - 1]), (s[0], s[s.len() - 1]));
+            assert_eq!(pairs(&t), pairs(&s));
+        }
+
+        // every shuffle of ACAGA keeps A at both ends, the pairs AC CA AG GA allow ACAGA and AGACA
+        let s = "ACAGA".chars().map(|c| c.to_string().parse().unwrap()).collect::<Vec<Dna>>();
+        let swapped = (0..1000).filter(|_| dinucleotide_shuffle(&s, &mut rng) != s).count();
+        assert!((400..600).contains(&swapped), "not uniform: {} / 1000", swapped);
+
+        let s = random(200, &mut rng);
+        assert_eq!(dinucleotide_shuffle(&s, &mut Rng::new(1)), dinucleotide_shuffle(&s, &mut Rng::new(1)));
+        assert_ne!(dinucleotide_shuffle(&s, &mut Rng::new(1)), s);
+    }
+
+    #[test]
+    fn empirical(){
+        let DnaBlock(l, r) = read_test_inst("Inst_0000100_3.adn").expect("couldn't read instance!");
+        let t = shuffle_test(&Dms, &l, &r, 99, &mut Rng::new(1), dist_2);
+        assert_eq!(t.score, dist_2(&Dms, &l, &r) as f64);
+        assert!(t.p_value >= 0.01 && t.p_value <= 1.0);
+        assert_eq!(t, shuffle_test(&Dms, &l, &r, 99, &mut Rng::new(1), dist_2));
+
+        // a sequence against a slightly mutated copy of itself is as significant as it gets
+        let mut rng = Rng::new(2);
+        let x = random(100, &mut rng);
+        let mut y = x.clone();
+        for k in (0..100).step_by(10) { y[k] = Dna::A; }
+        let t = shuffle_test(&Dms, &x, &y, 99, &mut rng, dist_2);
+        assert_eq!(t.p_value, 0.01);
+        assert!(t.z_score > 5.0, "z = {}", t.z_score);
+        assert!(t.mean > t.score);
+
+        let sim = DnaSimilarity::default();
+        let t = shuffle_test(&sim, &x, &y, 99, &mut rng, dist_local);
+        assert_eq!(t.p_value, 0.01);
+        assert!(t.z_score > 5.0 && t.mean < t.score);
+
+        // shuffling a single letter changes nothing, all the shuffles score the same
+        let (a, b) = (vec![Dna::A; 30], vec![Dna::A; 20]);
+        let t = shuffle_test(&Dms, &a, &b, 9, &mut rng, dist_2);
+        assert_eq!((t.sd, t.z_score, t.p_value), (0.0, 0.0, 1.0));
+        // a score (the first one computed) better than all the shuffles
+        let calls = std::cell::Cell::new(0);
+        let t = shuffle_test(&Dms, &a, &b, 9, &mut rng, |ms, x, y| {
+            calls.set(calls.get() + 1);
+            dist_2(ms, x, y) - (calls.get() == 1) as u64
+        });
+        assert_eq!(t.z_score, f64::INFINITY);
+    }
+
+    #[test]
+    fn gumbel_fit(){
+        // samples of a known distribution, by inverting its cumulative distribution
+        let (lambda, k, m, n) = (0.3, 0.05, 200, 300);
+        let mu = (k * m as f64 * n as f64).ln() / lambda;
+        let mut rng = Rng::new(3);
+        let samples = (0..20000).map(|_| mu - (-rng.f64().ln()).ln() / lambda).collect::<Vec<_>>();
+        let g = Gumbel::fit(&samples, m, n).expect("nothing to fit!");
+        assert!((g.lambda - lambda).abs() < 0.01, "lambda = {}", g.lambda);
+        assert!((g.k / k).ln().abs() < 0.2, "K = {}", g.k);
+        assert!((g.p_value(mu, m, n) - (1.0 - (-1f64).exp())).abs() < 0.05);
+        assert!(g.e_value(mu + 10.0, m, n) < g.e_value(mu, m, n));
+        assert_eq!(Gumbel::fit(&[7.0; 10], m, n), None);
+    }
+
+    #[test]
+    fn ungapped(){
+        // +1/-1 with uniform letters: exp(lambda) / 4 + 3 exp(-lambda) / 4 = 1, so lambda = ln 3
+        let sim = DnaSimilarity { matched: 1, mismatch: -1, gap: -2 };
+        let freqs = [Dna::A, Dna::C, Dna::G, Dna::T].map(|d| (d, 0.25));
+        let l = ungapped_lambda(&sim, &freqs).expect("no lambda!");
+        assert!((l - 3f64.ln()).abs() < 1e-9, "lambda = {}", l);
+        let positive = DnaSimilarity { matched: 1, mismatch: 1, gap: -2 };
+        assert_eq!(ungapped_lambda(&positive, &freqs), None);
+        let s = random(1000, &mut Rng::new(4));
+        let f = frequencies(&s);
+        assert_eq!(f.len(), 4);
+        assert!((f.iter().map(|(_, p)| p).sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn local_evalues(){
+        let sim = DnaSimilarity::default();
+        let mut rng = Rng::new(5);
+        let x = random(150, &mut rng);
+        let mut y = random(150, &mut rng);
+        y[50..100].copy_from_slice(&x[20..70]);
+        let related = local_significance(&sim, &x, &y, 100, &mut rng).expect("no significance!");
+        assert_eq!(related.score, dist_local(&sim, &x, &y) as f64);
+        assert!(related.e_value < 1e-6, "E = {}", related.e_value);
+        let unrelated = local_significance(&sim, &x, &random(150, &mut rng), 100, &mut rng).expect("no significance!");
+        assert!(unrelated.e_value > 1e-3, "E = {}", unrelated.e_value);
+        assert!(unrelated.p_value > related.p_value);
+        assert_eq!(local_significance(&sim, &[Dna::A; 40], &[Dna::A; 30], 10, &mut rng), None);
+    }
+}
@@ -0,0 +1,309 @@
+//! Suboptimal alignments, for when the single optimum is not enough (repeats, alternative homologies...).
+//!
+//! Globally, the alignments are the paths of the dp table from (0, 0) to (n, m) and they are enumerated
+//! from the best to the worst by a best-first search from (n, m), where the table of dist_dp_full gives
+//! the exact cost of the best way to complete every partial path.
+//! Locally, the alignments are found one after the other à la Waterman-Eggert: the next best local alignment
+//! is the best one which does not align any pair of letters already aligned by the previous ones.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::rc::Rc;
+
+use crate::cooptimal::{MOVES, column};
+use crate::math::*;
+
+/// A path of the dp table from (n, m) back to the cell (i, j),
+/// the paths going through the same cells share them
+struct Node {
+    i: usize,
+    j: usize,
+    /// the cell the path came from, None for (n, m)
+    next: Option<Rc<Node>>,
+}
+
+/// A path waiting in the queue of the search
+struct Entry<M: MetricSpace> {
+    /// the cost of the best alignment starting with this path
+    key: M::Cost,
+    /// the cost of the columns of the path
+    cost: M::Cost,
+    /// the order in which the entries were pushed, the last one wins ties
+    order: usize,
+    node: Rc<Node>,
+}
+
+impl<M: MetricSpace> PartialEq for Entry<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<M: MetricSpace> Eq for Entry<M> {}
+
+impl<M: MetricSpace> PartialOrd for Entry<M> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<M: MetricSpace> Ord for Entry<M> {
+    /// the greatest entry is the best one, as BinaryHeap pops the greatest
+    fn cmp(&self, other: &Self) -> Ordering {
+        if M::Goal::better(&self.key, &other.key) { Ordering::Greater }
+        else if M::Goal::better(&other.key, &self.key) { Ordering::Less }
+        else { self.order.cmp(&other.order) }
+    }
+}
+
+/// Lazy enumeration of the alignments from the best to the worst, see [`suboptimal_alignments`]
+pub struct Suboptimal<'a, M: MetricSpace> {
+    ms: &'a M,
+    x: &'a [M::Item],
+    y: &'a [M::Item],
+    t: &'a [Vec<M::Cost>],
+    heap: BinaryHeap<Entry<M>>,
+    pushed: usize,
+}
+
+impl<M: MetricSpace> Suboptimal<'_, M> {
+    fn push(&mut self, cost: M::Cost, node: Node) {
+        let key = self.t[node.i][node.j] + cost;
+        self.pushed += 1;
+        self.heap.push(Entry { key, cost, order: self.pushed, node: Rc::new(node) });
+    }
+}
+
+impl<M: MetricSpace> Iterator for Suboptimal<'_, M> {
+    type Item = (M::Cost, Align<M>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Entry { key, cost, node, .. } = self.heap.pop()?;
+            let (i, j) = (node.i, node.j);
+            if i == 0 && j == 0 {
+                let (mut xb, mut yb) = (vec![], vec![]);
+                let mut cur = &node;
+                while let Some(next) = &cur.next {
+                    let (a, b) = column(self.x, self.y, next.i, next.j, (next.i - cur.i, next.j - cur.j));
+                    xb.push(a);
+                    yb.push(b);
+                    cur = next;
+                }
+                return Some((key, Align(xb, yb)));
+            }
+            // the preferred move is pushed last so that it wins the ties, like in sol_1
+            for (di, dj) in MOVES.into_iter().rev() {
+                if i < di || j < dj { continue; }
+                let c = match (di, dj) {
+                    (1, 1) => self.ms.sub(self.x[i-1], self.y[j-1]),
+                    (0, 1) => self.ms.ins(),
+                    _ => self.ms.del(),
+                };
+                self.push(cost + c, Node { i: i - di, j: j - dj, next: Some(node.clone()) });
+            }
+        }
+    }
+}
+
+/// Enumerate lazily all the distinct alignments of x and y from the best to the worst, with their costs,
+/// t being the table of dist_dp_full. The first one is the alignment of sol_1,
+/// the k first ones cost O(k (n + m) log(k (n + m))) time and O(k (n + m)) memory.
+pub fn suboptimal_alignments<'a, M>(ms: &'a M, x: &'a [M::Item], y: &'a [M::Item], t: &'a [Vec<M::Cost>]) -> Suboptimal<'a, M>
+where M: MetricSpace
+{
+    assert_eq!(x.len() + 1, t.len());
+    assert_eq!(y.len() + 1, t[0].len());
+    let mut s = Suboptimal { ms, x, y, t, heap: BinaryHeap::new(), pushed: 0 };
+    s.push(M::ZEROCOST, Node { i: x.len(), j: y.len(), next: None });
+    s
+}
+
+/// The k best distinct alignments of x and y with their costs, from the best to the worst
+/// (fewer if there are less than k alignments). O(n^2 + k (n + m) log(k (n + m))) time
+pub fn k_best<M>(ms: &M, x: &[M::Item], y: &[M::Item], k: usize) -> Vec<(M::Cost, Align<M>)>
+where M: MetricSpace
+{
+    let t = dist_dp_full(ms, x, y);
+    suboptimal_alignments(ms, x, y, &t).take(k).collect()
+}
+
+/// The Smith-Waterman table of x and y, where the pairs (i, j) with used[i][j] can't be aligned
+fn local_table<M>(ms: &M, x: &[M::Item], y: &[M::Item], used: &[Vec<bool>]) -> Vec<Vec<M::Cost>>
+where M: MetricSpace<Goal = Maximise>
+{
+    let best = Maximise::best::<M::Cost>;
+    let mut t = vec![vec![M::ZEROCOST; y.len() + 1]; x.len() + 1];
+    for i in 0..=x.len() {
+        for j in 0..=y.len() {
+            let mut c = M::ZEROCOST;
+            if i > 0 && j > 0 && !used[i-1][j-1] { c = best(c, t[i-1][j-1] + ms.sub(x[i-1], y[j-1])); }
+            if j > 0 { c = best(c, t[i][j-1] + ms.ins()); }
+            if i > 0 { c = best(c, t[i-1][j] + ms.del()); }
+            t[i][j] = c;
+        }
+    }
+    t
+}
+
+/// The k best local alignments of x and y (Waterman-Eggert), from the best to the worst.
+/// Every alignment is the best one which aligns none of the pairs of letters aligned by the previous ones,
+/// the search stops early when no alignment with a positive score is left.
+/// Only makes sense for a similarity. O(k n^2) time and O(n^2) memory
+pub fn k_best_local<M>(ms: &M, x: &[M::Item], y: &[M::Item], k: usize) -> Vec<LocalAlign<M>>
+where M: MetricSpace<Goal = Maximise>
+{
+    let mut used = vec![vec![false; y.len()]; x.len()];
+    let mut res = vec![];
+    while res.len() < k {
+        let t = local_table(ms, x, y, &used);
+        let mut end = (M::ZEROCOST, 0, 0);
+        for (i, r) in t.iter().enumerate() {
+            for (j, &c) in r.iter().enumerate() {
+                if c > end.0 { end = (c, i, j); }
+            }
+        }
+        let (score, ie, je) = end;
+        if score <= M::ZEROCOST { break; }
+
+        let (mut xb, mut yb) = (vec![], vec![]);
+        let (mut i, mut j) = (ie, je);
+        let mut pairs = vec![];
+        while t[i][j] != M::ZEROCOST {
+            let (di, dj) = MOVES.into_iter().find(|&(di, dj)| match (di, dj) {
+                (1, 1) => i > 0 && j > 0 && !used[i-1][j-1] && t[i][j] == t[i-1][j-1] + ms.sub(x[i-1], y[j-1]),
+                (0, 1) => j > 0 && t[i][j] == t[i][j-1] + ms.ins(),
+                _ => i > 0 && t[i][j] == t[i-1][j] + ms.del(),
+            }).expect("broken local table!");
+            if (di, dj) == (1, 1) { pairs.push((i - 1, j - 1)); }
+            let (a, b) = column(x, y, i, j, (di, dj));
+            xb.push(a);
+            yb.push(b);
+            i -= di;
+            j -= dj;
+        }
+        if pairs.is_empty() { break; } // only gaps, it would be found again and again
+        for (a, b) in pairs { used[a][b] = true; }
+        xb.reverse();
+        yb.reverse();
+        res.push(LocalAlign { score, x: i..ie, y: j..je, align: Align(xb, yb) });
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cooptimal::count_optimal;
+    use crate::dna::{Dna, DnaBlock, DnaMetricSpace as Dms, DnaSimilarity};
+    use crate::io::read_test_insts_all;
+    use crate::math::{Align, Gapped, MetricSpace, cout_align, dist_1, dist_dp_full, dist_local, rm_gaps, sol_1};
+    use crate::rng::Rng;
+    use super::{k_best, k_best_local, suboptimal_alignments};
+
+    fn seq(s: &str) -> Vec<Dna> {
+        s.chars().map(|c| c.to_string().parse().expect("invalid sequence!")).collect()
+    }
+
+    /// The costs of all the alignments of x and y, sorted
+    fn all_costs<M: MetricSpace<Item = Dna>>(ms: &M, x: &[Dna], y: &[Dna]) -> Vec<M::Cost> {
+        fn rec<M: MetricSpace<Item = Dna>>(ms: &M, x: &[Dna], y: &[Dna], c: M::Cost, out: &mut Vec<M::Cost>) {
+            if x.is_empty() && y.is_empty() { out.push(c); }
+            if !x.is_empty() && !y.is_empty() { rec(ms, &x[1..], &y[1..], c + ms.sub(x[0], y[0]), out); }
+            if !y.is_empty() { rec(ms, x, &y[1..], c + ms.ins(), out); }
+            if !x.is_empty() { rec(ms, &x[1..], y, c + ms.del(), out); }
+        }
+        let mut out = vec![];
+        rec(ms, x, y, M::ZEROCOST, &mut out);
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn against_brute_force(){
+        let mut rng = Rng::new(34);
+        let letters = [Dna::A, Dna::C, Dna::G, Dna::T];
+        for _ in 0..30 {
+            let n = 3 + rng.below(3) as usize;
+            let mut random = |n| (0..n).map(|_| letters[rng.below(4) as usize]).collect::<Vec<_>>();
+            let (x, y) = (random(n), random(4));
+            let costs = all_costs(&Dms, &x, &y);
+            let best = k_best(&Dms, &x, &y, usize::MAX);
+            assert_eq!(best.len(), costs.len(), "not every alignment was enumerated");
+            assert_eq!(best.iter().map(|(c, _)| *c).collect::<Vec<_>>(), costs);
+            for (k, (c, al)) in best.iter().enumerate() {
+                assert_eq!(cout_align(&Dms, &al.0, &al.1), *c);
+                assert_eq!(rm_gaps::<Dms>(al.0.clone()), x, "some letters 🍪 got eaten");
+                assert_eq!(rm_gaps::<Dms>(al.1.clone()), y, "some letters 🍪 got eaten");
+                assert!(best[..k].iter().all(|(_, b)| b != al), "alignment enumerated twice");
+            }
+
+            // same thing with a similarity, the best scores come first
+            let sim = DnaSimilarity::default();
+            let mut scores = all_costs(&sim, &x, &y);
+            scores.reverse();
+            let best = k_best(&sim, &x, &y, 50);
+            assert_eq!(best.iter().map(|(c, _)| *c).collect::<Vec<_>>(), scores[..50]);
+        }
+    }
+
+    #[test]
+    fn instances(){
+        let testcases = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 100)
+            .map(|(_, b)| b);
+        for DnaBlock(l, r) in testcases {
+            let t = dist_dp_full(&Dms, &l, &r);
+            let best = suboptimal_alignments(&Dms, &l, &r, &t).take(20).collect::<Vec<_>>();
+            assert_eq!(best.len(), 20);
+            assert_eq!(best[0], (dist_1(&Dms, &l, &r), sol_1(&Dms, &l, &r)));
+            let optimal = best.iter().filter(|(c, _)| *c == best[0].0).count() as u128;
+            assert_eq!(optimal, count_optimal(&Dms, &l, &r, &t).min(20));
+            for w in best.windows(2) {
+                assert!(w[0].0 <= w[1].0, "the alignments are not sorted");
+            }
+            for (c, al) in best.iter() {
+                assert_eq!(cout_align(&Dms, &al.0, &al.1), *c);
+            }
+        }
+    }
+
+    #[test]
+    fn waterman_eggert(){
+        let sim = DnaSimilarity::default();
+        let (x, y) = (seq("ACGTGTTTTTTACGTG"), seq("GGACGTGGG"));
+        let res = k_best_local(&sim, &x, &y, 5);
+        assert_eq!(res[0].score, 5);
+        assert_eq!((res[0].x.clone(), res[0].y.clone()), (0..5, 2..7));
+        let acgtg = seq("ACGTG").into_iter().map(Gapped::Item).collect::<Vec<_>>();
+        assert_eq!(res[0].align, Align(acgtg.clone(), acgtg));
+        // the repeat is found as well, even though it aligns with the same part of y
+        assert_eq!(res[1].score, 5);
+        assert_eq!((res[1].x.clone(), res[1].y.clone()), (11..16, 2..7));
+        assert!(res.len() <= 5);
+
+        let testcases = read_test_insts_all()
+            .take_while(|&(size, _)| size <= 100)
+            .map(|(_, b)| b);
+        for DnaBlock(l, r) in testcases {
+            let res = k_best_local(&sim, &l, &r, 10);
+            assert_eq!(res[0].score, dist_local(&sim, &l, &r));
+            let mut pairs = vec![];
+            for (k, al) in res.iter().enumerate() {
+                assert!(al.score > 0);
+                assert!(k == 0 || al.score <= res[k-1].score, "the alignments are not sorted");
+                assert_eq!(cout_align(&sim, &al.align.0, &al.align.1), al.score);
+                assert_eq!(rm_gaps::<DnaSimilarity>(al.align.0.clone()), l[al.x.clone()].to_vec());
+                assert_eq!(rm_gaps::<DnaSimilarity>(al.align.1.clone()), r[al.y.clone()].to_vec());
+                let (mut i, mut j) = (al.x.start, al.y.start);
+                for (a, b) in al.align.0.iter().zip(&al.align.1) {
+                    if !a.is_gap() && !b.is_gap() {
+                        assert!(!pairs.contains(&(i, j)), "a pair is aligned twice");
+                        pairs.push((i, j));
+                    }
+                    i += !a.is_gap() as usize;
+                    j += !b.is_gap() as usize;
+                }
+            }
+        }
+    }
+}
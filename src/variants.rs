@@ -0,0 +1,270 @@
+//! Variant calling, the differences between a reference and a sample read from their alignment.
+//!
+//! The first row of the alignment is the reference and the second one the sample. Every run of consecutive
+//! columns which are not matches is one variant: a substitution of one letter (SNV) or of several (MNP),
+//! an insertion, a deletion or a mix of them (complex). The insertions and deletions are shifted as far left
+//! as they can go (left normalisation), and merged with the previous variant when they end up touching it,
+//! so that the same differences are always reported the same way whatever the alignment chose.
+//! The variants can then be written in the VCF format.
+
+use std::fmt::Display;
+use std::io::Write;
+
+use crate::dna::Dna;
+use crate::math::*;
+
+/// A difference between the reference and the sample: the letters reference[pos..pos + reference.len()]
+/// are replaced by `alternative` in the sample
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    pub pos: usize,
+    pub reference: Vec<Dna>,
+    pub alternative: Vec<Dna>,
+}
+
+/// The kinds of variants
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariantKind {
+    Snv,
+    Mnp,
+    Insertion,
+    Deletion,
+    Complex,
+}
+
+impl Display for VariantKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            VariantKind::Snv => "SNV",
+            VariantKind::Mnp => "MNP",
+            VariantKind::Insertion => "INS",
+            VariantKind::Deletion => "DEL",
+            VariantKind::Complex => "COMPLEX",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl Variant {
+    /// The position just after the replaced letters of the reference
+    pub fn end(&self) -> usize {
+        self.pos + self.reference.len()
+    }
+
+    pub fn kind(&self) -> VariantKind {
+        match (self.reference.len(), self.alternative.len()) {
+            (0, _) => VariantKind::Insertion,
+            (_, 0) => VariantKind::Deletion,
+            (1, 1) => VariantKind::Snv,
+            (r, a) if r == a => VariantKind::Mnp,
+            _ => VariantKind::Complex,
+        }
+    }
+
+    /// Shift an insertion or a deletion left as long as the sample stays the same, but not before `bound`
+    fn normalise(&mut self, reference: &[Dna], bound: usize) {
+        let allele = match self.kind() {
+            VariantKind::Insertion => &mut self.alternative,
+            VariantKind::Deletion => &mut self.reference,
+            _ => return,
+        };
+        while self.pos > bound && reference[self.pos - 1] == allele[allele.len() - 1] {
+            allele.rotate_right(1);
+            self.pos -= 1;
+        }
+    }
+}
+
+/// The variants of the sample aligned with the reference, from left to right.
+/// The alignment covers the part of the reference starting at `offset`, its positions are shifted by offset
+pub fn call_variants<M>(align: &Align<M>, offset: usize) -> Vec<Variant>
+where M: MetricSpace<Item = Dna>
+{
+    let reference = rm_gaps::<M>(align.0.clone());
+    let mut res: Vec<Variant> = vec![];
+    let mut current: Option<Variant> = None;
+    let mut pos = 0;
+    for (&a, &b) in align.0.iter().zip(&align.1) {
+        if let (Gapped::Item(r), Gapped::Item(s)) = (a, b) {
+            if r == s {
+                res.extend(current.take());
+                pos += 1;
+                continue;
+            }
+        }
+        let v = current.get_or_insert_with(|| Variant { pos, reference: vec![], alternative: vec![] });
+        if let Gapped::Item(r) = a {
+            v.reference.push(r);
+            pos += 1;
+        }
+        v.alternative.extend(b.item());
+    }
+    res.extend(current);
+
+    // normalise, merging with the previous variant when touching it
+    let mut merged: Vec<Variant> = vec![];
+    for mut v in res {
+        let bound = merged.last().map_or(0, |p| p.end());
+        v.normalise(&reference, bound);
+        match merged.last_mut() {
+            Some(p) if p.end() == v.pos => {
+                p.reference.extend(v.reference);
+                p.alternative.extend(v.alternative);
+            }
+            _ => merged.push(v),
+        }
+    }
+    for v in merged.iter_mut() {
+        v.pos += offset;
+    }
+    merged
+}
+
+/// The sample, the reference with the variants applied (they must be sorted and not overlap)
+pub fn apply_variants(reference: &[Dna], variants: &[Variant]) -> Vec<Dna> {
+    let mut res = vec![];
+    let mut pos = 0;
+    for v in variants {
+        assert!(v.pos >= pos, "the variants overlap!");
+        res.extend_from_slice(&reference[pos..v.pos]);
+        res.extend_from_slice(&v.alternative);
+        pos = v.end();
+    }
+    res.extend_from_slice(&reference[pos..]);
+    res
+}
+
+/// Write the variants in the VCF format (version 4.2), on the sequence named chrom whose letters are `reference`.
+/// Insertions and deletions get the letter before them (or after them at the start of the sequence)
+/// in both alleles, as VCF wants no empty allele. An insertion or deletion covering the whole reference
+/// has no such letter and can't be written, nothing is written then and an InvalidInput error is returned
+pub fn write_vcf<W: Write>(w: &mut W, chrom: &str, reference: &[Dna], variants: &[Variant]) -> std::io::Result<()> {
+    let word = |s: &[Dna]| s.iter().map(|d| d.to_string()).collect::<String>();
+    let lonely = |v: &&Variant| (v.reference.is_empty() || v.alternative.is_empty()) && v.pos == 0 && v.end() >= reference.len();
+    if let Some(v) = variants.iter().find(lonely) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+            format!("vcf error: the {} at {} has no reference letter around it", v.kind(), v.pos)));
+    }
+    writeln!(w, "##fileformat=VCFv4.2")?;
+    writeln!(w, "##source=RuNA")?;
+    writeln!(w, "##contig=<ID={},length={}>", chrom, reference.len())?;
+    writeln!(w, "##INFO=<ID=TYPE,Number=1,Type=String,Description=\"Kind of variant: SNV, MNP, INS, DEL or COMPLEX\">")?;
+    writeln!(w, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")?;
+    for v in variants {
+        let (mut pos, mut r, mut a) = (v.pos + 1, v.reference.clone(), v.alternative.clone());
+        if r.is_empty() || a.is_empty() {
+            if v.pos > 0 {
+                r.insert(0, reference[v.pos - 1]);
+                a.insert(0, reference[v.pos - 1]);
+                pos -= 1;
+            } else {
+                r.push(reference[v.end()]);
+                a.push(reference[v.end()]);
+            }
+        }
+        writeln!(w, "{}\t{}\t.\t{}\t{}\t.\tPASS\tTYPE={}", chrom, pos, word(&r), word(&a), v.kind())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dna::{Dna, DnaMetricSpace as Dms};
+    use crate::math::{Align, Gapped, rm_gaps, sol_2};
+    use crate::rng::Rng;
+    use super::{Variant, VariantKind, apply_variants, call_variants, write_vcf};
+
+    fn seq(s: &str) -> Vec<Dna> {
+        s.chars().map(|c| c.to_string().parse().expect("invalid sequence!")).collect()
+    }
+
+    fn row(s: &str) -> Vec<Gapped<Dna>> {
+        s.chars().map(|c| c.to_string().parse().expect("invalid row!")).collect()
+    }
+
+    fn var(pos: usize, r: &str, a: &str) -> Variant {
+        Variant { pos, reference: seq(r), alternative: seq(a) }
+    }
+
+    #[test]
+    fn calls(){
+        // a substitution, a deletion in TTT written on its last T, two neighbouring substitutions
+        let al = Align::<Dms>(row("ACGTTTGACCATG"), row("AGGTT-GAGGATG"));
+        let vs = call_variants(&al, 100);
+        assert_eq!(vs, vec![var(101, "C", "G"), var(103, "T", ""), var(108, "CC", "GG")]);
+        assert_eq!(vs.iter().map(|v| v.kind()).collect::<Vec<_>>(), vec![VariantKind::Snv, VariantKind::Deletion, VariantKind::Mnp]);
+
+        // an insertion in a repeat goes to its left end, and touches the substitution before it
+        let al = Align::<Dms>(row("ACAC--G"), row("AGACACG"));
+        assert_eq!(call_variants(&al, 0), vec![var(1, "C", "GAC")]);
+        let al = Align::<Dms>(row("-AAG"), row("AAAG"));
+        assert_eq!(call_variants(&al, 0), vec![var(0, "", "A")]);
+        let al = Align::<Dms>(row("AC-G"), row("A-TG"));
+        assert_eq!(call_variants(&al, 0), vec![var(1, "C", "T")]);
+    }
+
+    #[test]
+    fn vcf(){
+        let reference = seq("ACGTTTGACCATG");
+        let vs = vec![var(0, "", "T"), var(1, "C", "G"), var(3, "T", ""), var(8, "CC", "GG"), var(10, "A", "TT")];
+        let mut out = vec![];
+        write_vcf(&mut out, "chr1", &reference, &vs).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines[2], "##contig=<ID=chr1,length=13>");
+        assert_eq!(lines[4], "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO");
+        assert_eq!(lines[5..], [
+            "chr1\t1\t.\tA\tTA\t.\tPASS\tTYPE=INS",
+            "chr1\t2\t.\tC\tG\t.\tPASS\tTYPE=SNV",
+            "chr1\t3\t.\tGT\tG\t.\tPASS\tTYPE=DEL",
+            "chr1\t9\t.\tCC\tGG\t.\tPASS\tTYPE=MNP",
+            "chr1\t11\t.\tA\tTT\t.\tPASS\tTYPE=COMPLEX",
+        ]);
+
+        // a deletion of the whole reference and an insertion into an empty one have no letter to lean on
+        let mut out = vec![];
+        let err = write_vcf(&mut out, "chr1", &reference, &[var(0, "ACGTTTGACCATG", "")]).unwrap_err();
+        assert_eq!(err.to_string(), "vcf error: the DEL at 0 has no reference letter around it");
+        assert!(write_vcf(&mut out, "chr1", &[], &[var(0, "", "ACG")]).is_err());
+        assert!(out.is_empty());
+        write_vcf(&mut out, "chr1", &[], &[]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 5);
+    }
+
+    #[test]
+    fn round_trip(){
+        let mut rng = Rng::new(40);
+        let letters = [Dna::A, Dna::C, Dna::G, Dna::T];
+        for _ in 0..50 {
+            // a small alphabet makes repeats, and thus normalisations, likely
+            let reference = (0..200).map(|_| letters[rng.below(2) as usize]).collect::<Vec<_>>();
+            let mut sample = reference.clone();
+            for _ in 0..10 {
+                let p = rng.below(sample.len() as u64) as usize;
+                match rng.below(3) {
+                    0 => sample[p] = letters[rng.below(4) as usize],
+                    1 => { sample.remove(p); },
+                    _ => sample.insert(p, letters[rng.below(4) as usize]),
+                }
+            }
+            let al = sol_2(&Dms, &reference, &sample);
+            let vs = call_variants(&al, 0);
+            assert_eq!(apply_variants(&reference, &vs), sample);
+            for (k, v) in vs.iter().enumerate() {
+                assert!(k == 0 || vs[k - 1].end() < v.pos, "the variants were not merged");
+                assert_eq!(&reference[v.pos..v.end()], &v.reference[..]);
+                let allele = match v.kind() {
+                    VariantKind::Insertion => &v.alternative,
+                    VariantKind::Deletion => &v.reference,
+                    _ => continue,
+                };
+                let bound = if k == 0 { 0 } else { vs[k - 1].end() };
+                assert!(v.pos == bound || reference[v.pos - 1] != allele[allele.len() - 1], "{:?} is not left normalised", v);
+            }
+            // calling the variants on a part of the reference shifts them
+            let shifted = call_variants(&Align::<Dms>(al.0.clone(), al.1.clone()), 1000);
+            assert!(shifted.iter().zip(&vs).all(|(a, b)| a.pos == b.pos + 1000));
+            assert_eq!(rm_gaps::<Dms>(al.1), sample);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+// The C API from C, compiled and run by tests/c_api.rs
+#include <stdio.h>
+#include <string.h>
+
+#include "runa.h"
+
+static int failures = 0;
+
+#define CHECK(cond) do { \
+    if (!(cond)) { \
+        fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+        failures++; \
+    } \
+} while (0)
+
+static void global(void) {
+    const char *x = "ATTGTA", *y = "attcta";
+    RunaScheme *s = runa_scheme_new("course");
+    CHECK(s != NULL);
+
+    int64_t full = -1, linear = -1;
+    CHECK(runa_dist(s, (const uint8_t *)x, strlen(x), (const uint8_t *)y, strlen(y), RUNA_FULL, &full) == 0);
+    CHECK(runa_dist(s, (const uint8_t *)x, strlen(x), (const uint8_t *)y, strlen(y), RUNA_LINEAR, &linear) == 0);
+    CHECK(full == linear);
+
+    RunaAlignment *al = runa_align(s, (const uint8_t *)x, strlen(x), (const uint8_t *)y, strlen(y), RUNA_LINEAR);
+    CHECK(al != NULL);
+    CHECK(runa_alignment_cost(al) == linear);
+    CHECK(strlen(runa_alignment_row_x(al)) == strlen(runa_alignment_row_y(al)));
+    CHECK(strlen(runa_alignment_cigar(al)) > 0);
+    size_t xs = 9, xe = 9, ye = 9;
+    runa_alignment_range(al, &xs, &xe, NULL, &ye);
+    CHECK(xs == 0 && xe == 6 && ye == 6);
+    runa_alignment_free(al);
+
+    // the sequences need no terminating zero, and may be empty
+    int64_t d = -1;
+    CHECK(runa_dist(s, (const uint8_t *)"ACGTXXXX", 4, NULL, 0, RUNA_LINEAR, &d) == 0);
+    CHECK(d == 8);
+    runa_scheme_free(s);
+}
+
+static void local(void) {
+    const char *x = "TTTTACGTACGTTTTT", *y = "GGGACGTACGGG";
+    RunaScheme *s = runa_scheme_similarity(2, -1, -2);
+    RunaAlignment *al = runa_align(s, (const uint8_t *)x, strlen(x), (const uint8_t *)y, strlen(y), RUNA_LOCAL);
+    CHECK(al != NULL);
+    CHECK(runa_alignment_cost(al) == 14);
+    CHECK(strcmp(runa_alignment_cigar(al), "7=") == 0);
+    CHECK(strcmp(runa_alignment_row_x(al), "ACGTACG") == 0);
+    size_t r[4];
+    runa_alignment_range(al, &r[0], &r[1], &r[2], &r[3]);
+    CHECK(r[0] == 4 && r[1] == 11 && r[2] == 3 && r[3] == 10);
+    runa_alignment_free(al);
+    runa_scheme_free(s);
+}
+
+static void errors(void) {
+    RunaScheme *s = runa_scheme_new("course");
+    int64_t d;
+    CHECK(runa_dist(s, (const uint8_t *)"ANA", 3, (const uint8_t *)"A", 1, RUNA_LINEAR, &d) == -1);
+    CHECK(strcmp(runa_last_error(), "invalid letter 'N' at 1 of x") == 0);
+    CHECK(runa_align(s, (const uint8_t *)"A", 1, (const uint8_t *)"A", 1, RUNA_OVERLAP) == NULL);
+    CHECK(strstr(runa_last_error(), "similarity") != NULL);
+    CHECK(runa_scheme_new("blosum62") == NULL);
+    CHECK(strcmp(runa_last_error(), "unknown scheme blosum62") == 0);
+    runa_scheme_free(s);
+    runa_scheme_free(NULL);
+    runa_alignment_free(NULL);
+}
+
+int main(void) {
+    printf("runa %s\n", runa_version());
+    global();
+    local();
+    errors();
+    if (failures > 0) {
+        fprintf(stderr, "%d checks failed\n", failures);
+        return 1;
+    }
+    printf("all checks passed\n");
+    return 0;
+}
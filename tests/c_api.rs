@@ -0,0 +1,44 @@
+//! Compile tests/c/test_runa.c against include/runa.h and the shared library, and run it
+
+#[cfg(unix)]
+#[test]
+fn c_program(){
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // the test runs from target/<profile>/deps, the shared library is in target/<profile>
+    let lib_dir = std::env::current_exe().unwrap().parent().unwrap().parent().unwrap().to_path_buf();
+    let exe = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("test_runa");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+
+    // cargo test builds the library as an rlib only
+    let mut build = Command::new(env!("CARGO"));
+    build.args(["build", "--lib", "--manifest-path"]).arg(root.join("Cargo.toml"));
+    if !cfg!(debug_assertions) { build.arg("--release"); }
+    assert!(build.status().unwrap().success(), "the shared library does not build");
+
+    let status = match Command::new(&cc)
+        .arg(root.join("tests/c/test_runa.c"))
+        .arg("-I").arg(root.join("include"))
+        .arg("-L").arg(&lib_dir)
+        .args(["-lruna", "-Wall", "-Werror", "-o"]).arg(&exe)
+        .status()
+    {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("skipped, no C compiler ({}): {}", cc, e);
+            return;
+        }
+    };
+    assert!(status.success(), "the C test program does not compile");
+
+    let out = Command::new(&exe)
+        .env("LD_LIBRARY_PATH", &lib_dir)
+        .env("DYLD_LIBRARY_PATH", &lib_dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}{}", stdout, String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.contains("all checks passed"), "{}", stdout);
+}
@@ -9,18 +9,21 @@ use std::{iter, error::Error};
 fn run() -> Result<(), Box<dyn Error>>
 {
     let dists = [
-        ("dist_1", dist_1::<Dms> as fn(_, _) -> _),
+        ("dist_1", dist_1::<Dms> as fn(_, _, _) -> _),
         ("dist_2", dist_2::<Dms>),
         ("dist_naif", dist_naif::<Dms>),
     ];
     let sols = [
-        ("sol_1", sol_1::<Dms> as fn(_, _) -> _),
+        ("sol_1", sol_1::<Dms> as fn(_, _, _) -> _),
         ("sol_2", sol_2::<Dms>),
     ];
 
-    let block = {
-        let arg = env::args().nth(1).expect("instance location is required!");
-        read_to_string(arg)?.parse::<DnaBlock>()?
+    let block = match env::args().nth(1) {
+        None => {
+            eprintln!("instance location is required!");
+            return Ok(());
+        },
+        Some(arg) => read_to_string(arg)?.parse::<DnaBlock>()?,
     };
 
     let block = &block;
@@ -29,23 +32,23 @@ fn run() -> Result<(), Box<dyn Error>>
         dists
         .into_iter()
         .map(|(n, f)| (n, Box::new(move || {
-            let res = f(block.0.as_slice(), block.1.as_slice());
+            let res = f(&Dms, block.0.as_slice(), block.1.as_slice());
             println!("distance: {}", res);
         }) as Box<dyn Fn()>))
         .chain(
             sols
             .iter()
             .map(|&(n, f)| (n, Box::new(move || {
-                let res = f(block.0.as_slice(), block.1.as_slice());
-                println!("alignement: \n{}", res);
+                let res = f(&Dms, block.0.as_slice(), block.1.as_slice());
+                println!("alignment: \n{}", res);
             }) as Box<dyn Fn()>))
         )
         .chain(
             iter::once(("prog_dyn", prog_dyn::<Dms>))
             .map(|(n, f)| (n, Box::new(move || {
-                let res = f(block.0.as_slice(), block.1.as_slice());
-                println!("alignement: \n{}", res.1);
-                println!("coût: {}", res.0);
+                let res = f(&Dms, block.0.as_slice(), block.1.as_slice());
+                println!("alignment: \n{}", res.1);
+                println!("cost: {}", res.0);
             }) as Box<dyn Fn()>))
         );
     
@@ -6,25 +6,25 @@ use runa::{dna::{DnaMetricSpace as Dms}, math::*, io::read_test_inst};
 
 fn main_dist_naif(){
     let block = read_test_inst("Inst_0000014_83.adn").expect("cannot read block!");
-    let res = dist_naif::<Dms>(&block.0, &block.1);
+    let res = dist_naif(&Dms, &block.0, &block.1);
     println!("{}", res);
 }
 
 fn main_dist_1(){
     let block = read_test_inst("Inst_0010000_7.adn").expect("cannot read block!");
-    let res = dist_1::<Dms>(&block.0, &block.1);
+    let res = dist_1(&Dms, &block.0, &block.1);
     println!("{}", res);
 }
 
 fn main_sol_1(){
     let block = read_test_inst("Inst_0010000_7.adn").expect("cannot read block!");
-    let res = sol_1::<Dms>(&block.0, &block.1);
+    let res = sol_1(&Dms, &block.0, &block.1);
     println!("{}", res);
 }
 
 fn main_prog_dyn(){
     let block = read_test_inst("Inst_0010000_7.adn").expect("cannot read block!");
-    let res = prog_dyn::<Dms>(&block.0, &block.1);
+    let res = prog_dyn(&Dms, &block.0, &block.1);
     println!("{}", res.0);
 }
 
@@ -33,7 +33,7 @@ fn main_dist_2(){
     // let fnm = "Inst_0000100_3.adn";
     let block = read_test_inst(fnm).expect("cannot read block!");
     println!("running with {}", fnm);
-    let res = dist_2::<Dms>(&block.0, &block.1);
+    let res = dist_2(&Dms, &block.0, &block.1);
     println!("{}", res);
 }
 
@@ -41,7 +41,7 @@ fn main_sol_2(){
     let fnm = "Inst_0020000_64.adn";
     let block = read_test_inst(fnm).expect("cannot read block!");
     println!("running with {}", fnm);
-    let res = sol_2::<Dms>(&block.0, &block.1);
+    let res = sol_2(&Dms, &block.0, &block.1);
     println!("{}", res);
 }
 
@@ -57,7 +57,7 @@ fn main(){
     ];
     let arg = env::args().nth(1);
     
-    if arg == None {
+    if arg.is_none() {
         println!("an argument is required!");
         return;
     }
@@ -0,0 +1,101 @@
+"""Tests of the Python bindings, run by tests/python_api.rs with the python feature.
+
+They are pytest tests, and run as a plain script too when pytest is not installed:
+    python tests/python/test_runa.py
+"""
+
+import os
+import tempfile
+import threading
+
+import runa
+
+X, Y = "ATTGTA", "ATCTTA"
+
+
+def test_distances():
+    d = runa.dist_2(X, Y)
+    assert d == runa.dist_1(X, Y) == runa.dist_naif(X, Y)
+    assert runa.dist_2(X.encode(), Y.lower().encode()) == d
+    assert runa.dist_2("", "") == 0
+    assert runa.dist_2("ACGT", "") == 8
+    unit = runa.Scheme("unit")
+    assert not unit.is_similarity
+    assert runa.dist_2("ACGT", "AGGT", unit) == runa.dist_2("ACGT", "AGGT", scheme=unit)
+
+
+def test_alignments():
+    al = runa.sol_2(X, Y)
+    assert isinstance(al, runa.Align)
+    assert al.cost == runa.dist_2(X, Y)
+    assert al.x.replace("-", "") == X and al.y.replace("-", "") == Y
+    assert len(al) == len(al.x) == len(al.y)
+    assert (al.x_range, al.y_range) == ((0, 6), (0, 6))
+    assert runa.sol_1(X, Y).cost == al.cost
+    assert al == runa.Align(al.x, al.y)
+    assert repr(al).startswith("Align(")
+    assert str(al).startswith("| ")
+    assert runa.Align("AC-T", "A-GT").cigar() == "1=1D1I1="
+    assert runa.Align("ACGT", "ACGT").identity() == 1.0
+
+
+def test_local_and_overlap():
+    s = runa.Scheme.similarity(matched=2, mismatch=-1, gap=-2)
+    assert s.is_similarity
+    x, y = "TTTTACGTACGTTTTT", "GGGACGTACGGG"
+    al = runa.sol_local(x, y, s)
+    assert (al.cost, al.x_range, al.y_range, al.cigar()) == (14, (4, 11), (3, 10), "7=")
+    assert runa.dist_local(x, y, s) == 14
+    ov = runa.sol_overlap("GGGACGT", "ACGTCCC", s)
+    assert ov.cost == runa.dist_overlap("GGGACGT", "ACGTCCC", s) == 8
+    assert ov.x_range == (3, 7) and ov.y_range == (0, 4)
+
+
+def test_errors():
+    def raises(kind, f, *args):
+        try:
+            f(*args)
+        except kind as e:
+            return str(e)
+        raise AssertionError("no {} raised".format(kind.__name__))
+
+    assert raises(ValueError, runa.dist_2, "ANA", "A") == "invalid letter 'N' at 1 of x"
+    assert raises(TypeError, runa.dist_2, 12, "A") == "x must be str or bytes"
+    assert "similarity" in raises(ValueError, runa.dist_local, "A", "A")
+    assert raises(ValueError, runa.Scheme, "blosum") == "unknown scheme blosum"
+    raises(ValueError, runa.Align, "AC", "A")
+    raises(ValueError, runa.parse_adn, "2\n1\nA\n")
+    raises(OSError, runa.read_sequences, "/nonexistent/runa.fasta")
+
+
+def test_parsers():
+    assert runa.parse_adn("3\n2\nA C G \nT T \n") == ("ACG", "TT")
+    assert runa.parse_fasta(b">a first\nACGT\nac\n>b\nT\n") == [("a first", "ACGTAC"), ("b", "T")]
+    with tempfile.TemporaryDirectory() as d:
+        path = os.path.join(d, "pair.fasta")
+        with open(path, "w") as f:
+            f.write(">x\n{}\n>y\n{}\n".format(X, Y))
+        assert runa.read_sequences(path) == [("x", X), ("y", Y)]
+    m = runa.Scheme.matrix(" A C G T\nA 0 1 1 1\nC 1 0 1 1\nG 1 1 0 1\nT 1 1 1 0\ndel 1\nins 1\n")
+    assert runa.dist_2("ACGT", "AGGT", m) == runa.dist_2("ACGT", "AGGT", runa.Scheme("unit"))
+
+
+def test_threads():
+    # the alignments run without the GIL, the results must not mix
+    x, y = "ACGTTGCA" * 100, "ACGTGCAT" * 100
+    expected = runa.dist_2(x, y)
+    results = []
+    threads = [threading.Thread(target=lambda: results.append(runa.dist_2(x, y))) for _ in range(4)]
+    for t in threads:
+        t.start()
+    for t in threads:
+        t.join()
+    assert results == [expected] * 4
+
+
+if __name__ == "__main__":
+    for name, test in sorted(globals().items()):
+        if name.startswith("test_"):
+            test()
+            print(name, "ok")
+    print("all tests passed")
@@ -0,0 +1,34 @@
+//! Build the Python extension module and run tests/python/test_runa.py with it, with pytest if it is installed
+
+#![cfg(all(unix, feature = "python"))]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn python_module(){
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // the test runs from target/<profile>/deps, the shared library is in target/<profile>
+    let lib_dir = std::env::current_exe().unwrap().parent().unwrap().parent().unwrap().to_path_buf();
+    let python = std::env::var("PYO3_PYTHON").unwrap_or_else(|_| "python3".to_string());
+
+    // cargo test builds the library as an rlib only
+    let mut build = Command::new(env!("CARGO"));
+    build.args(["build", "--lib", "--features", "python", "--manifest-path"]).arg(root.join("Cargo.toml"));
+    if !cfg!(debug_assertions) { build.arg("--release"); }
+    assert!(build.status().unwrap().success(), "the extension module does not build");
+
+    // Python imports the module runa from runa.so
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("python");
+    std::fs::create_dir_all(&dir).unwrap();
+    let lib = ["libruna.so", "libruna.dylib"].iter().map(|l| lib_dir.join(l)).find(|l| l.exists()).expect("no shared library!");
+    std::fs::copy(lib, dir.join("runa.so")).unwrap();
+
+    let tests = root.join("tests/python/test_runa.py");
+    let has_pytest = Command::new(&python).args(["-c", "import pytest"]).output().is_ok_and(|o| o.status.success());
+    let mut run = Command::new(&python);
+    if has_pytest { run.args(["-m", "pytest", "-q", "-p", "no:cacheprovider"]); }
+    let out = run.arg(&tests).env("PYTHONPATH", &dir).output().unwrap();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}{}", stdout, String::from_utf8_lossy(&out.stderr));
+}